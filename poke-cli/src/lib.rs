@@ -1,5 +1,9 @@
+use std::path::PathBuf;
+
 use structopt::StructOpt;
 
+use poke_memory::warmup::Selection;
+
 #[derive(Debug, StructOpt)]
 #[structopt(name = "poke")]
 pub struct App {
@@ -7,17 +11,404 @@ pub struct App {
     pub subcommand: Subcommand,
 }
 
+// Parsed once at startup, boxing the flags would buy nothing.
+#[allow(clippy::large_enum_variant)]
 #[derive(Debug, StructOpt)]
 pub enum Subcommand {
     #[structopt(name = "web")]
-    Web {
-        #[structopt(
-            long = "port",
-            short = "p",
-            required = true,
-            default_value = "3030",
-            help = "http port to use for accepting connections"
-        )]
-        port: u16,
-    },
+    Web(Web),
+
+    #[structopt(name = "mirror")]
+    Mirror(Mirror),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Web {
+    #[structopt(
+        long = "port",
+        short = "p",
+        required = true,
+        default_value = "3030",
+        help = "http port to use for accepting connections"
+    )]
+    pub port: u16,
+
+    #[structopt(
+        long = "pokeapi-dump",
+        parse(from_os_str),
+        help = "path to a local pokeapi data dump to use instead of pokeapi.co"
+    )]
+    pub pokeapi_dump: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    pub upstream: Upstream,
+
+    #[structopt(flatten)]
+    pub breaker: Breaker,
+
+    #[structopt(flatten)]
+    pub faults: Faults,
+
+    #[structopt(flatten)]
+    pub fallback: Fallback,
+
+    #[structopt(
+        long = "patches",
+        parse(from_os_str),
+        help = "toml or json file of balance patches to apply over upstream pokemons, reloaded on SIGHUP"
+    )]
+    pub patches: Option<PathBuf>,
+
+    #[structopt(
+        long = "names-refresh",
+        default_value = "3600",
+        help = "seconds between refreshes of the pokemon names index, used for lookups by name"
+    )]
+    pub names_refresh: u64,
+
+    #[structopt(flatten)]
+    pub cache: Cache,
+
+    #[structopt(flatten)]
+    pub redis: Redis,
+
+    #[structopt(flatten)]
+    pub admin: Admin,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Upstream {
+    #[structopt(
+        name = "upstream-timeout",
+        long = "upstream-timeout",
+        default_value = "10000",
+        help = "milliseconds after which a pokeapi.co request fails"
+    )]
+    pub timeout: u64,
+
+    #[structopt(
+        long = "upstream-concurrency",
+        default_value = "32",
+        help = "maximum number of concurrent requests to pokeapi.co"
+    )]
+    pub concurrency: usize,
+
+    #[structopt(
+        long = "upstream-retries",
+        default_value = "0",
+        help = "number of times a failed pokeapi.co request is retried"
+    )]
+    pub retries: u32,
+
+    #[structopt(
+        long = "upstream-retry-backoff",
+        default_value = "100",
+        help = "milliseconds before retrying a failed pokeapi.co request, doubled at every retry"
+    )]
+    pub retry_backoff: u64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Breaker {
+    #[structopt(
+        long = "breaker-threshold",
+        default_value = "5",
+        help = "consecutive pokeapi.co failures after which requests fail fast"
+    )]
+    pub threshold: u32,
+
+    #[structopt(
+        long = "breaker-cooldown",
+        default_value = "30",
+        help = "seconds to fail fast for before probing pokeapi.co again"
+    )]
+    pub cooldown: u64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Faults {
+    #[structopt(
+        long = "fault-injection",
+        help = "inject faults into pokeapi.co requests, for resilience testing only"
+    )]
+    pub enabled: bool,
+
+    #[structopt(
+        long = "fault-seed",
+        default_value = "0",
+        help = "seed of the random faults, to reproduce a run"
+    )]
+    pub seed: u64,
+
+    #[structopt(
+        long = "fault-latency",
+        default_value = "0",
+        help = "milliseconds of latency added to every pokeapi.co request"
+    )]
+    pub latency: u64,
+
+    #[structopt(
+        long = "fault-jitter",
+        default_value = "0",
+        help = "maximum milliseconds of random latency added on top of --fault-latency"
+    )]
+    pub jitter: u64,
+
+    #[structopt(
+        long = "fault-error-rate",
+        default_value = "0",
+        parse(try_from_str = parse_rate),
+        help = "rate of pokeapi.co requests failing, between 0 and 1"
+    )]
+    pub error_rate: f64,
+
+    #[structopt(
+        long = "fault-not-found-rate",
+        default_value = "0",
+        parse(try_from_str = parse_rate),
+        help = "rate of pokeapi.co requests returning no pokemon, between 0 and 1"
+    )]
+    pub not_found_rate: f64,
+
+    #[structopt(
+        long = "fault-hang-rate",
+        default_value = "0",
+        parse(try_from_str = parse_rate),
+        help = "rate of pokeapi.co requests never completing, between 0 and 1"
+    )]
+    pub hang_rate: f64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Fallback {
+    #[structopt(
+        long = "fallback-dump",
+        parse(from_os_str),
+        help = "path to a local pokeapi data dump to fall back to when pokeapi.co is unavailable"
+    )]
+    pub dump: Option<PathBuf>,
+
+    #[structopt(
+        name = "fallback-timeout",
+        long = "fallback-timeout",
+        help = "milliseconds after which a pokeapi.co request falls back to the dump (never if not set)"
+    )]
+    pub timeout: Option<u64>,
+
+    #[structopt(
+        long = "fallback-on-not-found",
+        help = "fall back to the dump also for pokemons not found on pokeapi.co"
+    )]
+    pub on_not_found: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Cache {
+    #[structopt(
+        long = "cache-capacity",
+        help = "maximum number of pokemons kept in cache (unbounded if not set)"
+    )]
+    pub capacity: Option<usize>,
+
+    #[structopt(
+        long = "cache-ttl",
+        help = "seconds after which a cached pokemon is fetched again (never if not set)"
+    )]
+    pub ttl: Option<u64>,
+
+    #[structopt(
+        long = "cache-stale-while-revalidate",
+        help = "serve expired pokemons from cache while refreshing them in background"
+    )]
+    pub stale_while_revalidate: bool,
+
+    #[structopt(
+        long = "cache-negative-ttl",
+        help = "seconds for which a pokemon not found upstream is cached as such (never if not set)"
+    )]
+    pub negative_ttl: Option<u64>,
+
+    #[structopt(
+        long = "cache-write-buffer",
+        help = "maximum number of pending cache writes before dropping new ones (the cache default if not set)"
+    )]
+    pub write_buffer: Option<usize>,
+
+    #[structopt(
+        long = "cache-snapshot",
+        parse(from_os_str),
+        help = "file to persist the cache into, loaded at startup and saved periodically"
+    )]
+    pub snapshot: Option<PathBuf>,
+
+    #[structopt(
+        long = "cache-snapshot-interval",
+        default_value = "300",
+        help = "seconds between two cache snapshots"
+    )]
+    pub snapshot_interval: u64,
+
+    #[structopt(
+        long = "cache-warmup",
+        parse(try_from_str),
+        help = "pokemons to preload in cache at startup: \"all\", an id range (e.g. 1..=151) or a file of names (e.g. file:names.txt)"
+    )]
+    pub warmup: Option<Selection>,
+
+    #[structopt(
+        long = "cache-warmup-concurrency",
+        default_value = "8",
+        help = "maximum number of concurrent lookups while warming up the cache"
+    )]
+    pub warmup_concurrency: usize,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Redis {
+    #[structopt(
+        long = "redis-url",
+        help = "url of a redis instance to use as shared cache, e.g. redis://127.0.0.1:6379"
+    )]
+    pub url: Option<String>,
+
+    #[structopt(
+        name = "redis-ttl",
+        long = "redis-ttl",
+        help = "seconds after which a pokemon stored in redis expires (never if not set)"
+    )]
+    pub ttl: Option<u64>,
+
+    #[structopt(
+        long = "redis-key-prefix",
+        default_value = "poke:pokemon:",
+        help = "prefix of the redis keys the pokemons are stored under"
+    )]
+    pub key_prefix: String,
+
+    #[structopt(
+        name = "redis-timeout",
+        long = "redis-timeout",
+        default_value = "200",
+        help = "milliseconds after which a redis command is given up, and the upstream used instead"
+    )]
+    pub timeout: u64,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Admin {
+    #[structopt(
+        long = "admin-token",
+        help = "bearer token enabling the /admin endpoints to manage custom pokemons (disabled if not set)"
+    )]
+    pub token: Option<String>,
+
+    #[structopt(
+        long = "custom-pokemons",
+        parse(from_os_str),
+        help = "file to persist custom pokemons into, loaded at startup and saved on every change"
+    )]
+    pub custom_pokemons: Option<PathBuf>,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct Mirror {
+    #[structopt(
+        long = "output",
+        short = "o",
+        parse(from_os_str),
+        help = "directory to write the mirrored pokeapi dataset into"
+    )]
+    pub output: PathBuf,
+
+    #[structopt(
+        long = "concurrency",
+        short = "c",
+        default_value = "8",
+        help = "maximum number of concurrent requests to pokeapi.co"
+    )]
+    pub concurrency: usize,
+}
+
+/// Parses a rate between 0 and 1, both included.
+fn parse_rate(s: &str) -> Result<f64, String> {
+    let rate = s.parse::<f64>().map_err(|err| err.to_string())?;
+
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(format!("{} is not between 0 and 1", s));
+    }
+
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn web(args: &[&str]) -> Web {
+        let args = ["poke", "web"].iter().chain(args);
+
+        match App::from_iter_safe(args).unwrap().subcommand {
+            Subcommand::Web(web) => web,
+            Subcommand::Mirror(_) => panic!("expected the web subcommand"),
+        }
+    }
+
+    #[test]
+    fn parses_defaults() {
+        let web = web(&[]);
+
+        assert_eq!(web.upstream.timeout, 10000);
+        assert_eq!(web.upstream.retries, 0);
+        assert_eq!(web.fallback.timeout, None);
+        assert_eq!(web.cache.ttl, None);
+    }
+
+    // Flags sharing a field name in different groups must not clash.
+    #[test]
+    fn parses_flags_of_every_group() {
+        let web = web(&[
+            "--upstream-timeout",
+            "500",
+            "--fallback-timeout",
+            "200",
+            "--cache-ttl",
+            "60",
+            "--redis-ttl",
+            "3600",
+        ]);
+
+        assert_eq!(web.upstream.timeout, 500);
+        assert_eq!(web.fallback.timeout, Some(200));
+        assert_eq!(web.cache.ttl, Some(60));
+        assert_eq!(web.redis.ttl, Some(3600));
+    }
+
+    #[test]
+    fn parses_rates() {
+        assert_eq!(parse_rate("0"), Ok(0.0));
+        assert_eq!(parse_rate("0.25"), Ok(0.25));
+        assert_eq!(parse_rate("1"), Ok(1.0));
+    }
+
+    #[test]
+    fn refuses_rates_out_of_range() {
+        for rate in &["-0.1", "1.5", "NaN", "inf", "half"] {
+            assert!(parse_rate(rate).is_err(), "{} accepted", rate);
+        }
+    }
+
+    #[test]
+    fn refuses_fault_rates_out_of_range() {
+        let args = [
+            "poke",
+            "web",
+            "--fault-injection",
+            "--fault-error-rate",
+            "2",
+        ];
+        assert!(App::from_iter_safe(&args).is_err());
+
+        let web = web(&["--fault-injection", "--fault-hang-rate", "0.5"]);
+        assert_eq!(web.faults.hang_rate, 0.5);
+    }
 }
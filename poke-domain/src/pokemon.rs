@@ -1,10 +1,11 @@
-// Gotta use a Box<Pin<Future<Result>>> for returning an async result
-// from a trait for now, until we have Higher-kinded Types in stable...
-use futures::future::BoxFuture;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::future::Future;
 
-use serde::Serialize;
+use futures::Stream;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Element {
     Normal,
@@ -27,14 +28,14 @@ pub enum Element {
     Fairy,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum Type {
     Single(Element),
     Double(Element, Element),
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Stats {
     pub speed: u16,
     pub special_defense: u16,
@@ -44,7 +45,7 @@ pub struct Stats {
     pub hit_points: u16,
 }
 
-#[derive(Clone, Debug, PartialEq, Serialize)]
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Pokemon {
     pub dex_id: u32,
     pub name: String,
@@ -57,10 +58,140 @@ pub struct Pokemon {
     pub stats: Stats,
 }
 
+/// Name of the repository that served a lookup, e.g. `cache` or `pokeapi`.
+pub type Source = &'static str;
+
+// The returned futures are unboxed, but must be `Send` so that they can be
+// awaited in multi-threaded runtimes: implementations can simply use `async fn`.
 pub trait Repository {
     type Error: std::error::Error;
 
-    fn get<'a>(&'a self, num: u32) -> BoxFuture<'a, Result<Option<Pokemon>, Self::Error>>
+    fn get(&self, num: u32) -> impl Future<Output = Result<Option<Pokemon>, Self::Error>> + Send;
+
+    /// Looks up a Pokémon, together with the [`Source`] that served it.
+    ///
+    /// Decorators serving Pokémons on their own, e.g. caches, should name
+    /// themselves when they do, and forward the lookup otherwise.
+    /// The default implementation names the repository `upstream`.
+    fn get_sourced(
+        &self,
+        num: u32,
+    ) -> impl Future<Output = Result<Option<(Pokemon, Source)>, Self::Error>> + Send
+    where
+        Self: Sync,
+    {
+        async move { Ok(self.get(num).await?.map(|pokemon| (pokemon, "upstream"))) }
+    }
+
+    /// Looks up several Pokémons at once, returning the result for each id
+    /// in the same order as `ids`.
+    ///
+    /// The default implementation looks them up one at a time: backends
+    /// should override it with something more efficient.
+    fn get_many(
+        &self,
+        ids: &[u32],
+    ) -> impl Future<Output = Vec<Result<Option<Pokemon>, Self::Error>>> + Send
+    where
+        Self: Sync,
+        Self::Error: Send,
+    {
+        async move {
+            let mut results = Vec::with_capacity(ids.len());
+
+            for id in ids {
+                results.push(self.get(*id).await);
+            }
+
+            results
+        }
+    }
+
+    /// Checks, as cheaply as possible, that the repository is able to serve
+    /// lookups, e.g. for health checks.
+    ///
+    /// Decorators should forward the probe to the repository they decorate,
+    /// bypassing any cache, and leave it out of their own bookkeeping such as
+    /// concurrency limits or circuit breaking. The default implementation
+    /// looks up Pokémon #1.
+    fn probe(&self) -> impl Future<Output = Result<(), Self::Error>> + Send
     where
-        Self: Sync + 'a;
+        Self: Sync,
+    {
+        async move { self.get(1).await.map(|_| ()) }
+    }
+}
+
+/// Companion of [`Repository`] for sources able to stream all their Pokémons.
+pub trait Listing: Repository {
+    /// Streams all the Pokémons known to the source, in Pokédex order.
+    fn stream_all(&self) -> impl Stream<Item = Result<Pokemon, Self::Error>> + Send + '_;
+}
+
+/// Companion of [`Repository`] for sources whose Pokémons can be modified.
+pub trait WritableRepository: Repository {
+    /// Adds a new Pokémon, failing if one with the same id already exists.
+    fn insert(
+        &self,
+        pokemon: Pokemon,
+    ) -> impl Future<Output = Result<(), WriteError<Self::Error>>> + Send;
+
+    /// Replaces an existing Pokémon, failing if none with the same id exists.
+    fn update(
+        &self,
+        pokemon: Pokemon,
+    ) -> impl Future<Output = Result<(), WriteError<Self::Error>>> + Send;
+
+    /// Removes an existing Pokémon, returning it.
+    fn delete(
+        &self,
+        num: u32,
+    ) -> impl Future<Output = Result<Pokemon, WriteError<Self::Error>>> + Send;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WriteError<E> {
+    AlreadyExists { dex_id: u32 },
+    NotFound { dex_id: u32 },
+    RepositoryError(E),
+}
+
+impl<E> std::error::Error for WriteError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use WriteError::*;
+
+        match self {
+            AlreadyExists { .. } => None,
+            NotFound { .. } => None,
+            RepositoryError(inner) => Some(inner),
+        }
+    }
+}
+
+impl<E> Display for WriteError<E>
+where
+    E: std::error::Error,
+{
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        use WriteError::*;
+
+        match self {
+            AlreadyExists { dex_id } => write!(f, "pokemon #{} already exists", dex_id),
+            NotFound { dex_id } => write!(f, "pokemon #{} not found", dex_id),
+            RepositoryError(inner) => Display::fmt(&inner, f),
+        }
+    }
+}
+
+/// Pokédex id and name of a Pokémon listed in a [`Catalogue`].
+pub type CatalogueEntry = (u32, String);
+
+/// Lists the Pokédex ids and names of all the Pokémons known to a source.
+pub trait Catalogue {
+    type Error: std::error::Error;
+
+    fn list(&self) -> impl Future<Output = Result<Vec<CatalogueEntry>, Self::Error>> + Send;
 }
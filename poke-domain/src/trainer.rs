@@ -5,7 +5,7 @@ use async_trait::async_trait;
 use eventually::optional::{Aggregate, CommandHandler, EventOf, StateOf};
 use eventually::{command, command::dispatcher::Identifiable};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 use crate::pokemon;
 use crate::pokemon::Pokemon;
@@ -14,20 +14,43 @@ use crate::pokemon::Pokemon;
 pub struct Trainer {
     name: String,
     sex: Sex,
+    #[serde(flatten)]
+    profile: Profile,
     pokemons: Vec<Pokemon>,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum Sex {
     Male,
     Female,
 }
 
+/// Optional details about a trainer, given when starting the adventure.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub age: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hometown: Option<String>,
+}
+
 #[derive(Clone, PartialEq)]
 pub enum TrainerCommand {
-    StartAdventure { name: String, sex: Sex },
-    AddPokemonToTeam { name: String, pokemon_id: u32 },
+    StartAdventure {
+        name: String,
+        sex: Sex,
+        profile: Profile,
+    },
+    AddPokemonToTeam {
+        name: String,
+        pokemon_id: u32,
+    },
+    /// Removes the Pokémon in the specified team slot, starting from 0.
+    RemovePokemonFromTeam {
+        name: String,
+        slot: usize,
+    },
 }
 
 impl Identifiable for TrainerCommand {
@@ -39,6 +62,7 @@ impl Identifiable for TrainerCommand {
         match self {
             StartAdventure { name, .. } => name.clone(),
             AddPokemonToTeam { name, .. } => name.clone(),
+            RemovePokemonFromTeam { name, .. } => name.clone(),
         }
     }
 }
@@ -75,23 +99,31 @@ where
         use TrainerEvent::*;
 
         match command {
-            StartAdventure { name, sex } => Ok(vec![AdventureStarted { name, sex }]),
+            StartAdventure { name, sex, profile } => {
+                Ok(vec![AdventureStarted { name, sex, profile }])
+            }
             AddPokemonToTeam { .. } => Err(InvalidCommand(AdventureNotStarted)),
+            RemovePokemonFromTeam { .. } => Err(InvalidCommand(AdventureNotStarted)),
         }
     }
 
     async fn handle_next(
         &self,
-        _state: &StateOf<Self::Aggregate>,
+        state: &StateOf<Self::Aggregate>,
         command: Self::Command,
     ) -> command::Result<EventOf<Self::Aggregate>, Self::Error> {
         use TrainerCommand::*;
         use TrainerCommandHandlerError::*;
         use TrainerError::*;
+        use TrainerEvent::*;
 
         match command {
             StartAdventure { name, .. } => Err(InvalidCommand(AdventureAlreadyStarted { name })),
             AddPokemonToTeam { pokemon_id, .. } => self.add_pokemon_to_team(pokemon_id).await,
+            RemovePokemonFromTeam { slot, .. } if slot < state.pokemons.len() => {
+                Ok(vec![PokemonRemoved { slot }])
+            }
+            RemovePokemonFromTeam { slot, .. } => Err(InvalidCommand(EmptyTeamSlot { slot })),
         }
     }
 }
@@ -157,14 +189,24 @@ where
 
 #[derive(Clone, PartialEq)]
 pub enum TrainerEvent {
-    AdventureStarted { name: String, sex: Sex },
-    PokemonAdded { pokemon: Pokemon },
+    AdventureStarted {
+        name: String,
+        sex: Sex,
+        profile: Profile,
+    },
+    PokemonAdded {
+        pokemon: Pokemon,
+    },
+    PokemonRemoved {
+        slot: usize,
+    },
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum TrainerError {
     AdventureAlreadyStarted { name: String },
     AdventureNotStarted,
+    EmptyTeamSlot { slot: usize },
 }
 
 impl std::error::Error for TrainerError {}
@@ -178,6 +220,7 @@ impl Display for TrainerError {
                 write!(f, "adventure already started for trainer {}", name)
             }
             AdventureNotStarted => write!(f, "adventure not started yet"),
+            EmptyTeamSlot { slot } => write!(f, "no pokemon in team slot {}", slot),
         }
     }
 }
@@ -192,12 +235,14 @@ impl Aggregate for Trainer {
         use TrainerEvent::*;
 
         match event {
-            AdventureStarted { name, sex } => Ok(Trainer {
+            AdventureStarted { name, sex, profile } => Ok(Trainer {
                 name,
                 sex,
+                profile,
                 pokemons: Vec::default(),
             }),
             PokemonAdded { .. } => Err(AdventureNotStarted),
+            PokemonRemoved { .. } => Err(AdventureNotStarted),
         }
     }
 
@@ -211,6 +256,131 @@ impl Aggregate for Trainer {
                 state.pokemons.push(pokemon);
                 Ok(state)
             }
+            PokemonRemoved { slot } if slot < state.pokemons.len() => {
+                state.pokemons.remove(slot);
+                Ok(state)
+            }
+            PokemonRemoved { slot } => Err(EmptyTeamSlot { slot }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::convert::Infallible;
+
+    use crate::pokemon::{Element, Stats, Type};
+
+    fn pokemon(dex_id: u32) -> Pokemon {
+        Pokemon {
+            dex_id,
+            name: format!("pokemon-{}", dex_id),
+            typ: Type::Single(Element::Normal),
+            height: 10,
+            weight: 100,
+            base_experience: 64,
+            stats: Stats {
+                speed: 45,
+                special_defense: 65,
+                special_attack: 65,
+                defense: 49,
+                attack: 49,
+                hit_points: 45,
+            },
+        }
+    }
+
+    /// Repository finding every Pokémon.
+    struct Everything;
+
+    impl pokemon::Repository for Everything {
+        type Error = Infallible;
+
+        async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+            Ok(Some(pokemon(num)))
         }
     }
+
+    fn trainer(team: &[u32]) -> Trainer {
+        Trainer {
+            name: "ash".to_owned(),
+            sex: Sex::Male,
+            profile: Profile::default(),
+            pokemons: team.iter().copied().map(pokemon).collect(),
+        }
+    }
+
+    fn remove(slot: usize) -> TrainerCommand {
+        TrainerCommand::RemovePokemonFromTeam {
+            name: "ash".to_owned(),
+            slot,
+        }
+    }
+
+    #[tokio::test]
+    async fn removes_pokemons_from_occupied_slots() {
+        let handler = TrainerCommandHandler::new(Everything);
+
+        let events = handler.handle_next(&trainer(&[1, 25]), remove(1)).await;
+        assert!(events == Ok(vec![TrainerEvent::PokemonRemoved { slot: 1 }]));
+    }
+
+    #[tokio::test]
+    async fn refuses_to_remove_pokemons_from_empty_slots() {
+        let handler = TrainerCommandHandler::new(Everything);
+
+        let result = handler.handle_next(&trainer(&[1, 25]), remove(2)).await;
+        assert!(
+            result
+                == Err(TrainerCommandHandlerError::InvalidCommand(
+                    TrainerError::EmptyTeamSlot { slot: 2 }
+                ))
+        );
+
+        let result = handler.handle_next(&trainer(&[]), remove(0)).await;
+        assert!(
+            result
+                == Err(TrainerCommandHandlerError::InvalidCommand(
+                    TrainerError::EmptyTeamSlot { slot: 0 }
+                ))
+        );
+    }
+
+    #[tokio::test]
+    async fn refuses_to_remove_pokemons_before_the_adventure() {
+        let handler = TrainerCommandHandler::new(Everything);
+
+        let result = handler.handle_first(remove(0)).await;
+        assert!(
+            result
+                == Err(TrainerCommandHandlerError::InvalidCommand(
+                    TrainerError::AdventureNotStarted
+                ))
+        );
+    }
+
+    #[test]
+    fn removed_pokemons_leave_the_team_in_order() {
+        let state = Trainer::apply_next(
+            trainer(&[1, 25, 150]),
+            TrainerEvent::PokemonRemoved { slot: 1 },
+        );
+
+        assert_eq!(state, Ok(trainer(&[1, 150])));
+    }
+
+    #[test]
+    fn removing_from_empty_slots_fails() {
+        assert_eq!(
+            Trainer::apply_next(trainer(&[1]), TrainerEvent::PokemonRemoved { slot: 1 }),
+            Err(TrainerError::EmptyTeamSlot { slot: 1 })
+        );
+
+        assert_eq!(
+            Trainer::apply_first(TrainerEvent::PokemonRemoved { slot: 0 }),
+            Err(TrainerError::AdventureNotStarted)
+        );
+    }
 }
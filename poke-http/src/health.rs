@@ -0,0 +1,309 @@
+//! Liveness and readiness probes.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::future::{self, BoxFuture, FutureExt};
+use serde::Serialize;
+
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use eventually::Store;
+
+use poke_domain::pokemon;
+use poke_memory::names::NameIndex;
+use poke_memory::warmup::WarmupStatus;
+
+use crate::routes;
+
+/// Time after which a readiness check is considered failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Source read by the event store check: trainer names can't be empty,
+/// so it never has any event.
+const PROBE_SOURCE: &str = "";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Up,
+    Down,
+}
+
+/// Outcome of the check of a dependency.
+#[derive(Clone, Debug, Serialize)]
+pub struct Health {
+    pub status: Status,
+    /// Whether the service is ready only if the dependency is up.
+    pub critical: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Health {
+    pub fn up() -> Self {
+        Health {
+            status: Status::Up,
+            critical: true,
+            detail: None,
+            error: None,
+        }
+    }
+
+    pub fn down(error: impl ToString) -> Self {
+        Health {
+            status: Status::Down,
+            error: Some(error.to_string()),
+            ..Health::up()
+        }
+    }
+
+    pub fn with_detail<T: Serialize>(mut self, detail: &T) -> Self {
+        self.detail = serde_json::to_value(detail).ok();
+        self
+    }
+}
+
+/// Checks the repository with [`pokemon::Repository::probe`].
+pub async fn probe<R>(repository: R) -> Health
+where
+    R: pokemon::Repository + Sync,
+{
+    match repository.probe().await {
+        Ok(()) => Health::up(),
+        Err(err) => Health::down(err),
+    }
+}
+
+/// Checks the event store with a round trip, streaming the events
+/// of a source that doesn't exist.
+pub async fn event_store<S>(store: S) -> Health
+where
+    S: Store<SourceId = String>,
+    S::Offset: Default,
+    S::Error: Display,
+{
+    match store
+        .stream(PROBE_SOURCE.to_owned(), S::Offset::default())
+        .await
+    {
+        Ok(_) => Health::up(),
+        Err(err) => Health::down(err),
+    }
+}
+
+/// Reports the cache warm-up, which is up once the warm-up has finished.
+pub fn warmup(status: &WarmupStatus) -> Health {
+    let progress = status.progress();
+
+    let health = if progress.finished {
+        Health::up()
+    } else {
+        Health::down("cache warm-up in progress")
+    };
+
+    health.with_detail(&progress)
+}
+
+/// Reports the index of the Pokémon names, which is up once loaded:
+/// until then, lookups by name can't find any Pokémon.
+pub fn names(index: &NameIndex) -> Health {
+    let health = if index.is_loaded() {
+        Health::up()
+    } else {
+        Health::down("pokemon names not loaded yet")
+    };
+
+    health.with_detail(&serde_json::json!({ "names": index.len() }))
+}
+
+type Check = Arc<dyn Fn() -> BoxFuture<'static, Health> + Send + Sync>;
+
+/// Dependencies checked by the readiness probe.
+#[derive(Clone, Default)]
+pub struct Readiness {
+    checks: Vec<(&'static str, bool, Check)>,
+}
+
+impl Readiness {
+    pub fn new() -> Self {
+        Readiness::default()
+    }
+
+    /// Adds a dependency the service can't be ready without.
+    pub fn critical<F, Fut>(self, name: &'static str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Health> + Send + 'static,
+    {
+        self.check(name, true, check)
+    }
+
+    /// Adds a dependency whose failures are reported, but degrade
+    /// the service without making it unready, e.g. an upstream
+    /// shared by all the instances of the service.
+    pub fn optional<F, Fut>(self, name: &'static str, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Health> + Send + 'static,
+    {
+        self.check(name, false, check)
+    }
+
+    fn check<F, Fut>(mut self, name: &'static str, critical: bool, check: F) -> Self
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Health> + Send + 'static,
+    {
+        self.checks
+            .push((name, critical, Arc::new(move || check().boxed())));
+        self
+    }
+
+    /// Runs all the checks concurrently.
+    async fn run(&self) -> Report {
+        let checks = self.checks.iter().map(|(name, critical, check)| {
+            let check = tokio::time::timeout(CHECK_TIMEOUT, check());
+
+            async move {
+                let health = check.await.unwrap_or_else(|_| {
+                    Health::down(format!("check timed out after {:?}", CHECK_TIMEOUT))
+                });
+
+                let health = Health {
+                    critical: *critical,
+                    ..health
+                };
+
+                (*name, health)
+            }
+        });
+
+        let checks: BTreeMap<_, _> = future::join_all(checks).await.into_iter().collect();
+
+        let ready = checks
+            .values()
+            .all(|health| !health.critical || health.status == Status::Up);
+
+        Report { ready, checks }
+    }
+}
+
+#[derive(Serialize)]
+struct Report {
+    ready: bool,
+    checks: BTreeMap<&'static str, Health>,
+}
+
+/// `GET /healthz` answers as long as the process is serving requests;
+/// `GET /readyz` reports the health of every dependency, answering
+/// with `503 Service Unavailable` if any critical one is down.
+pub fn health(readiness: Readiness) -> BoxedFilter<(impl Reply,)> {
+    let healthz = routes::HEALTHZ
+        .filter()
+        .map(|| warp::reply::json(&serde_json::json!({ "status": "alive" })));
+
+    let readyz = routes::READYZ
+        .filter()
+        .and(warp::any().map(move || readiness.clone()))
+        .and_then(get_readiness);
+
+    healthz.or(readyz).boxed()
+}
+
+async fn get_readiness(
+    readiness: Readiness,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection> {
+    let report = readiness.run().await;
+
+    let status = if report.ready {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    Ok(warp::reply::with_status(warp::reply::json(&report), status))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn get(readiness: Readiness, path: &str) -> (StatusCode, serde_json::Value) {
+        let response = warp::test::request()
+            .path(path)
+            .reply(&health(readiness))
+            .await;
+        let body = serde_json::from_slice(response.body()).unwrap();
+
+        (response.status(), body)
+    }
+
+    fn up() -> impl Future<Output = Health> {
+        future::ready(Health::up())
+    }
+
+    fn down() -> impl Future<Output = Health> {
+        future::ready(Health::down("unreachable"))
+    }
+
+    #[tokio::test]
+    async fn critical_checks_down_make_the_service_unready() {
+        let readiness = Readiness::new()
+            .critical("store", down)
+            .optional("upstream", up);
+
+        let (status, body) = get(readiness, "/readyz").await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["ready"], false);
+        assert_eq!(body["checks"]["store"]["status"], "down");
+        assert_eq!(body["checks"]["store"]["error"], "unreachable");
+    }
+
+    #[tokio::test]
+    async fn optional_checks_down_leave_the_service_ready() {
+        let readiness = Readiness::new()
+            .critical("store", up)
+            .optional("upstream", down);
+
+        let (status, body) = get(readiness, "/readyz").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["ready"], true);
+        assert_eq!(body["checks"]["upstream"]["status"], "down");
+        assert_eq!(body["checks"]["upstream"]["critical"], false);
+    }
+
+    #[tokio::test]
+    async fn slow_checks_are_down() {
+        // Waits for the whole CHECK_TIMEOUT.
+        let readiness = Readiness::new().critical("store", future::pending::<Health>);
+
+        let (status, body) = get(readiness, "/readyz").await;
+
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["checks"]["store"]["status"], "down");
+        assert_eq!(
+            body["checks"]["store"]["error"],
+            format!("check timed out after {:?}", CHECK_TIMEOUT)
+        );
+    }
+
+    #[tokio::test]
+    async fn the_service_is_alive_even_if_unready() {
+        let readiness = Readiness::new().critical("store", down);
+
+        let (status, body) = get(readiness, "/healthz").await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "alive");
+    }
+}
@@ -1,128 +1,512 @@
+pub mod health;
+pub mod metrics;
+pub mod problem;
+pub mod routes;
+mod trainers;
+
+use serde::Deserialize;
+
 use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
 use warp::{Filter, Reply};
 
 use eventually::command::{Dispatcher, Handler};
 use eventually::optional::AsAggregate as OptionalAggregate;
 use eventually::versioned::AsAggregate as VersionedAggregate;
 
-use poke_domain::pokemon;
-use poke_domain::trainer::{Trainer, TrainerCommand};
+use poke_domain::pokemon::{self, Pokemon};
+use poke_domain::trainer::{Trainer, TrainerCommand, TrainerCommandHandlerError};
+use poke_layer::metrics::MetricsHandle;
+use poke_memory::breaker::{self, BreakerLayer};
+use poke_memory::cache::CacheLayer;
+use poke_memory::fallback::FallbackLayer;
+use poke_memory::names::NameIndex;
+use poke_memory::patch::PatchLayer;
+use poke_memory::warmup::WarmupStatus;
+
+use metrics::Registry;
+use problem::{FieldError, Problem};
+
+/// OpenAPI document of the routes served by the crate.
+const OPENAPI: &str = include_str!("../openapi.json");
 
-pub fn api<R, D>(repository: R, dispatcher: D) -> BoxedFilter<(impl Reply,)>
+/// Pokémon lookups and trainer adventures.
+///
+/// `E` is the error of the repository used by the trainer command handler,
+/// needed to tell the command failures apart.
+pub fn api<R, D, E>(
+    repository: R,
+    names: NameIndex,
+    dispatcher: D,
+    metrics: Registry,
+) -> BoxedFilter<(impl Reply,)>
 where
     R: pokemon::Repository + Send + Sync + Clone + 'static,
+    R::Error: 'static,
     D: Dispatcher + Send + Sync + Clone + 'static,
     <D as Dispatcher>::CommandHandler: Handler<
         Command = TrainerCommand,
         Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
+        Error = TrainerCommandHandlerError<E>,
     >,
-    <D as Dispatcher>::Error: std::error::Error,
+    <D as Dispatcher>::Error: std::error::Error + 'static,
+    E: std::error::Error + 'static,
 {
-    let api = warp::path("pokemons");
-
-    let get_pokemon_by_id = api
-        .and(warp::get())
-        .and(warp::path::param())
-        .and(with_repository(repository))
+    let get_pokemon_by_id = routes::GET_POKEMON
+        .param::<u32>()
+        .and(with_repository(repository.clone()))
         .and_then(get_pokemon_by_id);
 
-    let get_pokemon_by_name = api
-        .and(warp::get())
-        .and(warp::path!("name" / String))
+    let get_pokemon_by_name = routes::GET_POKEMON_BY_NAME
+        .param::<String>()
+        .and(with_names(names.clone()))
+        .and(with_repository(repository))
         .and_then(get_pokemon_by_name);
 
-    let start_adventure = api
-        .and(warp::post())
-        .and(warp::path!("adventure" / "start" / "name" / String))
-        .and(with_dispatcher(dispatcher.clone()))
-        .and_then(start_adventure_trainer);
-
-    let add_pokemon = api
-        .and(warp::post())
-        .and(warp::path!("adventure" / String / "team" / "add" / u32))
-        .and(with_dispatcher(dispatcher))
-        .and_then(add_pokemon_to_team);
+    let autocomplete = routes::AUTOCOMPLETE
+        .filter()
+        .and(warp::query::<AutocompleteQuery>())
+        .and(with_names(names))
+        .map(autocomplete);
 
-    warp::any()
-        .and(get_pokemon_by_id)
+    get_pokemon_by_id
         .or(get_pokemon_by_name)
-        .or(add_pokemon)
-        .or(start_adventure)
+        .or(autocomplete)
+        .or(trainers::routes(dispatcher, metrics))
         .boxed()
 }
 
-async fn get_pokemon_by_id<R>(id: u32, repository: R) -> Result<warp::reply::Json, warp::Rejection>
+pub fn cache<R>(cache: CacheLayer<R>, warmup: WarmupStatus) -> BoxedFilter<(impl Reply,)>
+where
+    R: pokemon::Repository + Send + Sync + Clone + 'static,
+    R::Error: Send + Sync,
+{
+    let stats = routes::CACHE_STATS
+        .filter()
+        .and(warp::any().map(move || cache.clone()))
+        .and_then(get_cache_stats);
+
+    let warmup = routes::CACHE_WARMUP
+        .filter()
+        .map(move || get_cache_warmup(&warmup));
+
+    stats.or(warmup).boxed()
+}
+
+/// Admin endpoints to insert, update and delete custom Pokémons,
+/// keeping the `names` index in sync with the changes.
+///
+/// Every request must carry an `Authorization: Bearer <token>` header
+/// matching `token`, or it is answered with `401 Unauthorized`.
+/// The endpoints are disabled if no token is set.
+pub fn admin<R>(
+    repository: R,
+    names: NameIndex,
+    token: Option<String>,
+) -> BoxedFilter<(impl Reply,)>
+where
+    R: pokemon::WritableRepository + Send + Sync + Clone + 'static,
+    R::Error: 'static,
+{
+    let insert_pokemon = routes::INSERT_POKEMON
+        .filter()
+        .and(authorized(token.clone()))
+        .and(warp::body::json())
+        .and(with_repository(repository.clone()))
+        .and(with_names(names.clone()))
+        .and_then(insert_pokemon);
+
+    let update_pokemon = routes::UPDATE_POKEMON
+        .param::<u32>()
+        .and(authorized(token.clone()))
+        .and(warp::body::json())
+        .and(with_repository(repository.clone()))
+        .and(with_names(names.clone()))
+        .and_then(update_pokemon);
+
+    let delete_pokemon = routes::DELETE_POKEMON
+        .param::<u32>()
+        .and(authorized(token))
+        .and(with_repository(repository))
+        .and(with_names(names))
+        .and_then(delete_pokemon);
+
+    insert_pokemon.or(update_pokemon).or(delete_pokemon).boxed()
+}
+
+/// Endpoints to inspect the balance patches applied over upstream Pokémons,
+/// and to reload them from file, the latter requiring the admin `token`.
+pub fn patches<R>(layer: PatchLayer<R>, token: Option<String>) -> BoxedFilter<(impl Reply,)>
+where
+    R: pokemon::Repository + Send + Sync + Clone + 'static,
+{
+    let list_patches = routes::LIST_PATCHES
+        .filter()
+        .and(with_patches(layer.clone()))
+        .and_then(list_patches);
+
+    let get_patch_diff = routes::PATCH_DIFF
+        .param::<u32>()
+        .and(with_patches(layer.clone()))
+        .and_then(get_patch_diff);
+
+    let reload_patches = routes::RELOAD_PATCHES
+        .filter()
+        .and(authorized(token))
+        .and(with_patches(layer))
+        .and_then(reload_patches);
+
+    list_patches.or(get_patch_diff).or(reload_patches).boxed()
+}
+
+async fn list_patches<R>(layer: PatchLayer<R>) -> Result<warp::reply::Json, warp::Rejection> {
+    Ok(warp::reply::json(&layer.patches().await))
+}
+
+async fn get_patch_diff<R>(
+    id: u32,
+    layer: PatchLayer<R>,
+) -> Result<warp::reply::Json, warp::Rejection>
 where
     R: pokemon::Repository + Send + Sync,
+    R::Error: 'static,
 {
-    let result = repository.get(id).await.map_err(|err| {
-        log::error!("Error received while calling repository: {}", err);
-        warp::reject()
-    })?;
+    let result = layer
+        .diff(id)
+        .await
+        .map_err(|err| Problem::repository(&err))?;
 
     match result {
-        None => Err(warp::reject::not_found()),
-        Some(pokemon) => Ok({
-            log::debug!("Pokemon found: {:?}", pokemon);
-            warp::reply::json(&pokemon)
-        }),
+        None => Err(Problem::pokemon_not_found(id).into()),
+        Some(diff) => Ok(warp::reply::json(&diff)),
     }
 }
 
-async fn get_pokemon_by_name(_name: String) -> Result<warp::reply::Json, warp::Rejection> {
-    Err(warp::reject::not_found())
+async fn reload_patches<R>(layer: PatchLayer<R>) -> Result<warp::reply::Json, warp::Rejection> {
+    match layer.reload().await {
+        Ok(count) => Ok(warp::reply::json(&serde_json::json!({ "patches": count }))),
+        Err(err) => {
+            log::error!("Failed to reload patches: {}", err);
+
+            Err(Problem::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "invalid_patches",
+                "Invalid balance patches",
+            )
+            .with_detail(err.to_string())
+            .into())
+        }
+    }
 }
 
-async fn start_adventure_trainer<D>(
-    name: String,
-    mut dispatcher: D,
-) -> Result<warp::reply::Json, warp::Rejection>
+fn with_patches<R>(
+    layer: PatchLayer<R>,
+) -> impl Filter<Extract = (PatchLayer<R>,), Error = std::convert::Infallible> + Clone
 where
-    D: Dispatcher,
-    <D as Dispatcher>::CommandHandler: Handler<
-        Command = TrainerCommand,
-        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
-    >,
+    R: Send + Sync + Clone,
 {
-    let result = dispatcher
-        .dispatch(TrainerCommand::StartAdventure {
-            name,
-            sex: poke_domain::trainer::Sex::Male,
+    warp::any().map(move || layer.clone())
+}
+
+/// Health check of the upstream repository, reporting the circuit breaker
+/// state: answers with `503 Service Unavailable` while the circuit is open.
+pub fn breaker<R>(layer: BreakerLayer<R>) -> BoxedFilter<(impl Reply,)>
+where
+    R: Send + Sync + Clone + 'static,
+{
+    routes::UPSTREAM_HEALTH
+        .filter()
+        .map(move || {
+            let status = layer.status();
+
+            let code = match status.state {
+                breaker::State::Open => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::OK,
+            };
+
+            warp::reply::with_status(warp::reply::json(&status), code)
         })
-        .await
-        .map_err(|_err| warp::reject())?;
+        .boxed()
+}
+
+/// OpenAPI document of all the routes, and Swagger UI to browse it.
+///
+/// The document is maintained by hand in `openapi.json`: the `openapi` test
+/// checks that it documents every route of [`routes::ROUTES`]. Swagger UI
+/// is vendored in `swagger-ui/`, so that the docs work without reaching a CDN.
+pub fn docs() -> BoxedFilter<(impl Reply,)> {
+    const SWAGGER_UI: &str = include_str!("../swagger-ui/index.html");
+
+    /// Assets loaded by the Swagger UI page, with their content type.
+    const ASSETS: &[(&str, &str, &str)] = &[
+        (
+            "swagger-ui.css",
+            "text/css",
+            include_str!("../swagger-ui/swagger-ui.css"),
+        ),
+        (
+            "swagger-ui-bundle.js",
+            "application/javascript",
+            include_str!("../swagger-ui/swagger-ui-bundle.js"),
+        ),
+    ];
 
-    log::debug!("Returned state: {:?}", result);
+    let openapi = routes::OPENAPI
+        .filter()
+        .map(|| warp::reply::with_header(OPENAPI, "content-type", "application/json"));
 
-    Ok(warp::reply::json(&(result.take())))
+    let swagger_ui = routes::DOCS.filter().map(|| warp::reply::html(SWAGGER_UI));
+
+    let asset = routes::DOCS_ASSET
+        .param::<String>()
+        .and_then(|name: String| async move {
+            ASSETS
+                .iter()
+                .find(|(asset, _, _)| *asset == name)
+                .map(|(_, content_type, body)| {
+                    warp::reply::with_header(*body, "content-type", *content_type)
+                })
+                .ok_or_else(warp::reject::not_found)
+        });
+
+    openapi.or(swagger_ui).or(asset).boxed()
 }
 
-async fn add_pokemon_to_team<D>(
-    name: String,
-    pokemon_id: u32,
-    mut dispatcher: D,
+/// Lookup metrics of the upstream repository.
+pub fn upstream_metrics(metrics: MetricsHandle) -> BoxedFilter<(impl Reply,)> {
+    routes::UPSTREAM_METRICS
+        .filter()
+        .map(move || warp::reply::json(&metrics.snapshot()))
+        .boxed()
+}
+
+/// Statistics of the fallback repository, counting the lookups served by each source.
+pub fn fallback<P, S>(layer: FallbackLayer<P, S>) -> BoxedFilter<(impl Reply,)>
+where
+    P: Send + Sync + Clone + 'static,
+    S: Send + Sync + Clone + 'static,
+{
+    routes::FALLBACK_STATS
+        .filter()
+        .map(move || warp::reply::json(&layer.stats()))
+        .boxed()
+}
+
+fn authorized(token: Option<String>) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+    let expected = token.map(|token| format!("Bearer {}", token));
+
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let result = match (&expected, header) {
+                (None, _) => Err(warp::reject::not_found()),
+                (Some(expected), Some(header)) if constant_time_eq(&header, expected) => Ok(()),
+                (Some(_), _) => Err(Problem::unauthorized().into()),
+            };
+
+            async move { result }
+        })
+        .untuple_one()
+}
+
+/// Compares the strings in a time depending only on their lengths,
+/// so that the time taken doesn't leak how much of a secret was guessed.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn validate_pokemon(pokemon: &Pokemon) -> Vec<FieldError> {
+    let mut errors = Vec::new();
+
+    if pokemon.dex_id == 0 {
+        errors.push(FieldError::new("dex_id", "must be a Pokédex id".to_owned()));
+    }
+
+    if pokemon.name.trim().is_empty() {
+        errors.push(FieldError::new("name", "must not be empty".to_owned()));
+    }
+
+    errors
+}
+
+async fn insert_pokemon<R>(
+    pokemon: Pokemon,
+    repository: R,
+    names: NameIndex,
+) -> Result<warp::reply::WithStatus<warp::reply::Json>, warp::Rejection>
+where
+    R: pokemon::WritableRepository + Send + Sync,
+    R::Error: 'static,
+{
+    let errors = validate_pokemon(&pokemon);
+    if !errors.is_empty() {
+        return Err(Problem::validation(errors).into());
+    }
+
+    let reply = warp::reply::json(&pokemon);
+    let (dex_id, name) = (pokemon.dex_id, pokemon.name.clone());
+
+    repository.insert(pokemon).await.map_err(Problem::write)?;
+    names.insert(dex_id, name);
+
+    Ok(warp::reply::with_status(reply, StatusCode::CREATED))
+}
+
+async fn update_pokemon<R>(
+    id: u32,
+    pokemon: Pokemon,
+    repository: R,
+    names: NameIndex,
 ) -> Result<warp::reply::Json, warp::Rejection>
 where
-    D: Dispatcher,
-    <D as Dispatcher>::CommandHandler: Handler<
-        Command = TrainerCommand,
-        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
-    >,
-    <D as Dispatcher>::Error: std::error::Error,
+    R: pokemon::WritableRepository + Send + Sync,
+    R::Error: 'static,
 {
-    log::debug!("banana");
+    if pokemon.dex_id != id {
+        return Err(Problem::bad_request(format!(
+            "pokemon id {} does not match path id {}",
+            pokemon.dex_id, id
+        ))
+        .into());
+    }
+
+    let errors = validate_pokemon(&pokemon);
+    if !errors.is_empty() {
+        return Err(Problem::validation(errors).into());
+    }
+
+    let reply = warp::reply::json(&pokemon);
+    let name = pokemon.name.clone();
+
+    repository.update(pokemon).await.map_err(Problem::write)?;
+    names.insert(id, name);
+
+    Ok(reply)
+}
 
-    let result = dispatcher
-        .dispatch(TrainerCommand::AddPokemonToTeam { name, pokemon_id })
+async fn delete_pokemon<R>(
+    id: u32,
+    repository: R,
+    names: NameIndex,
+) -> Result<warp::reply::Json, warp::Rejection>
+where
+    R: pokemon::WritableRepository + Send + Sync,
+    R::Error: 'static,
+{
+    let pokemon = repository.delete(id).await.map_err(Problem::write)?;
+
+    // The custom Pokémon might have been replacing an upstream one,
+    // whose name is to be found again.
+    match repository.get(id).await {
+        Ok(Some(upstream)) => names.insert(id, upstream.name),
+        Ok(None) => names.remove(id),
+        Err(err) => {
+            log::warn!(
+                "Failed to look up Pokemon #{} after deleting it: {}",
+                id,
+                err
+            );
+            names.remove(id);
+        }
+    }
+
+    Ok(warp::reply::json(&pokemon))
+}
+
+async fn get_cache_stats<R>(cache: CacheLayer<R>) -> Result<warp::reply::Json, warp::Rejection>
+where
+    R: pokemon::Repository,
+{
+    Ok(warp::reply::json(&cache.stats().await))
+}
+
+fn get_cache_warmup(warmup: &WarmupStatus) -> impl Reply {
+    let status = if warmup.is_finished() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+
+    warp::reply::with_status(warp::reply::json(&warmup.progress()), status)
+}
+
+/// Header naming the repository that served a Pokémon, e.g. `cache` or `pokeapi`.
+const SOURCE_HEADER: &str = "x-poke-source";
+
+async fn get_pokemon_by_id<R>(
+    id: u32,
+    repository: R,
+) -> Result<warp::reply::WithHeader<warp::reply::Json>, warp::Rejection>
+where
+    R: pokemon::Repository + Send + Sync,
+    R::Error: 'static,
+{
+    let result = repository
+        .get_sourced(id)
         .await
-        .map_err(|err| {
-            log::error!("failed to add pokemon to team: {}", err);
-            warp::reject()
-        })?;
+        .map_err(|err| Problem::repository(&err))?;
+
+    match result {
+        None => Err(Problem::pokemon_not_found(id).into()),
+        Some((pokemon, source)) => Ok({
+            log::debug!("Pokemon found in {}: {:?}", source, pokemon);
+            warp::reply::with_header(warp::reply::json(&pokemon), SOURCE_HEADER, source)
+        }),
+    }
+}
+
+/// Number of suggestions returned when no Pokémon has the name looked up.
+const SUGGESTIONS: usize = 5;
+
+async fn get_pokemon_by_name<R>(
+    name: String,
+    names: NameIndex,
+    repository: R,
+) -> Result<warp::reply::WithHeader<warp::reply::Json>, warp::Rejection>
+where
+    R: pokemon::Repository + Send + Sync,
+    R::Error: 'static,
+{
+    let id = match names.find(&name) {
+        Some(id) => id,
+        None => {
+            let suggestions = names.suggest(&name, SUGGESTIONS);
+            log::debug!("Pokemon {:?} not found, suggesting {:?}", name, suggestions);
+
+            return Err(Problem::new(
+                StatusCode::NOT_FOUND,
+                "pokemon_not_found",
+                "Pokémon not found",
+            )
+            .with_detail(format!("pokemon {:?} not found", name))
+            .with_extension("suggestions", serde_json::json!(suggestions))
+            .into());
+        }
+    };
+
+    get_pokemon_by_id(id, repository).await
+}
 
-    log::debug!("Returned state: {:?}", result);
+/// Default and maximum number of names returned by the autocompletion.
+const AUTOCOMPLETE_LIMIT: usize = 10;
+const AUTOCOMPLETE_MAX_LIMIT: usize = 100;
 
-    Ok(warp::reply::json(&(result.take())))
+#[derive(Deserialize)]
+struct AutocompleteQuery {
+    q: String,
+    limit: Option<usize>,
+}
+
+fn autocomplete(query: AutocompleteQuery, names: NameIndex) -> warp::reply::Json {
+    let limit = query
+        .limit
+        .unwrap_or(AUTOCOMPLETE_LIMIT)
+        .min(AUTOCOMPLETE_MAX_LIMIT);
+
+    warp::reply::json(&names.autocomplete(&query.q, limit))
 }
 
 fn with_repository<R>(
@@ -134,15 +518,226 @@ where
     warp::any().map(move || repository.clone())
 }
 
-fn with_dispatcher<D>(
-    dispatcher: D,
-) -> impl Filter<Extract = (D,), Error = std::convert::Infallible> + Clone
-where
-    D: Dispatcher + Send + Sync + Clone,
-    <D as Dispatcher>::CommandHandler: Handler<
-        Command = TrainerCommand,
-        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
-    >,
-{
-    warp::any().map(move || dispatcher.clone())
+fn with_names(
+    names: NameIndex,
+) -> impl Filter<Extract = (NameIndex,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || names.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use poke_domain::pokemon::{Element, Stats, Type};
+
+    fn pokemon(dex_id: u32) -> Pokemon {
+        Pokemon {
+            dex_id,
+            name: format!("pokemon-{}", dex_id),
+            typ: Type::Single(Element::Normal),
+            height: 10,
+            weight: 100,
+            base_experience: 64,
+            stats: Stats {
+                speed: 45,
+                special_defense: 65,
+                special_attack: 65,
+                defense: 49,
+                attack: 49,
+                hit_points: 45,
+            },
+        }
+    }
+
+    #[test]
+    fn compares_tokens() {
+        assert!(constant_time_eq("Bearer secret", "Bearer secret"));
+        assert!(!constant_time_eq("Bearer secreT", "Bearer secret"));
+        assert!(!constant_time_eq("Bearer secret!", "Bearer secret"));
+        assert!(!constant_time_eq("", "Bearer secret"));
+    }
+
+    #[tokio::test]
+    async fn authorizes_the_admin_token_only() {
+        let filter = authorized(Some("secret".to_owned())).map(warp::reply);
+
+        let ok = warp::test::request()
+            .header("authorization", "Bearer secret")
+            .reply(&filter)
+            .await;
+        assert_eq!(ok.status(), StatusCode::OK);
+
+        for header in &["Bearer secreT", "Bearer secret ", "secret"] {
+            let rejected = warp::test::request()
+                .header("authorization", *header)
+                .filter(&filter)
+                .await;
+            assert!(rejected.is_err(), "{:?} authorized", header);
+        }
+
+        assert!(warp::test::request().filter(&filter).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reports_the_source_of_pokemons() {
+        let repository = poke_memory::InMemoryRepository::from(vec![pokemon(25)]);
+        let filter = routes::GET_POKEMON
+            .param::<u32>()
+            .and(with_repository(repository))
+            .and_then(get_pokemon_by_id);
+
+        let response = warp::test::request()
+            .path("/pokemons/25")
+            .reply(&filter)
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[SOURCE_HEADER], "memory");
+    }
+
+    #[tokio::test]
+    async fn maps_the_upstream_faults_of_lookups() {
+        use poke_layer::fault::FaultConfig;
+        use poke_layer::{FaultLayer, Layer};
+
+        let lookup = |faults| {
+            let repository = FaultLayer::new(faults)
+                .layer(poke_memory::InMemoryRepository::from(vec![pokemon(25)]));
+
+            let filter = routes::GET_POKEMON
+                .param::<u32>()
+                .and(with_repository(repository))
+                .and_then(get_pokemon_by_id)
+                .recover(problem::recover);
+
+            async move {
+                warp::test::request()
+                    .path("/pokemons/25")
+                    .reply(&filter)
+                    .await
+            }
+        };
+
+        let response = lookup(FaultConfig {
+            error_rate: 1.0,
+            ..FaultConfig::default()
+        })
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+        let response = lookup(FaultConfig {
+            not_found_rate: 1.0,
+            ..FaultConfig::default()
+        })
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = lookup(FaultConfig::default()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn validates_admin_pokemons() {
+        let mut pokemon = Pokemon {
+            dex_id: 0,
+            name: " ".to_owned(),
+            ..pokemon(25)
+        };
+
+        let fields: Vec<_> = validate_pokemon(&pokemon)
+            .iter()
+            .map(|error| error.field)
+            .collect();
+        assert_eq!(fields, vec!["dex_id", "name"]);
+
+        pokemon.dex_id = 10001;
+        pokemon.name = "fakemon".to_owned();
+        assert!(validate_pokemon(&pokemon).is_empty());
+    }
+
+    #[tokio::test]
+    async fn admin_writes_update_the_name_index() {
+        let upstream = poke_memory::InMemoryRepository::from(vec![pokemon(25)]);
+        let repository = poke_memory::custom::CustomLayer::from(upstream);
+        let names = NameIndex::new(vec![(25, "pokemon-25".to_owned())]);
+        let filter = admin(repository, names.clone(), Some("secret".to_owned()));
+
+        let request = |method: &str, path: &str| {
+            warp::test::request()
+                .method(method)
+                .path(path)
+                .header("authorization", "Bearer secret")
+        };
+
+        let fakemon = Pokemon {
+            name: "fakemon".to_owned(),
+            ..pokemon(10001)
+        };
+        let response = request("POST", "/admin/pokemons")
+            .json(&fakemon)
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(names.find("fakemon"), Some(10001));
+
+        let renamed = Pokemon {
+            name: "fakemon-2".to_owned(),
+            ..fakemon
+        };
+        let response = request("PUT", "/admin/pokemons/10001")
+            .json(&renamed)
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(names.find("fakemon"), None);
+        assert_eq!(names.find("fakemon-2"), Some(10001));
+
+        let response = request("DELETE", "/admin/pokemons/10001")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(names.find("fakemon-2"), None);
+
+        // Deleting a custom replacement brings the upstream name back.
+        let replacement = Pokemon {
+            name: "pikachu-rock-star".to_owned(),
+            ..pokemon(25)
+        };
+        request("POST", "/admin/pokemons")
+            .json(&replacement)
+            .reply(&filter)
+            .await;
+        assert_eq!(names.find("pikachu-rock-star"), Some(25));
+
+        request("DELETE", "/admin/pokemons/25").reply(&filter).await;
+        assert_eq!(names.find("pikachu-rock-star"), None);
+        assert_eq!(names.find("pokemon-25"), Some(25));
+    }
+
+    #[tokio::test]
+    async fn serves_the_vendored_swagger_ui() {
+        let filter = docs().recover(problem::recover);
+
+        let page = warp::test::request().path("/docs").reply(&filter).await;
+        assert_eq!(page.status(), StatusCode::OK);
+
+        let page = String::from_utf8_lossy(page.body());
+        assert!(!page.contains("https://"), "docs load remote assets");
+
+        for (asset, content_type) in &[
+            ("/docs/swagger-ui.css", "text/css"),
+            ("/docs/swagger-ui-bundle.js", "application/javascript"),
+        ] {
+            assert!(page.contains(asset), "{} not loaded by the docs", asset);
+
+            let response = warp::test::request().path(asset).reply(&filter).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(response.headers()["content-type"], *content_type);
+        }
+
+        let response = warp::test::request()
+            .path("/docs/swagger-ui.js")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,697 @@
+//! Metrics of the service, exposed in the Prometheus text format.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use futures::future::{BoxFuture, FutureExt};
+use futures::stream::BoxStream;
+
+use warp::filters::BoxedFilter;
+use warp::http::{Method, StatusCode};
+use warp::{Filter, Reply};
+
+use eventually::Store;
+
+use poke_domain::pokemon;
+use poke_layer::metrics::{Histogram, HistogramSnapshot, MetricsHandle};
+use poke_memory::cache::CacheLayer;
+
+use crate::routes;
+
+/// Label of the requests not matching any documented route, so that
+/// unknown paths don't grow the number of series.
+const UNMATCHED: &str = "unmatched";
+
+/// Collects the metrics of the HTTP requests, of the repository backends,
+/// of the trainer commands and of the event store.
+///
+/// Clones share the same metrics.
+#[derive(Clone)]
+pub struct Registry(Arc<Inner>);
+
+struct Inner {
+    requests: Mutex<BTreeMap<(Option<&'static str>, &'static str), RequestStats>>,
+    backends: Mutex<Vec<(&'static str, MetricsHandle)>>,
+    commands: Mutex<BTreeMap<&'static str, CommandStats>>,
+    appends: Histogram,
+}
+
+#[derive(Default)]
+struct RequestStats {
+    statuses: BTreeMap<u16, u64>,
+    latency: Histogram,
+}
+
+#[derive(Default)]
+struct CommandStats {
+    outcomes: BTreeMap<&'static str, u64>,
+    latency: Histogram,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Registry::new()
+    }
+}
+
+impl Registry {
+    /// Creates a new registry, labelling the requests with the templates
+    /// of the [`routes::ROUTES`] they match.
+    pub fn new() -> Self {
+        Registry(Arc::new(Inner {
+            requests: Mutex::default(),
+            backends: Mutex::default(),
+            commands: Mutex::default(),
+            appends: Histogram::default(),
+        }))
+    }
+
+    /// Exposes the metrics of a repository, as collected by a `MetricsLayer`.
+    pub fn backend(&self, name: &'static str, metrics: MetricsHandle) {
+        lock(&self.0.backends).push((name, metrics));
+    }
+
+    fn observe_request(&self, method: &Method, path: &str, status: StatusCode, elapsed: Duration) {
+        let key = (route(path), method_label(method));
+
+        let mut requests = lock(&self.0.requests);
+        let stats = requests.entry(key).or_default();
+
+        *stats.statuses.entry(status.as_u16()).or_default() += 1;
+        stats.latency.observe(elapsed);
+    }
+
+    pub(crate) fn observe_command(
+        &self,
+        command: &'static str,
+        outcome: &'static str,
+        elapsed: Duration,
+    ) {
+        let mut commands = lock(&self.0.commands);
+        let stats = commands.entry(command).or_default();
+
+        *stats.outcomes.entry(outcome).or_default() += 1;
+        stats.latency.observe(elapsed);
+    }
+
+    /// Times the appends to the event store, returned wrapped in a [`MeteredStore`].
+    pub fn event_store<S>(&self, store: S) -> MeteredStore<S> {
+        MeteredStore {
+            store,
+            registry: self.clone(),
+        }
+    }
+
+    fn encode_requests(&self, encoder: &mut Encoder) {
+        let requests = lock(&self.0.requests);
+
+        let route = |template: &Option<&'static str>| template.unwrap_or(UNMATCHED);
+
+        encoder.family(
+            "poke_http_requests_total",
+            "counter",
+            "HTTP requests served, by route, method and status.",
+        );
+
+        for ((template, method), stats) in requests.iter() {
+            for (status, count) in &stats.statuses {
+                let status = status.to_string();
+                let labels = [
+                    ("route", route(template)),
+                    ("method", *method),
+                    ("status", status.as_str()),
+                ];
+
+                encoder.sample("poke_http_requests_total", &labels, count);
+            }
+        }
+
+        encoder.family(
+            "poke_http_request_duration_seconds",
+            "histogram",
+            "Time spent serving HTTP requests, by route and method.",
+        );
+
+        for ((template, method), stats) in requests.iter() {
+            let labels = [("route", route(template)), ("method", *method)];
+
+            encoder.histogram(
+                "poke_http_request_duration_seconds",
+                &labels,
+                &stats.latency.snapshot(),
+            );
+        }
+    }
+
+    fn encode_backends(&self, encoder: &mut Encoder) {
+        let backends = lock(&self.0.backends);
+
+        encoder.family(
+            "poke_repository_requests_total",
+            "counter",
+            "Pokemon lookups completed by a repository backend, by outcome.",
+        );
+
+        for (backend, handle) in backends.iter() {
+            let metrics = handle.snapshot();
+            let outcomes = [
+                ("found", metrics.found),
+                ("not_found", metrics.not_found),
+                ("error", metrics.errors),
+            ];
+
+            for (outcome, count) in &outcomes {
+                let labels = [("backend", *backend), ("outcome", *outcome)];
+                encoder.sample("poke_repository_requests_total", &labels, count);
+            }
+        }
+
+        encoder.family(
+            "poke_repository_in_flight",
+            "gauge",
+            "Pokemon lookups in progress on a repository backend.",
+        );
+
+        for (backend, handle) in backends.iter() {
+            let labels = [("backend", *backend)];
+            let in_flight = handle.snapshot().in_flight;
+
+            encoder.sample("poke_repository_in_flight", &labels, in_flight);
+        }
+
+        encoder.family(
+            "poke_repository_request_duration_seconds",
+            "histogram",
+            "Time spent in Pokemon lookups on a repository backend.",
+        );
+
+        for (backend, handle) in backends.iter() {
+            let labels = [("backend", *backend)];
+
+            encoder.histogram(
+                "poke_repository_request_duration_seconds",
+                &labels,
+                &handle.latency(),
+            );
+        }
+    }
+
+    fn encode_commands(&self, encoder: &mut Encoder) {
+        let commands = lock(&self.0.commands);
+
+        encoder.family(
+            "poke_trainer_commands_total",
+            "counter",
+            "Trainer commands dispatched, by command and outcome (`ok` or the problem code).",
+        );
+
+        for (command, stats) in commands.iter() {
+            for (outcome, count) in &stats.outcomes {
+                let labels = [("command", *command), ("outcome", *outcome)];
+                encoder.sample("poke_trainer_commands_total", &labels, count);
+            }
+        }
+
+        encoder.family(
+            "poke_trainer_command_duration_seconds",
+            "histogram",
+            "Time spent dispatching trainer commands, including appending their events to the event store.",
+        );
+
+        for (command, stats) in commands.iter() {
+            let labels = [("command", *command)];
+
+            encoder.histogram(
+                "poke_trainer_command_duration_seconds",
+                &labels,
+                &stats.latency.snapshot(),
+            );
+        }
+    }
+
+    fn encode_event_store(&self, encoder: &mut Encoder) {
+        encoder.family(
+            "poke_event_store_append_duration_seconds",
+            "histogram",
+            "Time spent appending events to the event store.",
+        );
+
+        encoder.histogram(
+            "poke_event_store_append_duration_seconds",
+            &[],
+            &self.0.appends.snapshot(),
+        );
+    }
+}
+
+/// Event store timing its appends, created by [`Registry::event_store`].
+#[derive(Clone)]
+pub struct MeteredStore<S> {
+    store: S,
+    registry: Registry,
+}
+
+impl<S> Store for MeteredStore<S>
+where
+    S: Store + Send + Sync,
+{
+    type SourceId = S::SourceId;
+    type Offset = S::Offset;
+    type Event = S::Event;
+    type Error = S::Error;
+
+    fn append(
+        &mut self,
+        source_id: Self::SourceId,
+        events: Vec<Self::Event>,
+    ) -> BoxFuture<'_, Result<(), Self::Error>> {
+        let appends = &self.registry.0.appends;
+        let append = self.store.append(source_id, events);
+
+        async move {
+            let start = Instant::now();
+            let result = append.await;
+            appends.observe(start.elapsed());
+            result
+        }
+        .boxed()
+    }
+
+    fn stream(
+        &self,
+        source_id: Self::SourceId,
+        from: Self::Offset,
+    ) -> BoxFuture<'_, Result<BoxStream<'_, Self::Event>, Self::Error>> {
+        self.store.stream(source_id, from)
+    }
+
+    fn remove(&mut self, source_id: Self::SourceId) -> BoxFuture<'_, Result<(), Self::Error>> {
+        self.store.remove(source_id)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn method_label(method: &Method) -> &'static str {
+    const METHODS: [&str; 7] = ["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"];
+
+    METHODS
+        .iter()
+        .find(|known| **known == method.as_str())
+        .copied()
+        .unwrap_or("OTHER")
+}
+
+/// Returns the template of the route matching the path, preferring literal
+/// segments over parameters, e.g. `/pokemons/autocomplete` over `/pokemons/{id}`.
+fn route(path: &str) -> Option<&'static str> {
+    let literals = |route: &&routes::Route| {
+        route
+            .template
+            .split('/')
+            .filter(|segment| !segment.starts_with('{'))
+            .count()
+    };
+
+    routes::ROUTES
+        .iter()
+        .filter(|route| route.matches(path))
+        .max_by_key(literals)
+        .map(|route| route.template)
+}
+
+/// Records the route, status and latency of every request served.
+pub fn track(registry: Registry) -> warp::log::Log<impl Fn(warp::log::Info) + Clone + Send + Sync> {
+    warp::log::custom(move |info| {
+        registry.observe_request(info.method(), info.path(), info.status(), info.elapsed())
+    })
+}
+
+/// `GET /metrics` exposes the metrics of the registry, together with
+/// those of the cache, in the Prometheus text format.
+pub fn metrics<R>(registry: Registry, cache: CacheLayer<R>) -> BoxedFilter<(impl Reply,)>
+where
+    R: pokemon::Repository + Send + Sync + Clone + 'static,
+    R::Error: Send + Sync,
+{
+    routes::METRICS
+        .filter()
+        .and(warp::any().map(move || registry.clone()))
+        .and(warp::any().map(move || cache.clone()))
+        .and_then(get_metrics)
+        .boxed()
+}
+
+async fn get_metrics<R>(
+    registry: Registry,
+    cache: CacheLayer<R>,
+) -> Result<impl Reply, warp::Rejection>
+where
+    R: pokemon::Repository,
+{
+    let mut encoder = Encoder::default();
+
+    registry.encode_requests(&mut encoder);
+    registry.encode_backends(&mut encoder);
+    registry.encode_commands(&mut encoder);
+    registry.encode_event_store(&mut encoder);
+    encode_cache(&mut encoder, &cache).await;
+
+    Ok(warp::reply::with_header(
+        encoder.finish(),
+        "content-type",
+        "text/plain; version=0.0.4",
+    ))
+}
+
+async fn encode_cache<R>(encoder: &mut Encoder, cache: &CacheLayer<R>)
+where
+    R: pokemon::Repository,
+{
+    let stats = cache.stats().await;
+
+    encoder.family(
+        "poke_cache_requests_total",
+        "counter",
+        "Lookups through the cache, by result.",
+    );
+
+    let results = [
+        ("hit", stats.hits),
+        ("negative_hit", stats.negative_hits),
+        ("miss", stats.misses),
+    ];
+
+    for (result, count) in &results {
+        encoder.sample("poke_cache_requests_total", &[("result", *result)], count);
+    }
+
+    // Known missing Pokémons are answered by the cache too.
+    let hits = stats.hits + stats.negative_hits;
+    let ratio = hits as f64 / (hits + stats.misses) as f64;
+
+    encoder.family(
+        "poke_cache_hit_ratio",
+        "gauge",
+        "Ratio of the lookups answered by the cache, NaN before the first lookup.",
+    );
+    encoder.sample("poke_cache_hit_ratio", &[], ratio);
+
+    encoder.family("poke_cache_size", "gauge", "Pokemons in the cache.");
+    encoder.sample("poke_cache_size", &[], stats.size);
+
+    encoder.family(
+        "poke_cache_negative_size",
+        "gauge",
+        "Pokemons known to be missing upstream.",
+    );
+    encoder.sample("poke_cache_negative_size", &[], stats.negative_size);
+
+    encoder.family(
+        "poke_cache_evictions_total",
+        "counter",
+        "Pokemons evicted from the cache.",
+    );
+    encoder.sample("poke_cache_evictions_total", &[], stats.evictions);
+}
+
+/// Writer of the Prometheus text format.
+#[derive(Default)]
+struct Encoder {
+    out: String,
+}
+
+impl Encoder {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        self.out.push_str(&format!(
+            "# HELP {} {}\n# TYPE {} {}\n",
+            name, help, name, kind
+        ));
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: impl Display) {
+        self.out.push_str(name);
+
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(key, value)| format!("{}=\"{}\"", key, escape(value)))
+                .collect();
+
+            self.out.push_str(&format!("{{{}}}", labels.join(",")));
+        }
+
+        self.out.push_str(&format!(" {}\n", value));
+    }
+
+    fn histogram(&mut self, name: &str, labels: &[(&str, &str)], histogram: &HistogramSnapshot) {
+        let bucket = format!("{}_bucket", name);
+
+        for (bound, count) in &histogram.buckets {
+            let bound = bound.to_string();
+            let mut labels = labels.to_vec();
+            labels.push(("le", &bound));
+
+            self.sample(&bucket, &labels, count);
+        }
+
+        let mut labels_inf = labels.to_vec();
+        labels_inf.push(("le", "+Inf"));
+        self.sample(&bucket, &labels_inf, histogram.count);
+
+        self.sample(&format!("{}_sum", name), labels, histogram.sum);
+        self.sample(&format!("{}_count", name), labels, histogram.count);
+    }
+
+    fn finish(self) -> String {
+        self.out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::convert::Infallible;
+
+    use futures::stream::StreamExt;
+
+    use poke_domain::pokemon::Repository;
+    use poke_layer::metrics::MetricsLayer;
+    use poke_layer::RepositoryBuilder;
+    use poke_memory::InMemoryRepository;
+
+    use super::*;
+
+    /// Event store keeping the events of all the sources together.
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<Vec<u32>>>);
+
+    impl Store for Events {
+        type SourceId = String;
+        type Offset = usize;
+        type Event = u32;
+        type Error = Infallible;
+
+        fn append(&mut self, _: String, events: Vec<u32>) -> BoxFuture<'_, Result<(), Infallible>> {
+            lock(&self.0).extend(events);
+            futures::future::ok(()).boxed()
+        }
+
+        fn stream(
+            &self,
+            _: String,
+            from: usize,
+        ) -> BoxFuture<'_, Result<BoxStream<'_, u32>, Infallible>> {
+            let events: Vec<u32> = lock(&self.0).iter().skip(from).copied().collect();
+            futures::future::ok(futures::stream::iter(events).boxed()).boxed()
+        }
+
+        fn remove(&mut self, _: String) -> BoxFuture<'_, Result<(), Infallible>> {
+            lock(&self.0).clear();
+            futures::future::ok(()).boxed()
+        }
+    }
+
+    /// Returns the samples of the exposition, by series, in order.
+    fn samples(body: &str) -> Vec<(String, f64)> {
+        body.lines()
+            .filter(|line| !line.starts_with('#'))
+            .map(|line| {
+                let idx = line.rfind(' ').expect("sample without value");
+                let value = line[idx + 1..].parse().expect("invalid sample value");
+                (line[..idx].to_owned(), value)
+            })
+            .collect()
+    }
+
+    /// Splits `name{a="1",le="0.5"}` into `name`, the `le` label and the other labels.
+    fn split_bucket(series: &str) -> (&str, String, String) {
+        let open = series.find('{').expect("bucket without labels");
+        let labels = series[open + 1..].trim_end_matches('}');
+
+        let mut le = None;
+        let mut others = Vec::new();
+
+        for label in labels.split(',') {
+            match label.strip_prefix("le=") {
+                Some(bound) => le = Some(bound.trim_matches('"').to_owned()),
+                None => others.push(label),
+            }
+        }
+
+        let name = series[..open].trim_end_matches("_bucket");
+        (name, le.expect("bucket without le"), others.join(","))
+    }
+
+    async fn scrape(registry: Registry, cache: CacheLayer<InMemoryRepository>) -> String {
+        let response = warp::test::request()
+            .path("/metrics")
+            .reply(&metrics(registry, cache))
+            .await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+        String::from_utf8(response.body().to_vec()).unwrap()
+    }
+
+    #[test]
+    fn labels_requests_with_the_most_specific_route() {
+        assert_eq!(route("/pokemons/25"), Some("/pokemons/{id}"));
+        assert_eq!(
+            route("/pokemons/autocomplete"),
+            Some("/pokemons/autocomplete")
+        );
+        assert_eq!(
+            route("/pokemons/name/pikachu"),
+            Some("/pokemons/name/{name}")
+        );
+        assert_eq!(route("/pokemons/25/moves"), None);
+        assert_eq!(route("/nope"), None);
+    }
+
+    #[tokio::test]
+    async fn histograms_are_cumulative() {
+        let registry = Registry::new();
+
+        for (path, millis) in &[("/pokemons/25", 3), ("/pokemons/1", 30), ("/nope", 3000)] {
+            let elapsed = Duration::from_millis(*millis);
+            registry.observe_request(&Method::GET, path, StatusCode::OK, elapsed);
+        }
+
+        registry.observe_command("create_trainer", "ok", Duration::from_millis(7));
+        registry.observe_command("create_trainer", "ok", Duration::from_millis(700));
+
+        let layer = MetricsLayer::new();
+        registry.backend("memory", layer.handle());
+
+        let repository = RepositoryBuilder::new()
+            .layer(layer)
+            .build(InMemoryRepository::default());
+        repository.get(25).await.unwrap();
+
+        let mut store = registry.event_store(Events::default());
+        store.append("ash".to_owned(), vec![1, 2]).await.unwrap();
+        store.append("misty".to_owned(), vec![3]).await.unwrap();
+
+        let body = scrape(registry, CacheLayer::from(InMemoryRepository::default())).await;
+        let samples = samples(&body);
+
+        let counts: HashMap<&str, f64> = samples
+            .iter()
+            .filter(|(series, _)| series.split('{').next().unwrap().ends_with("_count"))
+            .map(|(series, value)| (series.as_str(), *value))
+            .collect();
+
+        let mut histograms: BTreeMap<(&str, String), Vec<(String, f64)>> = BTreeMap::new();
+
+        for (series, value) in &samples {
+            if series.split('{').next().unwrap().ends_with("_bucket") {
+                let (name, le, labels) = split_bucket(series);
+                histograms
+                    .entry((name, labels))
+                    .or_default()
+                    .push((le, *value));
+            }
+        }
+
+        for name in &[
+            "poke_http_request_duration_seconds",
+            "poke_repository_request_duration_seconds",
+            "poke_trainer_command_duration_seconds",
+            "poke_event_store_append_duration_seconds",
+        ] {
+            assert!(
+                histograms.keys().any(|(histogram, _)| histogram == name),
+                "{} not exposed",
+                name
+            );
+        }
+
+        for ((name, labels), buckets) in &histograms {
+            let (last, inf) = buckets.last().unwrap();
+            assert_eq!(last, "+Inf", "{}{{{}}} doesn't end with +Inf", name, labels);
+
+            for pair in buckets.windows(2) {
+                let (lower, upper) = (&pair[0], &pair[1]);
+
+                if upper.0 != "+Inf" {
+                    let bounds: (f64, f64) = (lower.0.parse().unwrap(), upper.0.parse().unwrap());
+                    assert!(
+                        bounds.0 < bounds.1,
+                        "{}{{{}}} bounds not sorted",
+                        name,
+                        labels
+                    );
+                }
+
+                assert!(
+                    lower.1 <= upper.1,
+                    "{}{{{}}} buckets not cumulative: {:?}",
+                    name,
+                    labels,
+                    buckets
+                );
+            }
+
+            let count = if labels.is_empty() {
+                format!("{}_count", name)
+            } else {
+                format!("{}_count{{{}}}", name, labels)
+            };
+
+            assert_eq!(counts.get(count.as_str()), Some(inf), "{}", count);
+        }
+
+        let appends = &histograms[&("poke_event_store_append_duration_seconds", String::new())];
+        assert_eq!(appends.last().unwrap().1, 2.0);
+    }
+
+    #[tokio::test]
+    async fn metered_stores_forward_to_the_store() {
+        let events = Events::default();
+        let mut store = Registry::new().event_store(events.clone());
+
+        store.append("ash".to_owned(), vec![1, 2, 3]).await.unwrap();
+
+        let streamed: Vec<u32> = store
+            .stream("ash".to_owned(), 1)
+            .await
+            .unwrap()
+            .collect()
+            .await;
+        assert_eq!(streamed, vec![2, 3]);
+
+        store.remove("ash".to_owned()).await.unwrap();
+        assert!(lock(&events.0).is_empty());
+    }
+}
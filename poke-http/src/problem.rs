@@ -0,0 +1,519 @@
+//! Error responses, as [RFC 7807](https://tools.ietf.org/html/rfc7807)
+//! problem details.
+//!
+//! Handlers reject requests with a [`Problem`], which is rendered by the
+//! [`recover`] handler together with the rejections of warp itself.
+
+use std::convert::Infallible;
+use std::error::Error;
+
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
+
+use warp::http::header::{HeaderValue, CONTENT_TYPE};
+use warp::http::StatusCode;
+use warp::reject::Rejection;
+use warp::Reply;
+
+use poke_domain::pokemon::WriteError;
+use poke_domain::trainer::{TrainerCommandHandlerError, TrainerError};
+use poke_memory::breaker::CircuitOpen;
+
+/// Problem details of a failed request.
+///
+/// Handlers can reject with a problem through `?`, as warp converts
+/// any [`warp::reject::Reject`] into a [`Rejection`].
+///
+/// On top of the standard members, every problem carries a stable `code`,
+/// which clients can rely on to tell errors apart.
+#[derive(Clone, Debug, Serialize)]
+pub struct Problem {
+    #[serde(rename = "type")]
+    typ: String,
+    title: &'static str,
+    #[serde(serialize_with = "serialize_status")]
+    status: StatusCode,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+    code: &'static str,
+    #[serde(flatten)]
+    extensions: Map<String, Value>,
+}
+
+fn serialize_status<S>(status: &StatusCode, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_u16(status.as_u16())
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, code: &'static str, title: &'static str) -> Self {
+        Problem {
+            typ: format!("urn:poke:problem:{}", code),
+            title,
+            status,
+            detail: None,
+            code,
+            extensions: Map::new(),
+        }
+    }
+
+    pub fn with_detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    /// Adds a problem-specific member to the body.
+    pub fn with_extension(mut self, key: &str, value: Value) -> Self {
+        self.extensions.insert(key.to_owned(), value);
+        self
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.code
+    }
+
+    pub fn not_found() -> Self {
+        Problem::new(StatusCode::NOT_FOUND, "not_found", "Resource not found")
+    }
+
+    pub fn bad_request(detail: impl Into<String>) -> Self {
+        Problem::new(
+            StatusCode::BAD_REQUEST,
+            "invalid_request",
+            "Invalid request",
+        )
+        .with_detail(detail)
+    }
+
+    /// Problem for a request body failing validation, listing the invalid fields.
+    pub fn validation(errors: Vec<FieldError>) -> Self {
+        Problem::new(
+            StatusCode::BAD_REQUEST,
+            "validation_failed",
+            "Invalid request body",
+        )
+        .with_extension("errors", serde_json::json!(errors))
+    }
+
+    pub fn unauthorized() -> Self {
+        Problem::new(
+            StatusCode::UNAUTHORIZED,
+            "unauthorized",
+            "Missing or invalid admin token",
+        )
+    }
+
+    pub fn pokemon_not_found(dex_id: u32) -> Self {
+        Problem::new(
+            StatusCode::NOT_FOUND,
+            "pokemon_not_found",
+            "Pokémon not found",
+        )
+        .with_detail(format!("pokemon #{} not found", dex_id))
+    }
+
+    pub fn internal() -> Self {
+        Problem::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "internal_error",
+            "Internal server error",
+        )
+    }
+
+    /// Problem for a failed repository lookup: `503 Service Unavailable`
+    /// if the upstream is known to be unavailable, `502 Bad Gateway` otherwise.
+    pub fn repository<E>(err: &E) -> Self
+    where
+        E: Error + 'static,
+    {
+        log::error!("Error received while calling repository: {}", err);
+
+        if caused_by::<CircuitOpen>(err).is_some() {
+            return Problem::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "upstream_unavailable",
+                "Upstream unavailable",
+            )
+            .with_detail(err.to_string());
+        }
+
+        Problem::new(StatusCode::BAD_GATEWAY, "upstream_error", "Upstream error")
+            .with_detail(err.to_string())
+    }
+
+    pub fn write<E>(err: WriteError<E>) -> Self
+    where
+        E: Error + 'static,
+    {
+        match err {
+            WriteError::AlreadyExists { .. } => Problem::new(
+                StatusCode::CONFLICT,
+                "pokemon_already_exists",
+                "Pokémon already exists",
+            )
+            .with_detail(err.to_string()),
+            WriteError::NotFound { dex_id } => Problem::pokemon_not_found(dex_id),
+            WriteError::RepositoryError(ref inner) => {
+                log::error!("Error received while writing to repository: {}", inner);
+                Problem::internal().with_detail("failed to write pokemon")
+            }
+        }
+    }
+
+    pub fn trainer(err: &TrainerError) -> Self {
+        let problem = match err {
+            TrainerError::AdventureAlreadyStarted { .. } => Problem::new(
+                StatusCode::CONFLICT,
+                "adventure_already_started",
+                "Adventure already started",
+            ),
+            TrainerError::AdventureNotStarted => Problem::new(
+                StatusCode::NOT_FOUND,
+                "adventure_not_started",
+                "Adventure not started",
+            ),
+            TrainerError::EmptyTeamSlot { .. } => {
+                Problem::new(StatusCode::NOT_FOUND, "team_slot_empty", "Team slot empty")
+            }
+        };
+
+        problem.with_detail(err.to_string())
+    }
+
+    /// Problem for a failed trainer command, looking for the error of the
+    /// command handler, with repository error `E`, along the error chain.
+    pub fn command<E>(err: &(dyn Error + 'static)) -> Self
+    where
+        E: Error + 'static,
+    {
+        match caused_by::<TrainerCommandHandlerError<E>>(err) {
+            Some(TrainerCommandHandlerError::NoPokemonsFound) => Problem::new(
+                StatusCode::NOT_FOUND,
+                "pokemon_not_found",
+                "Pokémon not found",
+            )
+            .with_detail(err.to_string()),
+            Some(TrainerCommandHandlerError::InvalidCommand(inner)) => Problem::trainer(inner),
+            Some(TrainerCommandHandlerError::RepositoryError(inner)) => Problem::repository(inner),
+            None => match caused_by::<TrainerError>(err) {
+                Some(inner) => Problem::trainer(inner),
+                None => {
+                    log::error!("Failed to dispatch trainer command: {}", err);
+                    Problem::internal()
+                }
+            },
+        }
+    }
+}
+
+/// Invalid field of a request body.
+#[derive(Clone, Debug, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &'static str, message: String) -> Self {
+        FieldError { field, message }
+    }
+}
+
+/// Returns the first error of type `T` in the chain starting at `err`, if any.
+fn caused_by<'a, T>(err: &'a (dyn Error + 'static)) -> Option<&'a T>
+where
+    T: Error + 'static,
+{
+    let mut current = Some(err);
+
+    while let Some(err) = current {
+        if let Some(cause) = err.downcast_ref::<T>() {
+            return Some(cause);
+        }
+
+        current = err.source();
+    }
+
+    None
+}
+
+impl warp::reject::Reject for Problem {}
+
+impl Reply for Problem {
+    fn into_response(self) -> warp::reply::Response {
+        let mut response = warp::reply::json(&self).into_response();
+
+        *response.status_mut() = self.status;
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/problem+json"),
+        );
+
+        response
+    }
+}
+
+/// Renders the rejected requests as problem details.
+///
+/// Apply it once, to all the routes: recovering only some of them
+/// would keep warp from trying the others.
+pub async fn recover(rejection: Rejection) -> Result<Problem, Infallible> {
+    if let Some(problem) = rejection.find::<Problem>() {
+        return Ok(problem.clone());
+    }
+
+    if rejection.is_not_found() {
+        return Ok(Problem::not_found());
+    }
+
+    if rejection.find::<warp::reject::MethodNotAllowed>().is_some() {
+        return Ok(Problem::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "method_not_allowed",
+            "Method not allowed",
+        ));
+    }
+
+    if let Some(err) = rejection.find::<warp::filters::body::BodyDeserializeError>() {
+        return Ok(Problem::bad_request(err.to_string()));
+    }
+
+    if let Some(err) = rejection.find::<warp::reject::InvalidQuery>() {
+        return Ok(Problem::bad_request(err.to_string()));
+    }
+
+    if let Some(err) = rejection.find::<warp::reject::MissingHeader>() {
+        return Ok(Problem::bad_request(err.to_string()));
+    }
+
+    if let Some(err) = rejection.find::<warp::reject::InvalidHeader>() {
+        return Ok(Problem::bad_request(err.to_string()));
+    }
+
+    if rejection.find::<warp::reject::LengthRequired>().is_some() {
+        return Ok(Problem::new(
+            StatusCode::LENGTH_REQUIRED,
+            "length_required",
+            "Content length required",
+        ));
+    }
+
+    if rejection.find::<warp::reject::PayloadTooLarge>().is_some() {
+        return Ok(Problem::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "payload_too_large",
+            "Payload too large",
+        ));
+    }
+
+    if rejection
+        .find::<warp::reject::UnsupportedMediaType>()
+        .is_some()
+    {
+        return Ok(Problem::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "unsupported_media_type",
+            "Unsupported media type",
+        ));
+    }
+
+    log::error!("Unhandled rejection: {:?}", rejection);
+    Ok(Problem::internal())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::fmt::{Display, Formatter, Result as FmtResult};
+
+    use warp::Filter;
+
+    use poke_memory::breaker::BreakerError;
+
+    /// Error failing on its own, or because of another one.
+    #[derive(Debug)]
+    struct Failure(Option<Box<dyn Error + 'static>>);
+
+    impl Failure {
+        fn caused_by(cause: impl Error + 'static) -> Self {
+            Failure(Some(Box::new(cause)))
+        }
+    }
+
+    impl Error for Failure {
+        fn source(&self) -> Option<&(dyn Error + 'static)> {
+            self.0.as_deref()
+        }
+    }
+
+    impl Display for Failure {
+        fn fmt(&self, f: &mut Formatter) -> FmtResult {
+            write!(f, "failure")
+        }
+    }
+
+    type HandlerError = TrainerCommandHandlerError<Failure>;
+
+    fn status(problem: &Problem) -> (u16, &'static str) {
+        (problem.status.as_u16(), problem.code)
+    }
+
+    #[test]
+    fn maps_the_generic_problems() {
+        assert_eq!(status(&Problem::not_found()), (404, "not_found"));
+        assert_eq!(status(&Problem::bad_request("?")), (400, "invalid_request"));
+        assert_eq!(status(&Problem::unauthorized()), (401, "unauthorized"));
+        assert_eq!(status(&Problem::internal()), (500, "internal_error"));
+        assert_eq!(
+            status(&Problem::pokemon_not_found(25)),
+            (404, "pokemon_not_found")
+        );
+
+        let problem = Problem::validation(vec![FieldError::new("name", "blank".to_owned())]);
+        assert_eq!(status(&problem), (400, "validation_failed"));
+        assert_eq!(
+            problem.extensions["errors"],
+            serde_json::json!([{ "field": "name", "message": "blank" }])
+        );
+    }
+
+    #[test]
+    fn maps_repository_errors() {
+        assert_eq!(
+            status(&Problem::repository(&Failure(None))),
+            (502, "upstream_error")
+        );
+
+        let open = BreakerError::<Failure>::Open(CircuitOpen);
+        assert_eq!(
+            status(&Problem::repository(&open)),
+            (503, "upstream_unavailable")
+        );
+
+        // The open circuit is found anywhere along the error chain.
+        let wrapped = Failure::caused_by(Failure::caused_by(open));
+        assert_eq!(
+            status(&Problem::repository(&wrapped)),
+            (503, "upstream_unavailable")
+        );
+    }
+
+    #[test]
+    fn maps_write_errors() {
+        let problem = Problem::write(WriteError::<Failure>::AlreadyExists { dex_id: 25 });
+        assert_eq!(status(&problem), (409, "pokemon_already_exists"));
+
+        let problem = Problem::write(WriteError::<Failure>::NotFound { dex_id: 25 });
+        assert_eq!(status(&problem), (404, "pokemon_not_found"));
+
+        let problem = Problem::write(WriteError::RepositoryError(Failure(None)));
+        assert_eq!(status(&problem), (500, "internal_error"));
+    }
+
+    #[test]
+    fn maps_trainer_errors() {
+        let problem = Problem::trainer(&TrainerError::AdventureAlreadyStarted {
+            name: "ash".to_owned(),
+        });
+        assert_eq!(status(&problem), (409, "adventure_already_started"));
+
+        let problem = Problem::trainer(&TrainerError::AdventureNotStarted);
+        assert_eq!(status(&problem), (404, "adventure_not_started"));
+
+        let problem = Problem::trainer(&TrainerError::EmptyTeamSlot { slot: 3 });
+        assert_eq!(status(&problem), (404, "team_slot_empty"));
+        assert_eq!(problem.detail.as_deref(), Some("no pokemon in team slot 3"));
+    }
+
+    #[test]
+    fn maps_command_errors_along_the_chain() {
+        let command = |err: &(dyn Error + 'static)| status(&Problem::command::<Failure>(err));
+
+        assert_eq!(
+            command(&HandlerError::NoPokemonsFound),
+            (404, "pokemon_not_found")
+        );
+        assert_eq!(
+            command(&HandlerError::InvalidCommand(
+                TrainerError::AdventureNotStarted
+            )),
+            (404, "adventure_not_started")
+        );
+        assert_eq!(
+            command(&HandlerError::RepositoryError(Failure(None))),
+            (502, "upstream_error")
+        );
+        assert_eq!(
+            command(&HandlerError::RepositoryError(Failure::caused_by(
+                BreakerError::<Failure>::Open(CircuitOpen)
+            ))),
+            (503, "upstream_unavailable")
+        );
+
+        // Errors of the handler are found when wrapped, e.g. by the dispatcher.
+        assert_eq!(
+            command(&Failure::caused_by(HandlerError::InvalidCommand(
+                TrainerError::EmptyTeamSlot { slot: 1 }
+            ))),
+            (404, "team_slot_empty")
+        );
+
+        // Aggregate errors are found even without the handler around them.
+        assert_eq!(
+            command(&Failure::caused_by(TrainerError::AdventureAlreadyStarted {
+                name: "ash".to_owned()
+            })),
+            (409, "adventure_already_started")
+        );
+
+        assert_eq!(command(&Failure(None)), (500, "internal_error"));
+    }
+
+    #[test]
+    fn finds_the_first_cause_of_a_type() {
+        let err = Failure::caused_by(TrainerError::AdventureNotStarted);
+
+        assert!(caused_by::<Failure>(&err).is_some());
+        assert!(caused_by::<TrainerError>(&err).is_some());
+        assert!(caused_by::<CircuitOpen>(&err).is_none());
+    }
+
+    #[test]
+    fn renders_problems_as_problem_details() {
+        let response = Problem::pokemon_not_found(25).into_response();
+
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(response.headers()[CONTENT_TYPE], "application/problem+json");
+    }
+
+    #[tokio::test]
+    async fn recovers_rejections() {
+        let recovered = |rejection| async move { status(&recover(rejection).await.unwrap()) };
+
+        assert_eq!(
+            recovered(warp::reject::custom(Problem::unauthorized())).await,
+            (401, "unauthorized")
+        );
+        assert_eq!(
+            recovered(warp::reject::not_found()).await,
+            (404, "not_found")
+        );
+
+        let filter = warp::post().map(warp::reply).recover(recover);
+        let response = warp::test::request().method("GET").reply(&filter).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let filter = warp::body::json::<serde_json::Value>()
+            .map(|_| warp::reply())
+            .recover(recover);
+        let response = warp::test::request()
+            .method("POST")
+            .body("{")
+            .reply(&filter)
+            .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
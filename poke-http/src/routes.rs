@@ -0,0 +1,303 @@
+//! Routes served by the crate.
+//!
+//! Every filter is built from one of the routes declared here, which
+//! extracts the parameters of its template, and the `openapi` test
+//! checks that `openapi.json` documents exactly [`ROUTES`].
+
+use std::str::FromStr;
+
+use warp::filters::BoxedFilter;
+use warp::path::FullPath;
+use warp::Filter;
+
+/// Route as documented in `openapi.json`, e.g. `GET /pokemons/{id}`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Route {
+    pub method: &'static str,
+    pub template: &'static str,
+}
+
+impl Route {
+    const fn new(method: &'static str, template: &'static str) -> Self {
+        Route { method, template }
+    }
+
+    /// Whether the path matches the template, where each `{param}`
+    /// stands for exactly one non-empty segment.
+    pub fn matches(&self, path: &str) -> bool {
+        self.extract(path).is_some()
+    }
+
+    /// Segments of the path matching the `{param}`s of the template,
+    /// or `None` if the path doesn't match it.
+    fn extract<'a>(&self, path: &'a str) -> Option<Vec<&'a str>> {
+        let mut segments = path.split('/');
+        let mut template = self.template.split('/');
+        let mut params = Vec::new();
+
+        loop {
+            match (template.next(), segments.next()) {
+                (None, None) => return Some(params),
+                (Some(expected), Some(segment)) if expected.starts_with('{') => {
+                    if segment.is_empty() {
+                        return None;
+                    }
+
+                    params.push(segment);
+                }
+                (Some(expected), Some(segment)) if expected == segment => {}
+                _ => return None,
+            }
+        }
+    }
+
+    fn arity(&self) -> usize {
+        self.template
+            .split('/')
+            .filter(|segment| segment.starts_with('{'))
+            .count()
+    }
+
+    /// Accepts the requests to the route, extracting the segments of its
+    /// `{param}`s, whose number is checked against `arity`.
+    ///
+    /// Requests to other paths are rejected as not found, while requests
+    /// to the path with another method as not allowed.
+    fn accept(self, arity: usize) -> BoxedFilter<(Vec<String>,)> {
+        assert_eq!(
+            self.arity(),
+            arity,
+            "{} {} is not a route with {} parameter(s)",
+            self.method,
+            self.template,
+            arity
+        );
+
+        let path = warp::path::full().and_then(move |path: FullPath| async move {
+            match self.extract(path.as_str()) {
+                Some(params) => Ok(params.into_iter().map(str::to_owned).collect::<Vec<_>>()),
+                None => Err(warp::reject::not_found()),
+            }
+        });
+
+        let method = match self.method {
+            "GET" => warp::get().boxed(),
+            "POST" => warp::post().boxed(),
+            "PUT" => warp::put().boxed(),
+            "PATCH" => warp::patch().boxed(),
+            "DELETE" => warp::delete().boxed(),
+            method => panic!("unsupported method {} for {}", method, self.template),
+        };
+
+        path.and(method).boxed()
+    }
+
+    /// Accepts the requests to a route without parameters.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the template has parameters: use [`Route::param`]
+    /// or [`Route::params`] instead.
+    pub fn filter(self) -> BoxedFilter<()> {
+        self.accept(0).map(|_| ()).untuple_one().boxed()
+    }
+
+    /// Accepts the requests to a route with a single parameter, extracting
+    /// it, e.g. the `u32` of `GET_POKEMON.param::<u32>()`.
+    ///
+    /// Requests whose parameter doesn't parse are rejected as not found.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the template doesn't have exactly one parameter.
+    pub fn param<T>(self) -> BoxedFilter<(T,)>
+    where
+        T: FromStr + Send + 'static,
+    {
+        self.accept(1)
+            .and_then(|params: Vec<String>| async move {
+                params[0]
+                    .parse::<T>()
+                    .map_err(|_| warp::reject::not_found())
+            })
+            .boxed()
+    }
+
+    /// Accepts the requests to a route with two parameters, extracting
+    /// them in the order of the template.
+    ///
+    /// Requests whose parameters don't parse are rejected as not found.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the template doesn't have exactly two parameters.
+    pub fn params<A, B>(self) -> BoxedFilter<(A, B)>
+    where
+        A: FromStr + Send + 'static,
+        B: FromStr + Send + 'static,
+    {
+        self.accept(2)
+            .and_then(|params: Vec<String>| async move {
+                match (params[0].parse::<A>(), params[1].parse::<B>()) {
+                    (Ok(a), Ok(b)) => Ok((a, b)),
+                    _ => Err(warp::reject::not_found()),
+                }
+            })
+            .untuple_one()
+            .boxed()
+    }
+}
+
+pub const GET_POKEMON: Route = Route::new("GET", "/pokemons/{id}");
+pub const GET_POKEMON_BY_NAME: Route = Route::new("GET", "/pokemons/name/{name}");
+pub const AUTOCOMPLETE: Route = Route::new("GET", "/pokemons/autocomplete");
+
+pub const CREATE_TRAINER: Route = Route::new("POST", "/trainers");
+pub const ADD_TEAM_MEMBER: Route = Route::new("POST", "/trainers/{name}/team");
+pub const REMOVE_TEAM_MEMBER: Route = Route::new("DELETE", "/trainers/{name}/team/{slot}");
+pub const START_ADVENTURE: Route = Route::new("POST", "/pokemons/adventure/start/name/{name}");
+pub const ADD_POKEMON: Route =
+    Route::new("POST", "/pokemons/adventure/{name}/team/add/{pokemon_id}");
+
+pub const CACHE_STATS: Route = Route::new("GET", "/cache/stats");
+pub const CACHE_WARMUP: Route = Route::new("GET", "/cache/warmup");
+
+pub const INSERT_POKEMON: Route = Route::new("POST", "/admin/pokemons");
+pub const UPDATE_POKEMON: Route = Route::new("PUT", "/admin/pokemons/{id}");
+pub const DELETE_POKEMON: Route = Route::new("DELETE", "/admin/pokemons/{id}");
+
+pub const LIST_PATCHES: Route = Route::new("GET", "/patches");
+pub const PATCH_DIFF: Route = Route::new("GET", "/patches/{id}/diff");
+pub const RELOAD_PATCHES: Route = Route::new("POST", "/admin/patches/reload");
+
+pub const UPSTREAM_HEALTH: Route = Route::new("GET", "/health/upstream");
+pub const UPSTREAM_METRICS: Route = Route::new("GET", "/upstream/metrics");
+pub const FALLBACK_STATS: Route = Route::new("GET", "/fallback/stats");
+
+pub const HEALTHZ: Route = Route::new("GET", "/healthz");
+pub const READYZ: Route = Route::new("GET", "/readyz");
+pub const METRICS: Route = Route::new("GET", "/metrics");
+
+pub const OPENAPI: Route = Route::new("GET", "/openapi.json");
+pub const DOCS: Route = Route::new("GET", "/docs");
+pub const DOCS_ASSET: Route = Route::new("GET", "/docs/{asset}");
+
+/// All the routes served by the crate.
+pub const ROUTES: &[Route] = &[
+    GET_POKEMON,
+    GET_POKEMON_BY_NAME,
+    AUTOCOMPLETE,
+    CREATE_TRAINER,
+    ADD_TEAM_MEMBER,
+    REMOVE_TEAM_MEMBER,
+    START_ADVENTURE,
+    ADD_POKEMON,
+    CACHE_STATS,
+    CACHE_WARMUP,
+    INSERT_POKEMON,
+    UPDATE_POKEMON,
+    DELETE_POKEMON,
+    LIST_PATCHES,
+    PATCH_DIFF,
+    RELOAD_PATCHES,
+    UPSTREAM_HEALTH,
+    UPSTREAM_METRICS,
+    FALLBACK_STATS,
+    HEALTHZ,
+    READYZ,
+    METRICS,
+    OPENAPI,
+    DOCS,
+    DOCS_ASSET,
+];
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use warp::http::StatusCode;
+
+    use super::*;
+
+    #[test]
+    fn matches_the_paths_of_the_template() {
+        assert!(GET_POKEMON.matches("/pokemons/25"));
+        assert!(GET_POKEMON.matches("/pokemons/pikachu"));
+        assert!(AUTOCOMPLETE.matches("/pokemons/autocomplete"));
+
+        assert!(!GET_POKEMON.matches("/pokemons/"));
+        assert!(!GET_POKEMON.matches("/pokemons/25/"));
+        assert!(!GET_POKEMON.matches("/pokemons"));
+        assert!(!GET_POKEMON.matches("/pokemons/name/pikachu"));
+        assert!(!PATCH_DIFF.matches("/patches/25/stats"));
+    }
+
+    #[test]
+    fn routes_are_declared_once() {
+        let mut seen = HashSet::new();
+
+        for route in ROUTES {
+            assert!(seen.insert(route), "{:?} declared twice", route);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_other_paths_and_methods() {
+        let filter = GET_POKEMON
+            .param::<u32>()
+            .map(|id: u32| id.to_string())
+            .recover(crate::problem::recover);
+
+        let res = warp::test::request()
+            .path("/pokemons/25")
+            .reply(&filter)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "25");
+
+        let res = warp::test::request()
+            .method("DELETE")
+            .path("/pokemons/25")
+            .reply(&filter)
+            .await;
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let res = warp::test::request().path("/trainers").reply(&filter).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn extracts_the_parameters_of_the_template() {
+        let filter = REMOVE_TEAM_MEMBER
+            .params::<String, usize>()
+            .map(|name: String, slot: usize| format!("{}:{}", name, slot))
+            .recover(crate::problem::recover);
+
+        let res = warp::test::request()
+            .method("DELETE")
+            .path("/trainers/ash/team/2")
+            .reply(&filter)
+            .await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(res.body(), "ash:2");
+
+        let res = warp::test::request()
+            .method("DELETE")
+            .path("/trainers/ash/team/first")
+            .reply(&filter)
+            .await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a route with 0 parameter(s)")]
+    fn refuses_filters_ignoring_parameters() {
+        GET_POKEMON.filter();
+    }
+
+    #[test]
+    #[should_panic(expected = "is not a route with 1 parameter(s)")]
+    fn refuses_filters_with_extra_parameters() {
+        CACHE_STATS.param::<u32>();
+    }
+}
@@ -0,0 +1,466 @@
+//! The `/trainers` resource, and the deprecated verb-shaped routes it replaces.
+
+use std::time::Instant;
+
+use serde::Deserialize;
+
+use warp::filters::BoxedFilter;
+use warp::http::StatusCode;
+use warp::{Filter, Reply};
+
+use eventually::command::{Dispatcher, Handler};
+use eventually::optional::AsAggregate as OptionalAggregate;
+use eventually::versioned::AsAggregate as VersionedAggregate;
+
+use poke_domain::trainer::{Profile, Sex, Trainer, TrainerCommand, TrainerCommandHandlerError};
+
+use crate::metrics::Registry;
+use crate::problem::{FieldError, Problem};
+use crate::routes;
+
+const MAX_NAME_LENGTH: usize = 32;
+const MAX_HOMETOWN_LENGTH: usize = 64;
+const MAX_AGE: u8 = 120;
+
+#[derive(Deserialize)]
+struct NewTrainer {
+    name: String,
+    sex: Sex,
+    age: Option<u8>,
+    hometown: Option<String>,
+}
+
+impl NewTrainer {
+    /// Trainer started through the deprecated route, knowing only its name.
+    fn named(name: String) -> Self {
+        NewTrainer {
+            name,
+            sex: Sex::Male,
+            age: None,
+            hometown: None,
+        }
+    }
+
+    fn into_command(self) -> TrainerCommand {
+        TrainerCommand::StartAdventure {
+            name: self.name,
+            sex: self.sex,
+            profile: Profile {
+                age: self.age,
+                hometown: self.hometown,
+            },
+        }
+    }
+
+    fn validate(&self) -> Vec<FieldError> {
+        let mut errors = Vec::new();
+
+        let valid_name = self
+            .name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+
+        if self.name.is_empty() || self.name.len() > MAX_NAME_LENGTH || !valid_name {
+            errors.push(FieldError::new(
+                "name",
+                format!(
+                    "must be 1 to {} letters, digits, '-' or '_'",
+                    MAX_NAME_LENGTH
+                ),
+            ));
+        }
+
+        if let Some(age) = self.age {
+            if age == 0 || age > MAX_AGE {
+                errors.push(FieldError::new(
+                    "age",
+                    format!("must be between 1 and {}", MAX_AGE),
+                ));
+            }
+        }
+
+        if let Some(ref hometown) = self.hometown {
+            if hometown.trim().is_empty() || hometown.chars().count() > MAX_HOMETOWN_LENGTH {
+                errors.push(FieldError::new(
+                    "hometown",
+                    format!("must be 1 to {} characters", MAX_HOMETOWN_LENGTH),
+                ));
+            }
+        }
+
+        errors
+    }
+}
+
+#[derive(Deserialize)]
+struct NewTeamMember {
+    pokemon_id: u32,
+}
+
+impl NewTeamMember {
+    fn validate(&self) -> Vec<FieldError> {
+        if self.pokemon_id == 0 {
+            return vec![FieldError::new(
+                "pokemon_id",
+                "must be a Pokédex id".to_owned(),
+            )];
+        }
+
+        Vec::new()
+    }
+}
+
+pub(crate) fn routes<D, E>(dispatcher: D, metrics: Registry) -> BoxedFilter<(impl Reply,)>
+where
+    D: Dispatcher + Send + Sync + Clone + 'static,
+    <D as Dispatcher>::CommandHandler: Handler<
+        Command = TrainerCommand,
+        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
+        Error = TrainerCommandHandlerError<E>,
+    >,
+    <D as Dispatcher>::Error: std::error::Error + 'static,
+    E: std::error::Error + 'static,
+{
+    let create_trainer = routes::CREATE_TRAINER
+        .filter()
+        .and(warp::body::json())
+        .and(with_dispatcher(dispatcher.clone()))
+        .and(with_metrics(metrics.clone()))
+        .and_then(create_trainer);
+
+    let add_team_member = routes::ADD_TEAM_MEMBER
+        .param::<String>()
+        .and(warp::body::json())
+        .and(with_dispatcher(dispatcher.clone()))
+        .and(with_metrics(metrics.clone()))
+        .and_then(add_team_member);
+
+    // Team slots start from 0, in the order the Pokémons were added.
+    let remove_team_member = routes::REMOVE_TEAM_MEMBER
+        .params::<String, usize>()
+        .and(with_dispatcher(dispatcher.clone()))
+        .and(with_metrics(metrics.clone()))
+        .and_then(remove_team_member);
+
+    let start_adventure = routes::START_ADVENTURE
+        .param::<String>()
+        .and(with_dispatcher(dispatcher.clone()))
+        .and(with_metrics(metrics.clone()))
+        .and_then(start_adventure_trainer);
+
+    let add_pokemon = routes::ADD_POKEMON
+        .params::<String, u32>()
+        .and(with_dispatcher(dispatcher))
+        .and(with_metrics(metrics))
+        .and_then(add_pokemon_to_team);
+
+    create_trainer
+        .or(add_team_member)
+        .or(remove_team_member)
+        .or(start_adventure)
+        .or(add_pokemon)
+        .boxed()
+}
+
+async fn dispatch<D, E>(
+    mut dispatcher: D,
+    metrics: Registry,
+    command: TrainerCommand,
+) -> Result<Trainer, warp::Rejection>
+where
+    D: Dispatcher,
+    <D as Dispatcher>::CommandHandler: Handler<
+        Command = TrainerCommand,
+        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
+        Error = TrainerCommandHandlerError<E>,
+    >,
+    <D as Dispatcher>::Error: std::error::Error + 'static,
+    E: std::error::Error + 'static,
+{
+    let name = command_name(&command);
+    let start = Instant::now();
+
+    let result = dispatcher
+        .dispatch(command)
+        .await
+        .map_err(|err| Problem::command::<E>(&err));
+
+    let outcome = match result {
+        Ok(_) => "ok",
+        Err(ref problem) => problem.code(),
+    };
+
+    metrics.observe_command(name, outcome, start.elapsed());
+
+    let result = result?;
+
+    log::debug!("Returned state: {:?}", result);
+
+    // Every trainer command either fails or leaves a trainer behind.
+    result.take().ok_or_else(|| Problem::internal().into())
+}
+
+async fn create_trainer<D, E>(
+    body: NewTrainer,
+    dispatcher: D,
+    metrics: Registry,
+) -> Result<impl Reply, warp::Rejection>
+where
+    D: Dispatcher,
+    <D as Dispatcher>::CommandHandler: Handler<
+        Command = TrainerCommand,
+        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
+        Error = TrainerCommandHandlerError<E>,
+    >,
+    <D as Dispatcher>::Error: std::error::Error + 'static,
+    E: std::error::Error + 'static,
+{
+    let errors = body.validate();
+    if !errors.is_empty() {
+        return Err(Problem::validation(errors).into());
+    }
+
+    let location = format!("/trainers/{}", body.name);
+    let trainer = dispatch(dispatcher, metrics, body.into_command()).await?;
+
+    Ok(warp::reply::with_header(
+        warp::reply::with_status(warp::reply::json(&trainer), StatusCode::CREATED),
+        "location",
+        location,
+    ))
+}
+
+async fn add_team_member<D, E>(
+    name: String,
+    body: NewTeamMember,
+    dispatcher: D,
+    metrics: Registry,
+) -> Result<warp::reply::Json, warp::Rejection>
+where
+    D: Dispatcher,
+    <D as Dispatcher>::CommandHandler: Handler<
+        Command = TrainerCommand,
+        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
+        Error = TrainerCommandHandlerError<E>,
+    >,
+    <D as Dispatcher>::Error: std::error::Error + 'static,
+    E: std::error::Error + 'static,
+{
+    let errors = body.validate();
+    if !errors.is_empty() {
+        return Err(Problem::validation(errors).into());
+    }
+
+    let command = TrainerCommand::AddPokemonToTeam {
+        name,
+        pokemon_id: body.pokemon_id,
+    };
+
+    Ok(warp::reply::json(
+        &dispatch(dispatcher, metrics, command).await?,
+    ))
+}
+
+async fn remove_team_member<D, E>(
+    name: String,
+    slot: usize,
+    dispatcher: D,
+    metrics: Registry,
+) -> Result<warp::reply::Json, warp::Rejection>
+where
+    D: Dispatcher,
+    <D as Dispatcher>::CommandHandler: Handler<
+        Command = TrainerCommand,
+        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
+        Error = TrainerCommandHandlerError<E>,
+    >,
+    <D as Dispatcher>::Error: std::error::Error + 'static,
+    E: std::error::Error + 'static,
+{
+    let command = TrainerCommand::RemovePokemonFromTeam { name, slot };
+
+    Ok(warp::reply::json(
+        &dispatch(dispatcher, metrics, command).await?,
+    ))
+}
+
+/// Marks the reply of a deprecated route, pointing to the route replacing it.
+fn deprecated(reply: impl Reply, successor: String) -> impl Reply {
+    let reply = warp::reply::with_header(reply, "deprecation", "true");
+    let link = format!("<{}>; rel=\"successor-version\"", successor);
+
+    warp::reply::with_header(reply, "link", link)
+}
+
+/// Deprecated: use `POST /trainers` instead.
+async fn start_adventure_trainer<D, E>(
+    name: String,
+    dispatcher: D,
+    metrics: Registry,
+) -> Result<impl Reply, warp::Rejection>
+where
+    D: Dispatcher,
+    <D as Dispatcher>::CommandHandler: Handler<
+        Command = TrainerCommand,
+        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
+        Error = TrainerCommandHandlerError<E>,
+    >,
+    <D as Dispatcher>::Error: std::error::Error + 'static,
+    E: std::error::Error + 'static,
+{
+    // Same validation of `POST /trainers`, not to let through
+    // the names the new route would refuse.
+    let body = NewTrainer::named(name);
+
+    let errors = body.validate();
+    if !errors.is_empty() {
+        return Err(Problem::validation(errors).into());
+    }
+
+    let trainer = dispatch(dispatcher, metrics, body.into_command()).await?;
+
+    Ok(deprecated(
+        warp::reply::json(&trainer),
+        "/trainers".to_owned(),
+    ))
+}
+
+/// Deprecated: use `POST /trainers/{name}/team` instead.
+async fn add_pokemon_to_team<D, E>(
+    name: String,
+    pokemon_id: u32,
+    dispatcher: D,
+    metrics: Registry,
+) -> Result<impl Reply, warp::Rejection>
+where
+    D: Dispatcher,
+    <D as Dispatcher>::CommandHandler: Handler<
+        Command = TrainerCommand,
+        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
+        Error = TrainerCommandHandlerError<E>,
+    >,
+    <D as Dispatcher>::Error: std::error::Error + 'static,
+    E: std::error::Error + 'static,
+{
+    let successor = format!("/trainers/{}/team", name);
+    let command = TrainerCommand::AddPokemonToTeam { name, pokemon_id };
+
+    let trainer = dispatch(dispatcher, metrics, command).await?;
+
+    Ok(deprecated(warp::reply::json(&trainer), successor))
+}
+
+fn command_name(command: &TrainerCommand) -> &'static str {
+    match command {
+        TrainerCommand::StartAdventure { .. } => "start_adventure",
+        TrainerCommand::AddPokemonToTeam { .. } => "add_pokemon_to_team",
+        TrainerCommand::RemovePokemonFromTeam { .. } => "remove_pokemon_from_team",
+    }
+}
+
+fn with_metrics(
+    metrics: Registry,
+) -> impl Filter<Extract = (Registry,), Error = std::convert::Infallible> + Clone {
+    warp::any().map(move || metrics.clone())
+}
+
+fn with_dispatcher<D>(
+    dispatcher: D,
+) -> impl Filter<Extract = (D,), Error = std::convert::Infallible> + Clone
+where
+    D: Dispatcher + Send + Sync + Clone,
+    <D as Dispatcher>::CommandHandler: Handler<
+        Command = TrainerCommand,
+        Aggregate = VersionedAggregate<OptionalAggregate<Trainer>>,
+    >,
+{
+    warp::any().map(move || dispatcher.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+
+    use poke_layer::fault::{FaultConfig, FaultError};
+    use poke_layer::{FaultLayer, Layer};
+
+    use super::*;
+
+    fn fields(trainer: &NewTrainer) -> Vec<&'static str> {
+        trainer.validate().iter().map(|error| error.field).collect()
+    }
+
+    #[test]
+    fn validates_new_trainers() {
+        let trainer = NewTrainer {
+            name: "ash ketchum".to_owned(),
+            sex: Sex::Male,
+            age: Some(0),
+            hometown: Some(" ".to_owned()),
+        };
+        assert_eq!(fields(&trainer), vec!["name", "age", "hometown"]);
+
+        let trainer = NewTrainer {
+            name: "ash-ketchum".to_owned(),
+            age: Some(10),
+            hometown: Some("Pallet Town".to_owned()),
+            ..trainer
+        };
+        assert!(fields(&trainer).is_empty());
+    }
+
+    #[test]
+    fn validates_trainers_of_the_deprecated_route() {
+        assert!(fields(&NewTrainer::named("ash".to_owned())).is_empty());
+        assert_eq!(fields(&NewTrainer::named(String::new())), vec!["name"]);
+        assert_eq!(
+            fields(&NewTrainer::named("ash/ketchum".to_owned())),
+            vec!["name"]
+        );
+        assert_eq!(fields(&NewTrainer::named("a".repeat(33))), vec!["name"]);
+    }
+
+    /// Adds Pikachu to the team of a trainer, through a repository injecting the faults.
+    async fn add_pikachu(faults: FaultConfig) -> (StatusCode, &'static str) {
+        use eventually::optional::{Aggregate, CommandHandler};
+        use poke_domain::trainer::{TrainerCommandHandler, TrainerEvent};
+
+        let repository = FaultLayer::new(faults).layer(poke_memory::InMemoryRepository::default());
+        let handler = TrainerCommandHandler::new(repository);
+
+        let trainer = Trainer::apply_first(TrainerEvent::AdventureStarted {
+            name: "ash".to_owned(),
+            sex: Sex::Male,
+            profile: Profile::default(),
+        })
+        .unwrap();
+
+        let command = TrainerCommand::AddPokemonToTeam {
+            name: "ash".to_owned(),
+            pokemon_id: 25,
+        };
+
+        let err = handler.handle_next(&trainer, command).await.err().unwrap();
+        let problem = Problem::command::<FaultError<Infallible>>(&err);
+        let code = problem.code();
+
+        (problem.into_response().status(), code)
+    }
+
+    #[tokio::test]
+    async fn maps_the_upstream_faults_of_commands() {
+        let problem = add_pikachu(FaultConfig {
+            error_rate: 1.0,
+            ..FaultConfig::default()
+        })
+        .await;
+        assert_eq!(problem, (StatusCode::BAD_GATEWAY, "upstream_error"));
+
+        let problem = add_pikachu(FaultConfig {
+            not_found_rate: 1.0,
+            ..FaultConfig::default()
+        })
+        .await;
+        assert_eq!(problem, (StatusCode::NOT_FOUND, "pokemon_not_found"));
+    }
+}
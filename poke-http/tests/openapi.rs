@@ -0,0 +1,64 @@
+//! Checks that `openapi.json` documents exactly the routes declared in
+//! [`poke_http::routes::ROUTES`], from which every filter is built.
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use poke_http::routes::ROUTES;
+
+const METHODS: &[&str] = &["get", "post", "put", "patch", "delete"];
+
+/// Route as `METHOD /path/{param}`.
+type Route = String;
+
+fn routes_in_code() -> BTreeSet<Route> {
+    ROUTES
+        .iter()
+        .map(|route| format!("{} {}", route.method, route.template))
+        .collect()
+}
+
+fn routes_in_spec() -> BTreeSet<Route> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("openapi.json");
+    let spec: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(path).expect("failed to read openapi.json"))
+            .expect("openapi.json is not valid json");
+
+    let paths = spec["paths"]
+        .as_object()
+        .expect("openapi.json has no paths");
+
+    let mut routes = BTreeSet::new();
+
+    for (path, operations) in paths {
+        for method in METHODS {
+            if operations.get(*method).is_some() {
+                routes.insert(format!("{} {}", method.to_uppercase(), path));
+            }
+        }
+    }
+
+    routes
+}
+
+#[test]
+fn openapi_covers_every_route() {
+    let code = routes_in_code();
+    let spec = routes_in_spec();
+
+    let undocumented: Vec<_> = code.difference(&spec).collect();
+    let stale: Vec<_> = spec.difference(&code).collect();
+
+    assert!(
+        undocumented.is_empty(),
+        "routes missing from openapi.json: {:?}",
+        undocumented
+    );
+
+    assert!(
+        stale.is_empty(),
+        "routes in openapi.json not declared in the code: {:?}",
+        stale
+    );
+}
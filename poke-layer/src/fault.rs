@@ -0,0 +1,445 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use poke_domain::pokemon::{self, Pokemon, Source};
+
+use crate::Layer;
+
+/// Faults to inject into the lookups, for resilience testing.
+///
+/// Rates are the probabilities of each fault, between `0.0` and `1.0`:
+/// at most one fault is injected into a lookup, so the rates sum up
+/// to at most `1.0`, e.g. an error rate of `0.3` and a not found rate
+/// of `0.2` fail 30% of the lookups and find nothing in 20% of them.
+#[derive(Clone, Debug, Default)]
+pub struct FaultConfig {
+    /// Seed of the random number generator, so that runs can be reproduced.
+    /// The sequence of faults depends on the order of the lookups too.
+    pub seed: u64,
+
+    /// Latency added to every lookup.
+    pub latency: Duration,
+
+    /// Maximum random latency added on top of `latency`.
+    pub jitter: Duration,
+
+    /// Rate of lookups failing with [`FaultError::Injected`].
+    pub error_rate: f64,
+
+    /// Rate of lookups returning no Pokémon.
+    pub not_found_rate: f64,
+
+    /// Rate of lookups never completing.
+    pub hang_rate: f64,
+}
+
+#[derive(Debug)]
+enum Fault {
+    Hang,
+    Error,
+    NotFound,
+}
+
+struct Injector {
+    config: FaultConfig,
+    rng: Mutex<StdRng>,
+}
+
+impl Injector {
+    /// Draws the latency and the fault, if any, for the next lookup.
+    fn draw(&self) -> (Duration, Option<Fault>) {
+        let config = &self.config;
+        let mut rng = self.rng.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let jitter = match config.jitter.as_micros() as u64 {
+            0 => Duration::default(),
+            max => Duration::from_micros(rng.gen_range(0, max + 1)),
+        };
+
+        // A single draw, compared against the cumulative rates, so that
+        // every fault is injected at its own rate.
+        let draw = rng.gen::<f64>();
+        let hang = config.hang_rate;
+        let error = hang + config.error_rate;
+        let not_found = error + config.not_found_rate;
+
+        let fault = if draw < hang {
+            Some(Fault::Hang)
+        } else if draw < error {
+            Some(Fault::Error)
+        } else if draw < not_found {
+            Some(Fault::NotFound)
+        } else {
+            None
+        };
+
+        (config.latency + jitter, fault)
+    }
+}
+
+/// Injects latency, errors, not-found responses and hangs into the lookups,
+/// to test how the rest of the stack behaves under upstream failures.
+///
+/// A disabled layer leaves the lookups untouched.
+#[derive(Clone)]
+pub struct FaultLayer {
+    injector: Option<Arc<Injector>>,
+}
+
+impl FaultLayer {
+    /// # Panics
+    ///
+    /// Panics if any of the rates is not between `0.0` and `1.0`,
+    /// or if they sum up to more than `1.0`.
+    pub fn new(config: FaultConfig) -> Self {
+        let rates = [config.error_rate, config.not_found_rate, config.hang_rate];
+
+        for rate in &rates {
+            assert!(
+                (0.0..=1.0).contains(rate),
+                "fault rate {} is not between 0.0 and 1.0",
+                rate
+            );
+        }
+
+        // Tolerates the rounding of rates meant to sum up to 1.0, e.g. 0.7 + 0.2 + 0.1.
+        let total: f64 = rates.iter().sum();
+        assert!(
+            total <= 1.0 + 1e-9,
+            "fault rates sum up to {}, more than 1.0",
+            total
+        );
+
+        FaultLayer {
+            injector: Some(Arc::new(Injector {
+                rng: Mutex::new(StdRng::seed_from_u64(config.seed)),
+                config,
+            })),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        FaultLayer { injector: None }
+    }
+}
+
+impl<R> Layer<R> for FaultLayer {
+    type Repository = FaultInjection<R>;
+
+    fn layer(&self, inner: R) -> FaultInjection<R> {
+        FaultInjection {
+            inner,
+            injector: self.injector.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct FaultInjection<R> {
+    inner: R,
+    injector: Option<Arc<Injector>>,
+}
+
+impl<R> FaultInjection<R> {
+    /// Injects the latency, and returns the fault to inject in place
+    /// of the lookup result, if any. Never completes on hangs.
+    async fn inject(&self) -> Option<Fault> {
+        let injector = self.injector.as_ref()?;
+        let (latency, fault) = injector.draw();
+
+        if let Some(ref fault) = fault {
+            log::debug!("Injecting {:?} fault after {:?}", fault, latency);
+        }
+
+        delay(latency, matches!(fault, Some(Fault::Hang))).await;
+        fault
+    }
+}
+
+/// Waits for the injected latency, or forever on hangs.
+async fn delay(latency: Duration, hang: bool) {
+    if latency > Duration::default() {
+        tokio::time::delay_for(latency).await;
+    }
+
+    if hang {
+        futures::future::pending::<()>().await;
+    }
+}
+
+impl<R> pokemon::Repository for FaultInjection<R>
+where
+    R: pokemon::Repository + Send + Sync,
+    R::Error: Send + 'static,
+{
+    type Error = FaultError<R::Error>;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        match self.inject().await {
+            None | Some(Fault::Hang) => (),
+            Some(Fault::Error) => return Err(FaultError::Injected),
+            Some(Fault::NotFound) => return Ok(None),
+        }
+
+        self.inner.get_sourced(num).await.map_err(FaultError::Inner)
+    }
+
+    /// Faults are drawn for every lookup of the batch, which is delayed
+    /// by the longest latency drawn, and hangs if any of the lookups does.
+    /// The lookups without faults are forwarded as a batch of their own.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        let injector = match self.injector {
+            Some(ref injector) => injector,
+            None => {
+                return self
+                    .inner
+                    .get_many(ids)
+                    .await
+                    .into_iter()
+                    .map(|result| result.map_err(FaultError::Inner))
+                    .collect()
+            }
+        };
+
+        let draws: Vec<_> = ids.iter().map(|_| injector.draw()).collect();
+        let latency = draws
+            .iter()
+            .map(|(latency, _)| *latency)
+            .max()
+            .unwrap_or_default();
+        let faults = draws.iter().filter(|(_, fault)| fault.is_some()).count();
+
+        if faults > 0 {
+            log::debug!(
+                "Injecting {} faults into a batch of {} after {:?}",
+                faults,
+                ids.len(),
+                latency
+            );
+        }
+
+        let hang = draws
+            .iter()
+            .any(|(_, fault)| matches!(fault, Some(Fault::Hang)));
+        delay(latency, hang).await;
+
+        let forwarded: Vec<u32> = ids
+            .iter()
+            .zip(&draws)
+            .filter(|(_, (_, fault))| fault.is_none())
+            .map(|(num, _)| *num)
+            .collect();
+
+        let mut results = match forwarded.len() {
+            0 => Vec::new(),
+            _ => self.inner.get_many(&forwarded).await,
+        }
+        .into_iter();
+
+        draws
+            .into_iter()
+            .map(|(_, fault)| match fault {
+                None => results
+                    .next()
+                    .expect("missing result for a forwarded lookup")
+                    .map_err(FaultError::Inner),
+                Some(Fault::Error) => Err(FaultError::Injected),
+                Some(Fault::NotFound) => Ok(None),
+                Some(Fault::Hang) => unreachable!("hanging batches never complete"),
+            })
+            .collect()
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        match self.inject().await {
+            None | Some(Fault::Hang) | Some(Fault::NotFound) => (),
+            Some(Fault::Error) => return Err(FaultError::Injected),
+        }
+
+        self.inner.probe().await.map_err(FaultError::Inner)
+    }
+}
+
+impl<R> pokemon::Catalogue for FaultInjection<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+    R::Error: 'static,
+{
+    type Error = FaultError<R::Error>;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        match self.inject().await {
+            None | Some(Fault::Hang) => (),
+            Some(Fault::Error) => return Err(FaultError::Injected),
+            Some(Fault::NotFound) => return Ok(Vec::new()),
+        }
+
+        self.inner.list().await.map_err(FaultError::Inner)
+    }
+}
+
+#[derive(Debug)]
+pub enum FaultError<E> {
+    /// Failure injected on purpose.
+    Injected,
+    Inner(E),
+}
+
+impl<E> std::error::Error for FaultError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FaultError::Injected => None,
+            FaultError::Inner(inner) => Some(inner),
+        }
+    }
+}
+
+impl<E> Display for FaultError<E>
+where
+    E: std::error::Error,
+{
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            FaultError::Injected => write!(f, "injected fault"),
+            FaultError::Inner(inner) => Display::fmt(inner, f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poke_domain::pokemon::Repository;
+
+    use super::*;
+    use crate::testing::Stub;
+
+    fn config(error_rate: f64, not_found_rate: f64) -> FaultConfig {
+        FaultConfig {
+            seed: 42,
+            error_rate,
+            not_found_rate,
+            ..FaultConfig::default()
+        }
+    }
+
+    /// Outcomes of the lookups of the first Pokémons: whether they were found, or `None` on errors.
+    async fn outcomes<R: Repository + Sync>(repository: &R, lookups: u32) -> Vec<Option<bool>> {
+        let mut outcomes = Vec::new();
+
+        for num in 1..=lookups {
+            outcomes.push(repository.get(num).await.ok().map(|found| found.is_some()));
+        }
+
+        outcomes
+    }
+
+    #[tokio::test]
+    async fn same_seeds_inject_the_same_faults() {
+        let faults = |seed| {
+            FaultLayer::new(FaultConfig {
+                seed,
+                ..config(0.3, 0.3)
+            })
+            .layer(Stub::default())
+        };
+
+        let first = outcomes(&faults(7), 100).await;
+        let second = outcomes(&faults(7), 100).await;
+        let other = outcomes(&faults(8), 100).await;
+
+        assert_eq!(first, second);
+        assert_ne!(first, other);
+    }
+
+    #[test]
+    fn injects_every_fault_at_its_rate() {
+        let injector = Injector {
+            config: config(0.3, 0.2),
+            rng: Mutex::new(StdRng::seed_from_u64(42)),
+        };
+
+        let (mut errors, mut not_found) = (0, 0);
+
+        for _ in 0..10_000 {
+            match injector.draw().1 {
+                Some(Fault::Error) => errors += 1,
+                Some(Fault::NotFound) => not_found += 1,
+                Some(Fault::Hang) => panic!("hang injected at a zero rate"),
+                None => (),
+            }
+        }
+
+        assert!((2800..3200).contains(&errors), "errors: {}", errors);
+        assert!(
+            (1800..2200).contains(&not_found),
+            "not found: {}",
+            not_found
+        );
+    }
+
+    #[tokio::test]
+    async fn injected_faults_replace_the_lookups() {
+        let upstream = Stub::default();
+
+        let failing = FaultLayer::new(config(1.0, 0.0)).layer(upstream.clone());
+        assert!(matches!(failing.get(25).await, Err(FaultError::Injected)));
+
+        let missing = FaultLayer::new(config(0.0, 1.0)).layer(upstream.clone());
+        assert!(missing.get(25).await.unwrap().is_none());
+
+        assert_eq!(upstream.calls(), 0);
+
+        let disabled = FaultLayer::disabled().layer(upstream.clone());
+        assert!(disabled.get(25).await.unwrap().is_some());
+        assert_eq!(upstream.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn batches_forward_the_lookups_without_faults() {
+        let upstream = Stub::default();
+        let faults = FaultLayer::new(config(0.3, 0.3)).layer(upstream.clone());
+
+        let ids: Vec<u32> = (1..=20).collect();
+        let results = faults.get_many(&ids).await;
+
+        let forwarded: Vec<u32> = ids
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| matches!(result, Ok(Some(_))))
+            .map(|(num, _)| *num)
+            .collect();
+
+        assert_eq!(results.len(), ids.len());
+        assert!(forwarded.len() < ids.len(), "no fault injected");
+        assert_eq!(upstream.batches(), vec![forwarded]);
+    }
+
+    #[tokio::test]
+    async fn hanging_lookups_hang_the_whole_batch() {
+        let hanging = FaultLayer::new(FaultConfig {
+            hang_rate: 1.0,
+            ..config(0.0, 0.0)
+        })
+        .layer(Stub::default());
+
+        let batch = tokio::time::timeout(Duration::from_millis(20), hanging.get_many(&[1, 2]));
+        assert!(batch.await.is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "fault rates sum up to")]
+    fn refuses_rates_over_one() {
+        FaultLayer::new(config(0.6, 0.6));
+    }
+}
@@ -0,0 +1,191 @@
+//! Composable middleware for `pokemon::Repository`, in the style of `tower`.
+//!
+//! A [`Layer`] wraps a repository into another one, and layers are stacked
+//! with a [`RepositoryBuilder`]:
+//!
+//! ```ignore
+//! let repository = RepositoryBuilder::new()
+//!     .layer(LoggingLayer::new("pokeapi"))
+//!     .layer(TimeoutLayer::new(Duration::from_secs(5)))
+//!     .layer(ConcurrencyLimitLayer::new(32))
+//!     .build(PokemonRepository::default());
+//! ```
+//!
+//! The first layer added is the outermost one: in the example above, lookups
+//! are logged, then timed out, and then queued for the concurrency limit.
+
+pub mod fault;
+pub mod limit;
+pub mod logging;
+pub mod metrics;
+pub mod retry;
+pub mod timeout;
+
+#[cfg(test)]
+mod testing;
+
+pub use fault::FaultLayer;
+pub use limit::ConcurrencyLimitLayer;
+pub use logging::LoggingLayer;
+pub use metrics::MetricsLayer;
+pub use retry::RetryLayer;
+pub use timeout::TimeoutLayer;
+
+/// Decorates a repository with additional behaviour.
+pub trait Layer<R> {
+    type Repository;
+
+    fn layer(&self, inner: R) -> Self::Repository;
+}
+
+/// Layer leaving the repository untouched.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Identity;
+
+impl<R> Layer<R> for Identity {
+    type Repository = R;
+
+    fn layer(&self, inner: R) -> R {
+        inner
+    }
+}
+
+/// Two layers applied one after the other: `inner` first, then `outer`.
+#[derive(Clone, Debug)]
+pub struct Stack<Inner, Outer> {
+    inner: Inner,
+    outer: Outer,
+}
+
+impl<R, Inner, Outer> Layer<R> for Stack<Inner, Outer>
+where
+    Inner: Layer<R>,
+    Outer: Layer<Inner::Repository>,
+{
+    type Repository = Outer::Repository;
+
+    fn layer(&self, repository: R) -> Self::Repository {
+        self.outer.layer(self.inner.layer(repository))
+    }
+}
+
+/// Layer built from a function, e.g. to use an existing decorator
+/// such as `CacheLayer::new` as a layer.
+#[derive(Clone, Copy, Debug)]
+pub struct LayerFn<F>(F);
+
+pub fn layer_fn<F>(f: F) -> LayerFn<F> {
+    LayerFn(f)
+}
+
+impl<R, F, Out> Layer<R> for LayerFn<F>
+where
+    F: Fn(R) -> Out,
+{
+    type Repository = Out;
+
+    fn layer(&self, inner: R) -> Out {
+        (self.0)(inner)
+    }
+}
+
+/// Builds a repository out of a stack of layers.
+#[derive(Clone, Debug, Default)]
+pub struct RepositoryBuilder<L> {
+    layer: L,
+}
+
+impl RepositoryBuilder<Identity> {
+    pub fn new() -> Self {
+        RepositoryBuilder { layer: Identity }
+    }
+}
+
+impl<L> RepositoryBuilder<L> {
+    /// Adds a layer below the ones added so far.
+    pub fn layer<T>(self, layer: T) -> RepositoryBuilder<Stack<T, L>> {
+        RepositoryBuilder {
+            layer: Stack {
+                inner: layer,
+                outer: self.layer,
+            },
+        }
+    }
+
+    /// Wraps the repository into all the layers.
+    pub fn build<R>(&self, repository: R) -> L::Repository
+    where
+        L: Layer<R>,
+    {
+        self.layer.layer(repository)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use poke_domain::pokemon::{self, Pokemon, Repository};
+
+    use super::*;
+    use crate::testing::Stub;
+
+    type Calls = Arc<Mutex<Vec<&'static str>>>;
+
+    /// Records its name on every lookup, before forwarding it.
+    struct Named<R> {
+        inner: R,
+        name: &'static str,
+        calls: Calls,
+    }
+
+    impl<R> pokemon::Repository for Named<R>
+    where
+        R: pokemon::Repository + Send + Sync,
+    {
+        type Error = R::Error;
+
+        async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+            self.calls.lock().unwrap().push(self.name);
+            self.inner.get(num).await
+        }
+    }
+
+    fn named<R>(name: &'static str, calls: &Calls) -> impl Layer<R, Repository = Named<R>> {
+        let calls = calls.clone();
+        layer_fn(move |inner| Named {
+            inner,
+            name,
+            calls: calls.clone(),
+        })
+    }
+
+    #[tokio::test]
+    async fn builders_apply_the_first_layer_outermost() {
+        let calls = Calls::default();
+
+        let repository = RepositoryBuilder::new()
+            .layer(named("outer", &calls))
+            .layer(named("middle", &calls))
+            .layer(named("inner", &calls))
+            .build(Stub::default());
+
+        repository.get(25).await.unwrap();
+
+        assert_eq!(*calls.lock().unwrap(), vec!["outer", "middle", "inner"]);
+    }
+
+    #[tokio::test]
+    async fn stacks_apply_the_inner_layer_first() {
+        let calls = Calls::default();
+
+        let stack = Stack {
+            inner: named("inner", &calls),
+            outer: named("outer", &calls),
+        };
+
+        stack.layer(Stub::default()).get(25).await.unwrap();
+
+        assert_eq!(*calls.lock().unwrap(), vec!["outer", "inner"]);
+    }
+}
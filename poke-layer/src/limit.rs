@@ -0,0 +1,147 @@
+use std::sync::Arc;
+
+use tokio::sync::Semaphore;
+
+use poke_domain::pokemon::{self, Pokemon, Source};
+
+use crate::Layer;
+
+/// Limits the number of lookups in flight at the same time:
+/// lookups over the limit wait for a slot to be freed.
+///
+/// Repositories built by the same layer share the same limit.
+///
+/// The time spent waiting for a slot counts against the timeout
+/// of a [`TimeoutLayer`](crate::TimeoutLayer) added before this one,
+/// so that lookups don't queue for longer than their timeout.
+#[derive(Clone)]
+pub struct ConcurrencyLimitLayer {
+    semaphore: Arc<Semaphore>,
+}
+
+impl ConcurrencyLimitLayer {
+    /// # Panics
+    ///
+    /// Panics if `max` is 0, since no lookup would ever complete.
+    pub fn new(max: usize) -> Self {
+        assert!(max > 0, "concurrency limit must be at least 1");
+
+        ConcurrencyLimitLayer {
+            semaphore: Arc::new(Semaphore::new(max)),
+        }
+    }
+}
+
+impl<R> Layer<R> for ConcurrencyLimitLayer {
+    type Repository = ConcurrencyLimit<R>;
+
+    fn layer(&self, inner: R) -> ConcurrencyLimit<R> {
+        ConcurrencyLimit {
+            inner,
+            semaphore: self.semaphore.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ConcurrencyLimit<R> {
+    inner: R,
+    semaphore: Arc<Semaphore>,
+}
+
+impl<R> pokemon::Repository for ConcurrencyLimit<R>
+where
+    R: pokemon::Repository + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        let _permit = self.semaphore.acquire().await;
+        self.inner.get_sourced(num).await
+    }
+
+    /// The batch is forwarded as a whole, taking a single slot.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>>
+    where
+        Self::Error: Send,
+    {
+        let _permit = self.semaphore.acquire().await;
+        self.inner.get_many(ids).await
+    }
+
+    /// Probes don't take a slot: health checks must not queue behind the
+    /// lookups, nor delay them.
+    async fn probe(&self) -> Result<(), Self::Error> {
+        self.inner.probe().await
+    }
+}
+
+impl<R> pokemon::Catalogue for ConcurrencyLimit<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        let _permit = self.semaphore.acquire().await;
+        self.inner.list().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use futures::future::join_all;
+
+    use poke_domain::pokemon::Repository;
+
+    use super::*;
+    use crate::testing::Stub;
+
+    #[tokio::test]
+    async fn caps_the_lookups_in_flight() {
+        let upstream = Stub::default().slow(Duration::from_millis(20));
+        let limit = ConcurrencyLimitLayer::new(2).layer(upstream.clone());
+
+        let results = join_all((1..=6).map(|num| limit.get(num))).await;
+
+        assert!(results.iter().all(|result| matches!(result, Ok(Some(_)))));
+        assert_eq!(upstream.calls(), 6);
+        assert_eq!(upstream.max_in_flight(), 2);
+    }
+
+    #[tokio::test]
+    async fn repositories_of_the_same_layer_share_the_limit() {
+        let upstream = Stub::default().slow(Duration::from_millis(20));
+        let layer = ConcurrencyLimitLayer::new(1);
+        let (first, second) = (layer.layer(upstream.clone()), layer.layer(upstream.clone()));
+
+        let _ = futures::join!(first.get(1), second.get(2));
+
+        assert_eq!(upstream.max_in_flight(), 1);
+    }
+
+    #[tokio::test]
+    async fn batches_take_a_single_slot() {
+        let upstream = Stub::default().slow(Duration::from_millis(20));
+        let limit = ConcurrencyLimitLayer::new(1).layer(upstream.clone());
+
+        let (batch, single) = futures::join!(limit.get_many(&[1, 2, 3]), limit.get(4));
+
+        assert!(batch.iter().all(|result| matches!(result, Ok(Some(_)))));
+        assert!(single.unwrap().is_some());
+        assert_eq!(upstream.batches(), vec![vec![1, 2, 3]]);
+        assert_eq!(upstream.max_in_flight(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "concurrency limit must be at least 1")]
+    fn refuses_a_zero_limit() {
+        ConcurrencyLimitLayer::new(0);
+    }
+}
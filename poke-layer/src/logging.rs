@@ -0,0 +1,152 @@
+use std::time::Instant;
+
+use poke_domain::pokemon::{self, Pokemon, Source};
+
+use crate::Layer;
+
+/// Logs every lookup with its outcome and duration, under the specified name:
+/// successful lookups are logged at debug level, and failed ones as warnings.
+#[derive(Clone, Copy, Debug)]
+pub struct LoggingLayer {
+    name: &'static str,
+}
+
+impl LoggingLayer {
+    pub fn new(name: &'static str) -> Self {
+        LoggingLayer { name }
+    }
+}
+
+impl<R> Layer<R> for LoggingLayer {
+    type Repository = Logging<R>;
+
+    fn layer(&self, inner: R) -> Logging<R> {
+        Logging {
+            inner,
+            name: self.name,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Logging<R> {
+    inner: R,
+    name: &'static str,
+}
+
+impl<R> pokemon::Repository for Logging<R>
+where
+    R: pokemon::Repository + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        let start = Instant::now();
+        let result = self.inner.get_sourced(num).await;
+        let elapsed = start.elapsed();
+
+        match result {
+            Ok(Some((_, source))) => log::debug!(
+                "[{}] Got Pokemon #{} from {} in {:?}",
+                self.name,
+                num,
+                source,
+                elapsed
+            ),
+            Ok(None) => log::debug!(
+                "[{}] Pokemon #{} not found in {:?}",
+                self.name,
+                num,
+                elapsed
+            ),
+            Err(ref err) => log::warn!(
+                "[{}] Failed to get Pokemon #{} in {:?}: {}",
+                self.name,
+                num,
+                elapsed,
+                err
+            ),
+        }
+
+        result
+    }
+
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>>
+    where
+        Self::Error: Send,
+    {
+        let start = Instant::now();
+        let results = self.inner.get_many(ids).await;
+        let elapsed = start.elapsed();
+
+        let found = results
+            .iter()
+            .filter(|result| matches!(result, Ok(Some(_))))
+            .count();
+        let failed = results.iter().filter(|result| result.is_err()).count();
+
+        if failed > 0 {
+            log::warn!(
+                "[{}] Failed to get {} of {} Pokemons in {:?}",
+                self.name,
+                failed,
+                ids.len(),
+                elapsed
+            );
+        }
+
+        log::debug!(
+            "[{}] Got {} of {} Pokemons in {:?}",
+            self.name,
+            found,
+            ids.len(),
+            elapsed
+        );
+
+        results
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        let result = self.inner.probe().await;
+
+        if let Err(ref err) = result {
+            log::warn!("[{}] Probe failed: {}", self.name, err);
+        }
+
+        result
+    }
+}
+
+impl<R> pokemon::Catalogue for Logging<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        let start = Instant::now();
+        let result = self.inner.list().await;
+        let elapsed = start.elapsed();
+
+        match result {
+            Ok(ref list) => log::debug!(
+                "[{}] Listed {} Pokemons in {:?}",
+                self.name,
+                list.len(),
+                elapsed
+            ),
+            Err(ref err) => log::warn!(
+                "[{}] Failed to list Pokemons in {:?}: {}",
+                self.name,
+                elapsed,
+                err
+            ),
+        }
+
+        result
+    }
+}
@@ -0,0 +1,331 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use poke_domain::pokemon::{self, Pokemon, Source};
+
+use crate::Layer;
+
+/// Upper bounds of the latency histogram buckets, in seconds.
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Counts the lookups, their outcome and latency.
+///
+/// Repositories built by the same layer share the same metrics,
+/// which can be read through the [`MetricsHandle`] returned by [`MetricsLayer::handle`].
+#[derive(Clone, Default)]
+pub struct MetricsLayer {
+    counters: Arc<Counters>,
+}
+
+impl MetricsLayer {
+    pub fn new() -> Self {
+        MetricsLayer::default()
+    }
+
+    pub fn handle(&self) -> MetricsHandle {
+        MetricsHandle(self.counters.clone())
+    }
+}
+
+impl<R> Layer<R> for MetricsLayer {
+    type Repository = Metrics<R>;
+
+    fn layer(&self, inner: R) -> Metrics<R> {
+        Metrics {
+            inner,
+            counters: self.counters.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    requests: AtomicU64,
+    found: AtomicU64,
+    not_found: AtomicU64,
+    errors: AtomicU64,
+    in_flight: AtomicU64,
+    latency: Histogram,
+}
+
+/// Latency histogram over the [`LATENCY_BUCKETS`].
+#[derive(Default)]
+pub struct Histogram {
+    count: AtomicU64,
+    sum_micros: AtomicU64,
+    buckets: [AtomicU64; LATENCY_BUCKETS.len()],
+}
+
+/// Snapshot of a [`Histogram`].
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct HistogramSnapshot {
+    pub count: u64,
+    /// Sum of all the observations, in seconds.
+    pub sum: f64,
+    /// Cumulative number of observations within each of the [`LATENCY_BUCKETS`].
+    pub buckets: Vec<(f64, u64)>,
+}
+
+impl Histogram {
+    pub fn observe(&self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+
+        for (bound, bucket) in LATENCY_BUCKETS.iter().zip(self.buckets.iter()) {
+            if seconds <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    pub fn snapshot(&self) -> HistogramSnapshot {
+        HistogramSnapshot {
+            count: self.count.load(Ordering::Relaxed),
+            sum: self.sum_micros.load(Ordering::Relaxed) as f64 / 1e6,
+            buckets: LATENCY_BUCKETS
+                .iter()
+                .zip(self.buckets.iter())
+                .map(|(bound, count)| (*bound, count.load(Ordering::Relaxed)))
+                .collect(),
+        }
+    }
+}
+
+/// Read handle over the metrics collected by a [`MetricsLayer`].
+#[derive(Clone)]
+pub struct MetricsHandle(Arc<Counters>);
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RepositoryMetrics {
+    pub requests: u64,
+    pub found: u64,
+    pub not_found: u64,
+    pub errors: u64,
+    pub in_flight: u64,
+    /// Total time spent in lookups, in seconds.
+    pub latency_sum: f64,
+    /// Cumulative number of lookups completed within each of the [`LATENCY_BUCKETS`].
+    pub latency_buckets: Vec<(f64, u64)>,
+}
+
+impl MetricsHandle {
+    pub fn snapshot(&self) -> RepositoryMetrics {
+        let counters = &self.0;
+        let latency = counters.latency.snapshot();
+
+        RepositoryMetrics {
+            requests: counters.requests.load(Ordering::Relaxed),
+            found: counters.found.load(Ordering::Relaxed),
+            not_found: counters.not_found.load(Ordering::Relaxed),
+            errors: counters.errors.load(Ordering::Relaxed),
+            in_flight: counters.in_flight.load(Ordering::Relaxed),
+            latency_sum: latency.sum,
+            latency_buckets: latency.buckets,
+        }
+    }
+
+    pub fn latency(&self) -> HistogramSnapshot {
+        self.0.latency.snapshot()
+    }
+}
+
+#[derive(Clone)]
+pub struct Metrics<R> {
+    inner: R,
+    counters: Arc<Counters>,
+}
+
+/// Tracks lookups in flight, so that they're accounted for even if cancelled.
+struct InFlight<'a> {
+    counters: &'a Counters,
+    lookups: u64,
+    start: Instant,
+}
+
+impl<'a> InFlight<'a> {
+    fn start(counters: &'a Counters, lookups: u64) -> Self {
+        counters.requests.fetch_add(lookups, Ordering::Relaxed);
+        counters.in_flight.fetch_add(lookups, Ordering::Relaxed);
+
+        InFlight {
+            counters,
+            lookups,
+            start: Instant::now(),
+        }
+    }
+
+    fn count<T, E>(&self, result: &Result<Option<T>, E>) {
+        let counter = match result {
+            Ok(Some(_)) => &self.counters.found,
+            Ok(None) => &self.counters.not_found,
+            Err(_) => &self.counters.errors,
+        };
+
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+impl<'a> Drop for InFlight<'a> {
+    fn drop(&mut self) {
+        let elapsed = self.start.elapsed();
+
+        self.counters
+            .in_flight
+            .fetch_sub(self.lookups, Ordering::Relaxed);
+
+        // Every lookup of a batch takes as long as the batch.
+        for _ in 0..self.lookups {
+            self.counters.latency.observe(elapsed);
+        }
+    }
+}
+
+impl<R> pokemon::Repository for Metrics<R>
+where
+    R: pokemon::Repository + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        let in_flight = InFlight::start(&self.counters, 1);
+        let result = self.inner.get_sourced(num).await;
+
+        in_flight.count(&result);
+        result
+    }
+
+    /// The batch is forwarded as a whole, and timed once: every lookup
+    /// of the batch is counted, with the latency of the batch.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>>
+    where
+        Self::Error: Send,
+    {
+        let in_flight = InFlight::start(&self.counters, ids.len() as u64);
+        let results = self.inner.get_many(ids).await;
+
+        for result in &results {
+            in_flight.count(result);
+        }
+
+        results
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        // Probes are not lookups: forward them without counting.
+        self.inner.probe().await
+    }
+}
+
+impl<R> pokemon::Catalogue for Metrics<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        // Listings are not lookups: forward them without counting.
+        self.inner.list().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poke_domain::pokemon::Repository;
+
+    use super::*;
+    use crate::testing::Stub;
+
+    #[tokio::test]
+    async fn counts_the_outcome_of_lookups() {
+        let upstream = Stub::default();
+        let layer = MetricsLayer::new();
+        let metrics = layer.layer(upstream.clone());
+
+        metrics.get(25).await.unwrap();
+        metrics.get(1000).await.unwrap();
+
+        upstream.fail_next(1);
+        assert!(metrics.get(25).await.is_err());
+
+        let snapshot = layer.handle().snapshot();
+        assert_eq!(snapshot.requests, 3);
+        assert_eq!(
+            (snapshot.found, snapshot.not_found, snapshot.errors),
+            (1, 1, 1)
+        );
+        assert_eq!(snapshot.in_flight, 0);
+    }
+
+    #[tokio::test]
+    async fn times_the_lookups() {
+        let upstream = Stub::default().slow(Duration::from_millis(30));
+        let layer = MetricsLayer::new();
+        let metrics = layer.layer(upstream.clone());
+
+        metrics.get(25).await.unwrap();
+
+        upstream.fail_next(1);
+        assert!(metrics.get(25).await.is_err());
+
+        let latency = layer.handle().latency();
+        assert_eq!(latency.count, 2);
+        assert!(latency.sum >= 0.06, "sum: {}", latency.sum);
+
+        // Both lookups took between 25ms and 50ms.
+        let within = |bound: f64| {
+            latency
+                .buckets
+                .iter()
+                .find(|(upper, _)| *upper == bound)
+                .map(|(_, count)| *count)
+        };
+
+        assert_eq!(within(0.025), Some(0));
+        assert_eq!(within(10.0), Some(2));
+    }
+
+    #[tokio::test]
+    async fn counts_every_lookup_of_batches() {
+        let upstream = Stub::default();
+        let layer = MetricsLayer::new();
+        let metrics = layer.layer(upstream.clone());
+
+        upstream.fail_next(1);
+        metrics.get_many(&[1, 25, 1000]).await;
+
+        let snapshot = layer.handle().snapshot();
+        assert_eq!(snapshot.requests, 3);
+        assert_eq!(
+            (snapshot.found, snapshot.not_found, snapshot.errors),
+            (1, 1, 1)
+        );
+        assert_eq!(layer.handle().latency().count, 3);
+        assert_eq!(upstream.batches(), vec![vec![1, 25, 1000]]);
+    }
+
+    #[test]
+    fn histograms_are_cumulative() {
+        let histogram = Histogram::default();
+
+        histogram.observe(Duration::from_millis(3));
+        histogram.observe(Duration::from_millis(75));
+
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count, 2);
+        assert_eq!(snapshot.buckets[0], (0.005, 1));
+        assert_eq!(snapshot.buckets[4], (0.1, 2));
+        assert!((snapshot.sum - 0.078).abs() < 1e-9);
+    }
+}
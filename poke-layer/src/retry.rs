@@ -0,0 +1,191 @@
+use std::future::Future;
+use std::time::Duration;
+
+use poke_domain::pokemon::{self, Pokemon, Source};
+
+use crate::Layer;
+
+/// Retries failed lookups up to the specified number of times, waiting
+/// `backoff` before the first retry and doubling it before every other.
+///
+/// Lookups are retried on any error: the layer goes outside a
+/// [`TimeoutLayer`](crate::TimeoutLayer) to retry timed out lookups too.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryLayer {
+    retries: u32,
+    backoff: Duration,
+}
+
+impl RetryLayer {
+    pub fn new(retries: u32, backoff: Duration) -> Self {
+        RetryLayer { retries, backoff }
+    }
+}
+
+impl<R> Layer<R> for RetryLayer {
+    type Repository = Retry<R>;
+
+    fn layer(&self, inner: R) -> Retry<R> {
+        Retry {
+            inner,
+            retries: self.retries,
+            backoff: self.backoff,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Retry<R> {
+    inner: R,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl<R> Retry<R> {
+    /// Time to wait before the specified retry, starting from 0.
+    fn backoff(&self, retry: u32) -> Duration {
+        self.backoff
+            .checked_mul(2u32.saturating_pow(retry))
+            .unwrap_or_else(|| Duration::from_secs(u64::MAX))
+    }
+
+    async fn retry<T, E, F, Fut>(&self, what: &str, mut request: F) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: std::error::Error,
+    {
+        let mut retry = 0;
+
+        loop {
+            let backoff = self.backoff(retry);
+
+            // The error is dropped before waiting, since it might not be `Send`.
+            match request().await {
+                Err(err) if retry < self.retries => {
+                    log::debug!("Retrying {} in {:?} after: {}", what, backoff, err)
+                }
+                result => return result,
+            }
+
+            tokio::time::delay_for(backoff).await;
+            retry += 1;
+        }
+    }
+}
+
+impl<R> pokemon::Repository for Retry<R>
+where
+    R: pokemon::Repository + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        let what = format!("Pokemon #{}", num);
+        self.retry(&what, || self.inner.get_sourced(num)).await
+    }
+
+    /// Only the failed lookups of the batch are retried, in a batch of their own.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>>
+    where
+        Self::Error: Send,
+    {
+        let mut results = self.inner.get_many(ids).await;
+
+        for retry in 0..self.retries {
+            let failed: Vec<usize> = (0..results.len())
+                .filter(|idx| results[*idx].is_err())
+                .collect();
+
+            if failed.is_empty() {
+                break;
+            }
+
+            let backoff = self.backoff(retry);
+            log::debug!(
+                "Retrying {} of {} Pokemons in {:?}",
+                failed.len(),
+                ids.len(),
+                backoff
+            );
+
+            tokio::time::delay_for(backoff).await;
+
+            let retried: Vec<u32> = failed.iter().map(|idx| ids[*idx]).collect();
+            let retried = self.inner.get_many(&retried).await;
+
+            for (idx, result) in failed.into_iter().zip(retried) {
+                results[idx] = result;
+            }
+        }
+
+        results
+    }
+
+    /// Probes are not retried: health checks report the upstream as it is.
+    async fn probe(&self) -> Result<(), Self::Error> {
+        self.inner.probe().await
+    }
+}
+
+impl<R> pokemon::Catalogue for Retry<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        self.retry("catalogue", || self.inner.list()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poke_domain::pokemon::Repository;
+
+    use super::*;
+    use crate::testing::Stub;
+
+    fn retry(upstream: Stub, retries: u32) -> Retry<Stub> {
+        RetryLayer::new(retries, Duration::from_millis(1)).layer(upstream)
+    }
+
+    #[tokio::test]
+    async fn retries_failed_lookups() {
+        let upstream = Stub::default();
+        upstream.fail_next(2);
+
+        let retry = retry(upstream.clone(), 2);
+
+        assert!(retry.get(25).await.unwrap().is_some());
+        assert_eq!(upstream.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_last_retry() {
+        let upstream = Stub::default();
+        upstream.fail_next(3);
+
+        let retry = retry(upstream.clone(), 2);
+
+        assert!(retry.get(25).await.is_err());
+        assert_eq!(upstream.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn retries_only_the_failed_lookups_of_batches() {
+        let upstream = Stub::default();
+        upstream.fail_next(2);
+
+        let retry = retry(upstream.clone(), 1);
+
+        let results = retry.get_many(&[1, 4, 7]).await;
+
+        assert!(results.iter().all(|result| matches!(result, Ok(Some(_)))));
+        assert_eq!(upstream.batches(), vec![vec![1, 4, 7], vec![1, 4]]);
+    }
+}
@@ -0,0 +1,118 @@
+//! Repositories shared by the tests of the crate.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use poke_domain::pokemon::{self, Element, Pokemon, Stats, Type};
+
+pub(crate) fn pokemon(dex_id: u32) -> Pokemon {
+    Pokemon {
+        dex_id,
+        name: format!("pokemon-{}", dex_id),
+        typ: Type::Single(Element::Normal),
+        height: 10,
+        weight: 100,
+        base_experience: 64,
+        stats: Stats {
+            speed: 45,
+            special_defense: 65,
+            special_attack: 65,
+            defense: 49,
+            attack: 49,
+            hit_points: 45,
+        },
+    }
+}
+
+/// Upstream finding every Pokémon up to `#151`, counting its lookups,
+/// which can be made slow or failing.
+#[derive(Clone, Default)]
+pub(crate) struct Stub {
+    calls: Arc<AtomicUsize>,
+    batches: Arc<Mutex<Vec<Vec<u32>>>>,
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: Arc<AtomicUsize>,
+    failures: Arc<AtomicUsize>,
+    delay: Duration,
+}
+
+#[derive(Debug)]
+pub(crate) struct StubError;
+
+impl std::error::Error for StubError {}
+
+impl Display for StubError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "stub upstream failure")
+    }
+}
+
+impl Stub {
+    pub(crate) fn slow(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Fails the next `failures` lookups.
+    pub(crate) fn fail_next(&self, failures: usize) {
+        self.failures.store(failures, Ordering::SeqCst);
+    }
+
+    pub(crate) fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Returns the ids of every batch looked up, in order.
+    pub(crate) fn batches(&self) -> Vec<Vec<u32>> {
+        self.batches.lock().unwrap().clone()
+    }
+
+    /// Maximum number of lookups in flight at the same time so far.
+    pub(crate) fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+impl pokemon::Repository for Stub {
+    type Error = StubError;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+
+        if self.delay > Duration::default() {
+            tokio::time::delay_for(self.delay).await;
+        }
+
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+        let failing = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |failures| {
+                failures.checked_sub(1)
+            })
+            .is_ok();
+
+        match num {
+            _ if failing => Err(StubError),
+            1..=151 => Ok(Some(pokemon(num))),
+            _ => Ok(None),
+        }
+    }
+
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        self.batches.lock().unwrap().push(ids.to_vec());
+
+        let mut results = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            results.push(self.get(*id).await);
+        }
+
+        results
+    }
+}
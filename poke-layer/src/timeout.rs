@@ -0,0 +1,167 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::time::Duration;
+
+use poke_domain::pokemon::{self, Pokemon, Source};
+
+use crate::Layer;
+
+/// Fails lookups taking longer than the specified duration.
+#[derive(Clone, Copy, Debug)]
+pub struct TimeoutLayer {
+    timeout: Duration,
+}
+
+impl TimeoutLayer {
+    pub fn new(timeout: Duration) -> Self {
+        TimeoutLayer { timeout }
+    }
+}
+
+impl<R> Layer<R> for TimeoutLayer {
+    type Repository = Timeout<R>;
+
+    fn layer(&self, inner: R) -> Timeout<R> {
+        Timeout {
+            inner,
+            timeout: self.timeout,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Timeout<R> {
+    inner: R,
+    timeout: Duration,
+}
+
+impl<R> pokemon::Repository for Timeout<R>
+where
+    R: pokemon::Repository + Send + Sync,
+    R::Error: Send + 'static,
+{
+    type Error = TimeoutError<R::Error>;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        tokio::time::timeout(self.timeout, self.inner.get_sourced(num))
+            .await
+            .map_err(|_| TimeoutError::Elapsed(self.timeout))?
+            .map_err(TimeoutError::Inner)
+    }
+
+    /// The batch is forwarded as a whole, under a single timeout:
+    /// every lookup of the batch fails if it elapses.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        match tokio::time::timeout(self.timeout, self.inner.get_many(ids)).await {
+            Ok(results) => results
+                .into_iter()
+                .map(|result| result.map_err(TimeoutError::Inner))
+                .collect(),
+            Err(_) => ids
+                .iter()
+                .map(|_| Err(TimeoutError::Elapsed(self.timeout)))
+                .collect(),
+        }
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        tokio::time::timeout(self.timeout, self.inner.probe())
+            .await
+            .map_err(|_| TimeoutError::Elapsed(self.timeout))?
+            .map_err(TimeoutError::Inner)
+    }
+}
+
+impl<R> pokemon::Catalogue for Timeout<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+    R::Error: 'static,
+{
+    type Error = TimeoutError<R::Error>;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        tokio::time::timeout(self.timeout, self.inner.list())
+            .await
+            .map_err(|_| TimeoutError::Elapsed(self.timeout))?
+            .map_err(TimeoutError::Inner)
+    }
+}
+
+#[derive(Debug)]
+pub enum TimeoutError<E> {
+    Elapsed(Duration),
+    Inner(E),
+}
+
+impl<E> std::error::Error for TimeoutError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TimeoutError::Elapsed(_) => None,
+            TimeoutError::Inner(inner) => Some(inner),
+        }
+    }
+}
+
+impl<E> Display for TimeoutError<E>
+where
+    E: std::error::Error,
+{
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            TimeoutError::Elapsed(timeout) => write!(f, "request timed out after {:?}", timeout),
+            TimeoutError::Inner(inner) => Display::fmt(inner, f),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poke_domain::pokemon::Repository;
+
+    use super::*;
+    use crate::testing::Stub;
+
+    #[tokio::test]
+    async fn fails_slow_lookups() {
+        let upstream = Stub::default().slow(Duration::from_millis(50));
+        let timeout = TimeoutLayer::new(Duration::from_millis(10)).layer(upstream);
+
+        assert!(matches!(
+            timeout.get(25).await,
+            Err(TimeoutError::Elapsed(elapsed)) if elapsed == Duration::from_millis(10)
+        ));
+    }
+
+    #[tokio::test]
+    async fn forwards_timely_results() {
+        let upstream = Stub::default();
+        let timeout = TimeoutLayer::new(Duration::from_millis(50)).layer(upstream.clone());
+
+        assert!(timeout.get(25).await.unwrap().is_some());
+        assert!(timeout.get(1000).await.unwrap().is_none());
+
+        upstream.fail_next(1);
+        assert!(matches!(timeout.get(25).await, Err(TimeoutError::Inner(_))));
+    }
+
+    #[tokio::test]
+    async fn batches_share_a_single_timeout() {
+        // Each lookup of the batch is timely, but not the batch as a whole.
+        let upstream = Stub::default().slow(Duration::from_millis(20));
+        let timeout = TimeoutLayer::new(Duration::from_millis(50)).layer(upstream.clone());
+
+        let results = timeout.get_many(&[1, 2, 3, 4]).await;
+
+        assert_eq!(results.len(), 4);
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(TimeoutError::Elapsed(_)))));
+        assert_eq!(upstream.batches(), vec![vec![1, 2, 3, 4]]);
+    }
+}
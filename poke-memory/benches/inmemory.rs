@@ -0,0 +1,86 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use futures::executor::block_on;
+use futures::future::{BoxFuture, FutureExt};
+
+use poke_domain::pokemon::{Element, Pokemon, Repository, Stats, Type};
+use poke_memory::custom::CustomLayer;
+use poke_memory::patch::PatchLayer;
+use poke_memory::InMemoryRepository;
+
+const DEX_SIZE: u32 = 1000;
+
+fn pokemon(dex_id: u32) -> Pokemon {
+    Pokemon {
+        dex_id,
+        name: format!("pokemon-{}", dex_id),
+        typ: Type::Single(Element::Normal),
+        height: 10,
+        weight: 100,
+        base_experience: 64,
+        stats: Stats {
+            speed: 45,
+            special_defense: 65,
+            special_attack: 65,
+            defense: 49,
+            attack: 49,
+            hit_points: 45,
+        },
+    }
+}
+
+fn full_dex() -> InMemoryRepository {
+    InMemoryRepository::from((1..=DEX_SIZE).map(pokemon).collect::<Vec<_>>())
+}
+
+fn lookup(c: &mut Criterion) {
+    let repository = full_dex();
+
+    c.bench_function("get hit (full dex)", |b| {
+        b.iter(|| block_on(repository.get(black_box(DEX_SIZE))))
+    });
+
+    c.bench_function("get miss (full dex)", |b| {
+        b.iter(|| block_on(repository.get(black_box(DEX_SIZE + 1))))
+    });
+
+    c.bench_function("get_by_name hit (full dex)", |b| {
+        b.iter(|| block_on(repository.get_by_name(black_box("pokemon-1000"))))
+    });
+
+    c.bench_function("upsert existing (full dex)", |b| {
+        b.iter(|| block_on(repository.upsert(pokemon(black_box(DEX_SIZE / 2)))))
+    });
+}
+
+/// Compares the unboxed futures returned by `Repository::get` with boxing
+/// them, as the trait used to do for every call and every decorator.
+fn dispatch(c: &mut Criterion) {
+    let repository = full_dex();
+
+    c.bench_function("get hit, unboxed future", |b| {
+        b.iter(|| block_on(repository.get(black_box(DEX_SIZE))))
+    });
+
+    c.bench_function("get hit, boxed future", |b| {
+        b.iter(|| {
+            let future: BoxFuture<_> = repository.get(black_box(DEX_SIZE)).boxed();
+            block_on(future)
+        })
+    });
+
+    let layered = CustomLayer::from(PatchLayer::from(full_dex()));
+
+    c.bench_function("get hit through 2 layers, unboxed futures", |b| {
+        b.iter(|| block_on(layered.get(black_box(DEX_SIZE))))
+    });
+
+    c.bench_function("get hit through 2 layers, boxed future", |b| {
+        b.iter(|| {
+            let future: BoxFuture<_> = layered.get(black_box(DEX_SIZE)).boxed();
+            block_on(future)
+        })
+    });
+}
+
+criterion_group!(benches, lookup, dispatch);
+criterion_main!(benches);
@@ -0,0 +1,544 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use poke_domain::pokemon::{self, Pokemon, Source};
+
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub struct BreakerConfig {
+    /// Number of consecutive upstream failures opening the circuit.
+    pub failure_threshold: u32,
+
+    /// Time the circuit stays open before letting a probe request through.
+    pub cooldown: Duration,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown: DEFAULT_COOLDOWN,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    /// Requests go upstream, and failures are counted.
+    Closed,
+    /// Requests fail fast, without going upstream.
+    Open,
+    /// A single probe request goes upstream, deciding whether
+    /// to close the circuit again or to keep it open.
+    HalfOpen,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BreakerStatus {
+    pub state: State,
+    pub consecutive_failures: u32,
+    pub rejected: u64,
+}
+
+struct Circuit {
+    state: State,
+    consecutive_failures: u32,
+    opened_at: Instant,
+    probing: bool,
+}
+
+struct Inner {
+    config: BreakerConfig,
+    circuit: Mutex<Circuit>,
+    rejected: AtomicU64,
+}
+
+/// Repository decorator failing fast while the upstream repository
+/// is failing, instead of having every lookup wait for it to time out.
+///
+/// After `failure_threshold` consecutive failures the circuit opens,
+/// and lookups fail with [`BreakerError::Open`] for the `cooldown` time.
+/// After that a single probe lookup goes upstream: the circuit closes
+/// if it succeeds, and opens again otherwise.
+#[derive(Clone)]
+pub struct BreakerLayer<R> {
+    upstream: R,
+    inner: Arc<Inner>,
+}
+
+/// Permission to send a request upstream: dropping it always releases
+/// the probe, so that a cancelled probe doesn't keep the circuit half-open.
+struct Permit<'a> {
+    inner: &'a Inner,
+    probe: bool,
+}
+
+impl Inner {
+    fn circuit(&self) -> MutexGuard<'_, Circuit> {
+        self.circuit.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn acquire(&self) -> Option<Permit<'_>> {
+        let mut circuit = self.circuit();
+
+        match circuit.state {
+            State::Closed => Some(Permit {
+                inner: self,
+                probe: false,
+            }),
+            State::Open => {
+                if circuit.opened_at.elapsed() < self.config.cooldown {
+                    return None;
+                }
+
+                log::info!("Circuit breaker half-open, probing upstream");
+                circuit.state = State::HalfOpen;
+                circuit.probing = true;
+
+                Some(Permit {
+                    inner: self,
+                    probe: true,
+                })
+            }
+            State::HalfOpen if circuit.probing => None,
+            State::HalfOpen => {
+                circuit.probing = true;
+
+                Some(Permit {
+                    inner: self,
+                    probe: true,
+                })
+            }
+        }
+    }
+
+    fn open(&self, circuit: &mut Circuit) {
+        if circuit.state != State::Open {
+            log::warn!(
+                "Circuit breaker open after {} consecutive failures, failing fast for {:?}",
+                circuit.consecutive_failures,
+                self.config.cooldown
+            );
+        }
+
+        circuit.state = State::Open;
+        circuit.opened_at = Instant::now();
+        circuit.probing = false;
+    }
+}
+
+impl<'a> Permit<'a> {
+    fn success(self) {
+        let mut circuit = self.inner.circuit();
+
+        if circuit.state != State::Closed {
+            log::info!("Circuit breaker closed, upstream is back");
+        }
+
+        circuit.state = State::Closed;
+        circuit.consecutive_failures = 0;
+        circuit.probing = false;
+    }
+
+    fn failure(self) {
+        let mut circuit = self.inner.circuit();
+        circuit.consecutive_failures = circuit.consecutive_failures.saturating_add(1);
+
+        if self.probe || circuit.consecutive_failures >= self.inner.config.failure_threshold {
+            self.inner.open(&mut circuit);
+        }
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        if self.probe {
+            self.inner.circuit().probing = false;
+        }
+    }
+}
+
+impl<R> BreakerLayer<R> {
+    pub fn new(upstream: R, config: BreakerConfig) -> BreakerLayer<R> {
+        BreakerLayer {
+            upstream,
+            inner: Arc::new(Inner {
+                config,
+                circuit: Mutex::new(Circuit {
+                    state: State::Closed,
+                    consecutive_failures: 0,
+                    opened_at: Instant::now(),
+                    probing: false,
+                }),
+                rejected: AtomicU64::default(),
+            }),
+        }
+    }
+
+    pub fn status(&self) -> BreakerStatus {
+        let circuit = self.inner.circuit();
+
+        BreakerStatus {
+            state: circuit.state,
+            consecutive_failures: circuit.consecutive_failures,
+            rejected: self.inner.rejected.load(Ordering::Relaxed),
+        }
+    }
+
+    async fn call<T, E, F>(&self, request: F) -> Result<T, BreakerError<E>>
+    where
+        F: Future<Output = Result<T, E>>,
+    {
+        let permit = match self.inner.acquire() {
+            Some(permit) => permit,
+            None => {
+                self.inner.rejected.fetch_add(1, Ordering::Relaxed);
+                return Err(BreakerError::Open(CircuitOpen));
+            }
+        };
+
+        match request.await {
+            Ok(value) => {
+                permit.success();
+                Ok(value)
+            }
+            Err(err) => {
+                permit.failure();
+                Err(BreakerError::Upstream(err))
+            }
+        }
+    }
+}
+
+impl<R> From<R> for BreakerLayer<R> {
+    #[inline]
+    fn from(upstream: R) -> BreakerLayer<R> {
+        BreakerLayer::new(upstream, BreakerConfig::default())
+    }
+}
+
+impl<R> pokemon::Repository for BreakerLayer<R>
+where
+    R: pokemon::Repository + Send + Sync,
+    R::Error: Send + 'static,
+{
+    type Error = BreakerError<R::Error>;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        self.call(self.upstream.get_sourced(num)).await
+    }
+
+    /// The batch goes through the circuit as a whole: it counts as
+    /// a single request, failed if any of its lookups failed.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        let permit = match self.inner.acquire() {
+            Some(permit) => permit,
+            None => {
+                self.inner
+                    .rejected
+                    .fetch_add(ids.len() as u64, Ordering::Relaxed);
+
+                return ids
+                    .iter()
+                    .map(|_| Err(BreakerError::Open(CircuitOpen)))
+                    .collect();
+            }
+        };
+
+        let results = self.upstream.get_many(ids).await;
+
+        if results.iter().any(Result::is_err) {
+            permit.failure();
+        } else {
+            permit.success();
+        }
+
+        results
+            .into_iter()
+            .map(|result| result.map_err(BreakerError::Upstream))
+            .collect()
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        // Probes reach the upstream even while the circuit is open, and their
+        // outcome is not counted: health checks must neither trip the circuit
+        // nor close it, nor be reported as rejected requests.
+        self.upstream.probe().await.map_err(BreakerError::Upstream)
+    }
+}
+
+impl<R> pokemon::Catalogue for BreakerLayer<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+    R::Error: 'static,
+{
+    type Error = BreakerError<R::Error>;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        self.call(self.upstream.list()).await
+    }
+}
+
+#[derive(Debug)]
+pub enum BreakerError<E> {
+    /// The circuit is open: the request was not sent upstream.
+    Open(CircuitOpen),
+    Upstream(E),
+}
+
+impl<E> std::error::Error for BreakerError<E>
+where
+    E: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BreakerError::Open(inner) => Some(inner),
+            BreakerError::Upstream(inner) => Some(inner),
+        }
+    }
+}
+
+impl<E> Display for BreakerError<E>
+where
+    E: std::error::Error,
+{
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            BreakerError::Open(inner) => Display::fmt(inner, f),
+            BreakerError::Upstream(inner) => Display::fmt(inner, f),
+        }
+    }
+}
+
+/// Cause of the lookups rejected while the circuit is open,
+/// exposed as the source of [`BreakerError::Open`] so that it can be
+/// recognized further up the error chain.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CircuitOpen;
+
+impl std::error::Error for CircuitOpen {}
+
+impl Display for CircuitOpen {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "circuit breaker open, upstream unavailable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Stub;
+
+    use poke_domain::pokemon::Repository;
+
+    fn breaker(upstream: Stub) -> BreakerLayer<Stub> {
+        BreakerLayer::new(
+            upstream,
+            BreakerConfig {
+                failure_threshold: 3,
+                cooldown: Duration::from_secs(60),
+            },
+        )
+    }
+
+    const COOLDOWN: Duration = Duration::from_millis(20);
+
+    /// Breaker with a short cooldown, opened by failures of the upstream.
+    async fn opened(upstream: Stub) -> BreakerLayer<Stub> {
+        let breaker = BreakerLayer::new(
+            upstream.clone(),
+            BreakerConfig {
+                failure_threshold: 1,
+                cooldown: COOLDOWN,
+            },
+        );
+
+        upstream.set_failing(true);
+        let _ = breaker.get(25).await;
+        upstream.set_failing(false);
+
+        assert_eq!(breaker.status().state, State::Open);
+        breaker
+    }
+
+    async fn cool_down() {
+        tokio::time::delay_for(COOLDOWN * 2).await;
+    }
+
+    #[tokio::test]
+    async fn not_found_lookups_leave_the_circuit_closed() {
+        let upstream = Stub::with(vec![25]);
+        let breaker = breaker(upstream.clone());
+
+        for id in 10000..10010 {
+            assert_eq!(breaker.get(id).await.unwrap(), None);
+        }
+
+        assert_eq!(breaker.status().state, State::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+        assert_eq!(upstream.calls(), 10);
+    }
+
+    #[tokio::test]
+    async fn failures_open_the_circuit() {
+        let upstream = Stub::with(vec![25]);
+        let breaker = breaker(upstream.clone());
+        upstream.set_failing(true);
+
+        for _ in 0..3 {
+            assert!(matches!(
+                breaker.get(25).await,
+                Err(BreakerError::Upstream(_))
+            ));
+        }
+
+        assert_eq!(breaker.status().state, State::Open);
+        assert!(matches!(breaker.get(25).await, Err(BreakerError::Open(_))));
+        assert_eq!(upstream.calls(), 3);
+        assert_eq!(breaker.status().rejected, 1);
+    }
+
+    #[tokio::test]
+    async fn batches_count_as_a_single_request() {
+        let upstream = Stub::with(vec![25]);
+        let breaker = breaker(upstream.clone());
+
+        let results = breaker.get_many(&[25, 26]).await;
+        assert!(matches!(results[0], Ok(Some(_))));
+        assert!(matches!(results[1], Ok(None)));
+
+        upstream.set_failing(true);
+
+        for _ in 0..2 {
+            breaker.get_many(&[25, 26]).await;
+        }
+
+        assert_eq!(breaker.status().state, State::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 2);
+
+        breaker.get_many(&[25, 26]).await;
+        assert_eq!(breaker.status().state, State::Open);
+
+        let results = breaker.get_many(&[25, 26]).await;
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Err(BreakerError::Open(_)))));
+        assert_eq!(breaker.status().rejected, 2);
+        assert_eq!(upstream.batches().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn probes_bypass_the_circuit() {
+        let upstream = Stub::with(vec![25]);
+        let breaker = breaker(upstream.clone());
+        upstream.set_failing(true);
+
+        for _ in 0..5 {
+            assert!(matches!(
+                breaker.probe().await,
+                Err(BreakerError::Upstream(_))
+            ));
+        }
+
+        assert_eq!(breaker.status().state, State::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+
+        for _ in 0..3 {
+            let _ = breaker.get(25).await;
+        }
+
+        // Once open, probes still reach the upstream, without closing it.
+        upstream.set_failing(false);
+        breaker.probe().await.unwrap();
+
+        assert_eq!(breaker.status().state, State::Open);
+        assert_eq!(breaker.status().rejected, 0);
+        assert_eq!(upstream.calls(), 9);
+    }
+
+    #[tokio::test]
+    async fn successful_probes_close_the_circuit() {
+        let upstream = Stub::with(vec![25]);
+        let breaker = opened(upstream.clone()).await;
+
+        assert!(matches!(breaker.get(25).await, Err(BreakerError::Open(_))));
+
+        cool_down().await;
+
+        assert!(breaker.get(25).await.unwrap().is_some());
+        assert_eq!(breaker.status().state, State::Closed);
+        assert_eq!(breaker.status().consecutive_failures, 0);
+        assert_eq!(upstream.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn failed_probes_open_the_circuit_again() {
+        let upstream = Stub::with(vec![25]);
+        let breaker = opened(upstream.clone()).await;
+
+        cool_down().await;
+        upstream.set_failing(true);
+
+        assert!(matches!(
+            breaker.get(25).await,
+            Err(BreakerError::Upstream(_))
+        ));
+        assert_eq!(breaker.status().state, State::Open);
+
+        // The cooldown starts over from the failed probe.
+        assert!(matches!(breaker.get(25).await, Err(BreakerError::Open(_))));
+        assert_eq!(upstream.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn half_open_circuits_let_a_single_probe_through() {
+        let upstream = Stub::with(vec![25]).slow(Duration::from_millis(50));
+        let breaker = opened(upstream.clone()).await;
+
+        cool_down().await;
+
+        let (probe, concurrent) = futures::join!(breaker.get(25), async {
+            tokio::time::delay_for(Duration::from_millis(10)).await;
+            let status = breaker.status();
+            (status.state, breaker.get(25).await)
+        });
+
+        assert!(probe.unwrap().is_some());
+
+        let (state, concurrent) = concurrent;
+        assert_eq!(state, State::HalfOpen);
+        assert!(matches!(concurrent, Err(BreakerError::Open(_))));
+
+        assert_eq!(breaker.status().state, State::Closed);
+        assert_eq!(upstream.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn dropped_probes_release_the_half_open_circuit() {
+        let upstream = Stub::with(vec![25]).slow(Duration::from_millis(50));
+        let breaker = opened(upstream.clone()).await;
+
+        cool_down().await;
+
+        let probe = tokio::time::timeout(Duration::from_millis(10), breaker.get(25)).await;
+        assert!(probe.is_err());
+        assert_eq!(breaker.status().state, State::HalfOpen);
+
+        // Another probe can go through, instead of being rejected forever.
+        assert!(breaker.get(25).await.unwrap().is_some());
+        assert_eq!(breaker.status().state, State::Closed);
+        assert_eq!(upstream.calls(), 3);
+    }
+}
@@ -1,68 +1,736 @@
-use futures::future::BoxFuture;
-use tokio::sync::mpsc;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, PoisonError};
+use std::time::{Duration, Instant};
 
-use poke_domain::{pokemon, pokemon::Pokemon};
+use serde::Serialize;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::{Mutex, RwLock};
 
+use poke_domain::pokemon::{self, Pokemon, Repository, Source};
+
+use crate::coalesce::CoalescingLayer;
 use crate::InMemoryRepository;
 
+/// Repository decorator caching in memory the Pokémons found upstream.
+///
+/// Concurrent misses for the same id are coalesced into a single upstream call.
 #[derive(Clone)]
-pub struct CacheLayer<R> {
-    upstream: R,
-    tx: mpsc::Sender<Pokemon>,
+pub struct CacheLayer<R>
+where
+    R: pokemon::Repository,
+{
+    upstream: CoalescingLayer<R>,
+    writer: Writer,
     inmemory: InMemoryRepository,
+    // Ids not found upstream, with the time they were looked up.
+    negative: Arc<RwLock<HashMap<u32, Instant>>>,
+    // Ids of the stale Pokémons being refreshed in background.
+    refreshing: Arc<std::sync::Mutex<HashSet<u32>>>,
+    counters: Arc<Counters>,
+    config: CacheConfig,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CacheConfig {
+    /// Maximum number of cached Pokémons, evicted in LRU order.
+    /// The cache is unbounded if not set.
+    pub capacity: Option<usize>,
+
+    /// Time after which a cached Pokémon is considered stale and fetched
+    /// again from upstream. Cached Pokémons never expire if not set.
+    pub ttl: Option<Duration>,
+
+    /// Serve stale Pokémons while they're refreshed in background,
+    /// instead of waiting for upstream.
+    pub stale_while_revalidate: bool,
+
+    /// Time for which a Pokémon not found upstream is remembered as such.
+    /// Not-found results are not cached if not set.
+    ///
+    /// At most `capacity` (or [`MAX_NEGATIVE_ENTRIES`]) ids are remembered at once.
+    pub negative_ttl: Option<Duration>,
+
+    /// How Pokémons found upstream are written into the cache.
+    pub write_mode: WriteMode,
+}
+
+/// Size of the write queue of [`WriteMode::Background`], by default.
+pub const DEFAULT_WRITE_BUFFER: usize = 1024;
+
+/// Maximum number of ids remembered as not found, when the cache has no capacity:
+/// lookups for arbitrary ids must not grow the memory usage without bounds.
+pub const MAX_NEGATIVE_ENTRIES: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WriteMode {
+    /// Writes are queued to a background task, never blocking lookups:
+    /// when the queue is full, the write is dropped.
+    ///
+    /// The background task is restarted if it fails.
+    Background { buffer: usize },
+
+    /// Writes are performed before returning from lookups.
+    /// Useful for tests, since it doesn't need a running task.
+    WriteThrough,
+}
+
+impl Default for WriteMode {
+    fn default() -> Self {
+        WriteMode::Background {
+            buffer: DEFAULT_WRITE_BUFFER,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Writer {
+    Background(mpsc::Sender<Pokemon>),
+    WriteThrough,
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    negative_hits: AtomicU64,
+    evictions: AtomicU64,
+    upstream_errors: AtomicU64,
+    dropped_writes: AtomicU64,
+    writer_restarts: AtomicU64,
+}
+
+impl Counters {
+    fn incr(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Snapshot of the [`CacheLayer`] counters, since its creation.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub negative_hits: u64,
+    pub evictions: u64,
+    pub upstream_errors: u64,
+    pub dropped_writes: u64,
+    pub writer_restarts: u64,
+    pub size: usize,
+    pub negative_size: usize,
 }
 
 impl<R> From<R> for CacheLayer<R>
 where
     R: pokemon::Repository,
 {
+    #[inline]
     fn from(upstream: R) -> CacheLayer<R> {
-        let inmemory = InMemoryRepository::default();
-        let cache = inmemory.clone();
+        CacheLayer::new(upstream, CacheConfig::default())
+    }
+}
 
-        let (tx, mut rx) = mpsc::channel::<Pokemon>(1);
+impl<R> CacheLayer<R>
+where
+    R: pokemon::Repository,
+{
+    pub fn new(upstream: R, config: CacheConfig) -> CacheLayer<R> {
+        let inmemory = match config.capacity {
+            Some(capacity) => InMemoryRepository::with_capacity(capacity),
+            None => InMemoryRepository::default(),
+        };
 
-        // Spawn a thread for background update
-        tokio::spawn(async move {
-            while let Some(pokemon) = rx.recv().await {
-                log::debug!("Updating cache with Pokemon #{}", pokemon.dex_id);
-                cache.backend.write().await.push(pokemon);
+        let counters = Arc::<Counters>::default();
+
+        let writer = match config.write_mode {
+            WriteMode::WriteThrough => Writer::WriteThrough,
+            WriteMode::Background { buffer } => {
+                let (tx, rx) = mpsc::channel::<Pokemon>(buffer.max(1));
+                let rx = Arc::new(Mutex::new(rx));
+
+                tokio::spawn(supervise_writer(inmemory.clone(), counters.clone(), rx));
+                Writer::Background(tx)
             }
-        });
+        };
 
         CacheLayer {
-            upstream,
-            tx,
+            upstream: CoalescingLayer::from(upstream),
+            writer,
             inmemory,
+            negative: Arc::default(),
+            refreshing: Arc::default(),
+            counters,
+            config,
+        }
+    }
+
+    /// Returns the in-memory storage backing the cache.
+    pub fn storage(&self) -> &InMemoryRepository {
+        &self.inmemory
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let counters = &self.counters;
+
+        CacheStats {
+            hits: counters.hits.load(Ordering::Relaxed),
+            misses: counters.misses.load(Ordering::Relaxed),
+            negative_hits: counters.negative_hits.load(Ordering::Relaxed),
+            evictions: counters.evictions.load(Ordering::Relaxed),
+            upstream_errors: counters.upstream_errors.load(Ordering::Relaxed),
+            dropped_writes: counters.dropped_writes.load(Ordering::Relaxed),
+            writer_restarts: counters.writer_restarts.load(Ordering::Relaxed),
+            size: self.inmemory.len().await,
+            negative_size: self.negative.read().await.len(),
+        }
+    }
+
+    async fn is_known_missing(&self, num: u32) -> bool {
+        let ttl = match self.config.negative_ttl {
+            Some(ttl) => ttl,
+            None => return false,
+        };
+
+        match self.negative.read().await.get(&num) {
+            Some(looked_up_at) => looked_up_at.elapsed() <= ttl,
+            None => false,
+        }
+    }
+
+    async fn remember_missing(&self, num: u32) {
+        let ttl = match self.config.negative_ttl {
+            Some(ttl) => ttl,
+            None => return,
+        };
+
+        let mut negative = self.negative.write().await;
+
+        // Keep the negative entries within the same capacity of the cache,
+        // by pruning the expired ones first and giving up if still full.
+        let capacity = self.config.capacity.unwrap_or(MAX_NEGATIVE_ENTRIES);
+
+        if negative.len() >= capacity {
+            negative.retain(|_, looked_up_at| looked_up_at.elapsed() <= ttl);
+        }
+
+        if negative.len() >= capacity {
+            return;
+        }
+
+        negative.insert(num, Instant::now());
+    }
+
+    async fn store(&self, pokemon: Pokemon) {
+        let mut tx = match self.writer {
+            Writer::WriteThrough => return write(&self.inmemory, &self.counters, pokemon).await,
+            Writer::Background(ref tx) => tx.clone(),
+        };
+
+        log::debug!("Sending Pokemon #{} to background writer", pokemon.dex_id);
+
+        match tx.try_send(pokemon) {
+            Ok(()) => (),
+            Err(TrySendError::Full(pokemon)) => {
+                log::warn!(
+                    "Cache write queue full, dropping Pokemon #{}",
+                    pokemon.dex_id
+                );
+                Counters::incr(&self.counters.dropped_writes);
+            }
+            Err(TrySendError::Closed(pokemon)) => {
+                log::error!("Cache writer stopped, dropping Pokemon #{}", pokemon.dex_id);
+                Counters::incr(&self.counters.dropped_writes);
+            }
         }
     }
 }
 
-impl<R> pokemon::Repository for CacheLayer<R>
+async fn write(inmemory: &InMemoryRepository, counters: &Counters, pokemon: Pokemon) {
+    log::debug!("Updating cache with Pokemon #{}", pokemon.dex_id);
+
+    if let Some(evicted) = inmemory.upsert(pokemon).await {
+        log::debug!("Evicted Pokemon #{} from cache", evicted.dex_id);
+        Counters::incr(&counters.evictions);
+    }
+}
+
+/// Runs the background writer, restarting it whenever it fails.
+/// Stops once every [`CacheLayer`] sharing the write queue has been dropped.
+async fn supervise_writer(
+    inmemory: InMemoryRepository,
+    counters: Arc<Counters>,
+    rx: Arc<Mutex<mpsc::Receiver<Pokemon>>>,
+) {
+    loop {
+        let writer = tokio::spawn(run_writer(inmemory.clone(), counters.clone(), rx.clone()));
+
+        match writer.await {
+            Ok(()) => return,
+            Err(err) => {
+                log::error!("Cache background writer failed, restarting: {}", err);
+                Counters::incr(&counters.writer_restarts);
+            }
+        }
+    }
+}
+
+async fn run_writer(
+    inmemory: InMemoryRepository,
+    counters: Arc<Counters>,
+    rx: Arc<Mutex<mpsc::Receiver<Pokemon>>>,
+) {
+    let mut rx = rx.lock().await;
+
+    while let Some(pokemon) = rx.recv().await {
+        write(&inmemory, &counters, pokemon).await;
+    }
+}
+
+impl<R> CacheLayer<R>
 where
-    R: pokemon::Repository + Sync + Send,
+    R: pokemon::Repository + Clone + Send + Sync + 'static,
+    R::Error: Send + Sync,
 {
-    type Error = R::Error;
-
-    fn get<'a>(&'a self, num: u32) -> BoxFuture<'a, Result<Option<Pokemon>, Self::Error>>
-    where
-        Self: Sync + 'a,
-    {
-        Box::pin(async move {
-            if let Some(pokemon) = self.inmemory.get(num).await.unwrap() {
-                log::debug!("Got Pokemon #{} from cache", pokemon.dex_id);
-                return Ok(Some(pokemon));
+    async fn fetch(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Arc<R::Error>> {
+        let result = self
+            .upstream
+            .get_sourced(num)
+            .await
+            .inspect_err(|_| Counters::incr(&self.counters.upstream_errors))?;
+
+        log::debug!("Got Pokemon #{} from upstream", num);
+
+        self.update(num, result.as_ref().map(|(pokemon, _)| pokemon))
+            .await;
+
+        Ok(result)
+    }
+
+    /// Fetches the specified ids in a single upstream batch,
+    /// returning the results in the same order.
+    async fn fetch_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Arc<R::Error>>> {
+        let results = self.upstream.get_many(ids).await;
+
+        for (num, result) in ids.iter().zip(&results) {
+            match result {
+                Ok(pokemon) => self.update(*num, pokemon.as_ref()).await,
+                Err(_) => Counters::incr(&self.counters.upstream_errors),
             }
+        }
+
+        log::debug!("Got {} Pokemons from upstream", ids.len());
 
-            let result = self.upstream.get(num).await?;
-            log::debug!("Got Pokemon #{} from upstream", num);
+        results
+    }
 
-            if let Some(ref pokemon) = result {
-                log::debug!("Sending Pokemon #{} to background thread", pokemon.dex_id);
-                self.tx.clone().send(pokemon.clone()).await.unwrap();
+    /// Updates the cache with the result of an upstream lookup.
+    async fn update(&self, num: u32, pokemon: Option<&Pokemon>) {
+        match pokemon {
+            Some(pokemon) => {
+                self.negative.write().await.remove(&num);
+                self.store(pokemon.clone()).await;
+            }
+            // Drop the expired entry, if any, since it's gone upstream.
+            None => {
+                self.inmemory.remove(num).await;
+                self.remember_missing(num).await;
+            }
+        }
+    }
+
+    /// Looks up the Pokémon in the cache alone, refreshing it in background
+    /// if stale and allowed to.
+    async fn lookup(&self, num: u32) -> Lookup {
+        let (pokemon, age) = match self.inmemory.get_with_age(num).await {
+            Some(cached) => cached,
+            None if self.is_known_missing(num).await => {
+                log::debug!("Pokemon #{} known to be missing upstream", num);
+                Counters::incr(&self.counters.negative_hits);
+                return Lookup::Missing;
+            }
+            None => {
+                Counters::incr(&self.counters.misses);
+                return Lookup::Miss;
             }
+        };
+
+        match self.config.ttl {
+            Some(ttl) if age > ttl && self.config.stale_while_revalidate => {
+                log::debug!("Got stale Pokemon #{} from cache, refreshing", num);
+                Counters::incr(&self.counters.hits);
+
+                self.refresh(num);
+                Lookup::Hit(pokemon)
+            }
+            Some(ttl) if age > ttl => {
+                log::debug!("Pokemon #{} in cache has expired", num);
+                Counters::incr(&self.counters.misses);
+                Lookup::Miss
+            }
+            _ => {
+                log::debug!("Got Pokemon #{} from cache", num);
+                Counters::incr(&self.counters.hits);
+                Lookup::Hit(pokemon)
+            }
+        }
+    }
+}
+
+/// Outcome of a lookup in the cache alone.
+enum Lookup {
+    Hit(Pokemon),
+    /// Known to be missing upstream.
+    Missing,
+    /// To be fetched from upstream.
+    Miss,
+}
+
+impl<R> CacheLayer<R>
+where
+    R: pokemon::Repository + Clone + Send + Sync + 'static,
+    R::Error: Send + Sync,
+{
+    /// Fetches the Pokémon again in background, unless it's already being refreshed.
+    fn refresh(&self, num: u32) {
+        let refreshing = self.refreshing.clone();
+
+        if !lock(&refreshing).insert(num) {
+            log::debug!("Pokemon #{} already being refreshed", num);
+            return;
+        }
+
+        let cache = self.clone();
+        tokio::spawn(async move {
+            let _refreshing = Refreshing {
+                ids: refreshing,
+                num,
+            };
+
+            if let Err(err) = cache.fetch(num).await {
+                log::warn!("Failed to refresh Pokemon #{}: {}", num, err);
+            }
+        });
+    }
+}
+
+/// Marks a refresh done once dropped, even if the refresh panics.
+struct Refreshing {
+    ids: Arc<std::sync::Mutex<HashSet<u32>>>,
+    num: u32,
+}
+
+impl Drop for Refreshing {
+    fn drop(&mut self) {
+        lock(&self.ids).remove(&self.num);
+    }
+}
+
+fn lock<T>(mutex: &std::sync::Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl<R> pokemon::Repository for CacheLayer<R>
+where
+    R: pokemon::Repository + Clone + Send + Sync + 'static,
+    R::Error: Send + Sync,
+{
+    type Error = Arc<R::Error>;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        match self.lookup(num).await {
+            Lookup::Hit(pokemon) => Ok(Some((pokemon, "cache"))),
+            Lookup::Missing => Ok(None),
+            Lookup::Miss => self.fetch(num).await,
+        }
+    }
+
+    /// Serves the cached Pokémons, fetching all the others in a single upstream batch.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        let mut results = Vec::with_capacity(ids.len());
+        let mut misses = Vec::new();
+
+        for num in ids {
+            results.push(match self.lookup(*num).await {
+                Lookup::Hit(pokemon) => Some(Ok(Some(pokemon))),
+                Lookup::Missing => Some(Ok(None)),
+                Lookup::Miss => {
+                    misses.push(*num);
+                    None
+                }
+            });
+        }
+
+        if misses.is_empty() {
+            return results.into_iter().flatten().collect();
+        }
+
+        let mut fetched = self.fetch_many(&misses).await.into_iter();
+
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| fetched.next().expect("missing upstream result"))
+            })
+            .collect()
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        self.upstream.probe().await
+    }
+}
+
+impl<R> pokemon::Catalogue for CacheLayer<R>
+where
+    R: pokemon::Repository + pokemon::Catalogue + Send + Sync,
+    <R as pokemon::Repository>::Error: Send + Sync,
+{
+    type Error = <R as pokemon::Catalogue>::Error;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        // Only the Pokémons looked up so far are cached: list them all from upstream.
+        self.upstream.list().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poke_layer::fault::FaultConfig;
+    use poke_layer::{FaultLayer, Layer};
+
+    use super::*;
+    use crate::testing::Stub;
+
+    fn config() -> CacheConfig {
+        CacheConfig {
+            write_mode: WriteMode::WriteThrough,
+            ..CacheConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn write_through_caches_before_returning() {
+        let upstream = Stub::with(vec![25]);
+        let cache = CacheLayer::new(upstream.clone(), config());
+
+        assert!(cache.get(25).await.unwrap().is_some());
+        assert_eq!(cache.stats().await.size, 1);
+
+        assert!(cache.get(25).await.unwrap().is_some());
+        assert_eq!(upstream.calls(), 1);
+
+        let stats = cache.stats().await;
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn names_the_source_of_lookups() {
+        let cache = CacheLayer::new(Stub::with(vec![25]), config());
+
+        let (_, source) = cache.get_sourced(25).await.unwrap().unwrap();
+        assert_eq!(source, "upstream");
+
+        let (_, source) = cache.get_sourced(25).await.unwrap().unwrap();
+        assert_eq!(source, "cache");
+    }
+
+    #[tokio::test]
+    async fn write_through_counts_evictions() {
+        let cache = CacheLayer::new(
+            Stub::with(vec![1, 25]),
+            CacheConfig {
+                capacity: Some(1),
+                ..config()
+            },
+        );
+
+        cache.get(1).await.unwrap();
+        cache.get(25).await.unwrap();
+
+        let stats = cache.stats().await;
+        assert_eq!((stats.size, stats.evictions), (1, 1));
+        assert!(cache.storage().get_with_age(25).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn background_writes_are_eventually_cached() {
+        let upstream = Stub::with(vec![25]);
+        let cache = CacheLayer::new(upstream.clone(), CacheConfig::default());
+
+        assert!(cache.get(25).await.unwrap().is_some());
+        tokio::time::delay_for(Duration::from_millis(10)).await;
+
+        assert_eq!(cache.stats().await.size, 1);
+        assert!(cache.get(25).await.unwrap().is_some());
+        assert_eq!(upstream.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn stale_hits_share_one_refresh() {
+        let upstream = Stub::with(vec![25]).slow(Duration::from_millis(50));
+        let cache = CacheLayer::new(
+            upstream.clone(),
+            CacheConfig {
+                ttl: Some(Duration::from_millis(1)),
+                stale_while_revalidate: true,
+                ..config()
+            },
+        );
+
+        cache.get(25).await.unwrap();
+        tokio::time::delay_for(Duration::from_millis(5)).await;
+
+        for _ in 0..5 {
+            assert!(cache.get(25).await.unwrap().is_some());
+        }
+
+        assert_eq!(lock(&cache.refreshing).len(), 1);
+
+        tokio::time::delay_for(Duration::from_millis(100)).await;
+
+        assert!(lock(&cache.refreshing).is_empty());
+        assert_eq!(upstream.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn not_found_results_are_remembered() {
+        let upstream = Stub::default();
+        let cache = CacheLayer::new(
+            upstream.clone(),
+            CacheConfig {
+                negative_ttl: Some(Duration::from_millis(20)),
+                ..config()
+            },
+        );
+
+        assert_eq!(cache.get(99999).await.unwrap(), None);
+        assert_eq!(cache.get(99999).await.unwrap(), None);
+        assert_eq!(upstream.calls(), 1);
+
+        let stats = cache.stats().await;
+        assert_eq!((stats.misses, stats.negative_hits), (1, 1));
+        assert_eq!(stats.upstream_errors, 0);
+
+        tokio::time::delay_for(Duration::from_millis(30)).await;
+
+        assert_eq!(cache.get(99999).await.unwrap(), None);
+        assert_eq!(upstream.calls(), 2);
+    }
+
+    #[tokio::test]
+    async fn not_found_results_are_bounded() {
+        let cache = CacheLayer::new(
+            Stub::default(),
+            CacheConfig {
+                capacity: Some(2),
+                negative_ttl: Some(Duration::from_millis(20)),
+                ..config()
+            },
+        );
+
+        for id in 1..=3 {
+            cache.get(id).await.unwrap();
+        }
+
+        assert_eq!(cache.stats().await.negative_size, 2);
+
+        // Expired entries are pruned to make room for new ones.
+        tokio::time::delay_for(Duration::from_millis(30)).await;
+        cache.get(4).await.unwrap();
+
+        assert_eq!(cache.stats().await.negative_size, 1);
+    }
+
+    #[tokio::test]
+    async fn not_found_results_are_bounded_without_capacity() {
+        let cache = CacheLayer::new(
+            Stub::default(),
+            CacheConfig {
+                negative_ttl: Some(Duration::from_secs(60)),
+                ..config()
+            },
+        );
+
+        for id in 0..=MAX_NEGATIVE_ENTRIES as u32 {
+            cache.get(id).await.unwrap();
+        }
+
+        assert_eq!(cache.stats().await.negative_size, MAX_NEGATIVE_ENTRIES);
+    }
+
+    #[tokio::test]
+    async fn batches_fetch_the_misses_only() {
+        let upstream = Stub::with(vec![1, 2, 3]);
+        let cache = CacheLayer::new(
+            upstream.clone(),
+            CacheConfig {
+                negative_ttl: Some(Duration::from_secs(60)),
+                ..config()
+            },
+        );
+
+        cache.get(1).await.unwrap();
+
+        let found: Vec<_> = cache
+            .get_many(&[2, 1, 99999, 3])
+            .await
+            .into_iter()
+            .map(|result| result.unwrap().map(|pokemon| pokemon.dex_id))
+            .collect();
+
+        assert_eq!(found, vec![Some(2), Some(1), None, Some(3)]);
+        assert_eq!(upstream.batches(), vec![vec![2, 99999, 3]]);
+
+        // Both the found and the missing Pokémons are cached.
+        let results = cache.get_many(&[3, 99999]).await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(upstream.batches().len(), 1);
+
+        let stats = cache.stats().await;
+        assert_eq!((stats.size, stats.negative_size), (3, 1));
+        assert_eq!((stats.hits, stats.negative_hits), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn batches_count_upstream_errors() {
+        let upstream = Stub::with(vec![1, 2]);
+        let cache = CacheLayer::new(upstream.clone(), config());
+
+        upstream.set_failing(true);
+
+        let results = cache.get_many(&[1, 2]).await;
+        assert!(results.iter().all(Result::is_err));
+
+        let stats = cache.stats().await;
+        assert_eq!((stats.size, stats.upstream_errors), (0, 2));
+    }
+
+    #[tokio::test]
+    async fn serves_hits_while_the_upstream_fails() {
+        let upstream = Stub::with(vec![25]);
+        let faults = FaultLayer::new(FaultConfig {
+            seed: 42,
+            error_rate: 0.5,
+            ..FaultConfig::default()
+        });
+        let cache = CacheLayer::new(faults.layer(upstream.clone()), config());
+
+        let mut results = Vec::new();
+
+        for _ in 0..20 {
+            results.push(cache.get(25).await.is_ok());
+        }
+
+        // Failures are never cached: once found, the Pokémon is always served.
+        let first_hit = results.iter().position(|ok| *ok).unwrap();
+        assert!(results[first_hit..].iter().all(|ok| *ok));
+        assert_eq!(upstream.calls(), 1);
 
-            Ok(result)
-        })
+        let stats = cache.stats().await;
+        assert_eq!(stats.upstream_errors, first_hit as u64);
+        assert_eq!(stats.hits, (results.len() - first_hit - 1) as u64);
     }
 }
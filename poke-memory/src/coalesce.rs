@@ -0,0 +1,198 @@
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+use std::sync::{Arc, Mutex, PoisonError};
+
+use futures::future::{BoxFuture, FutureExt, Shared};
+
+use poke_domain::{pokemon, pokemon::Pokemon, pokemon::Source};
+
+type SharedGet<E> = Shared<BoxFuture<'static, Result<Option<(Pokemon, Source)>, Arc<E>>>>;
+
+/// Repository decorator deduplicating concurrent lookups for the same id:
+/// only one upstream call is in flight per id, and all the concurrent
+/// callers receive its result.
+///
+/// Since the result is shared, upstream errors are wrapped in an `Arc`.
+pub struct CoalescingLayer<R>
+where
+    R: pokemon::Repository,
+{
+    upstream: R,
+    inflight: Arc<Mutex<HashMap<u32, SharedGet<R::Error>>>>,
+}
+
+impl<R> Clone for CoalescingLayer<R>
+where
+    R: pokemon::Repository + Clone,
+{
+    fn clone(&self) -> Self {
+        CoalescingLayer {
+            upstream: self.upstream.clone(),
+            inflight: self.inflight.clone(),
+        }
+    }
+}
+
+impl<R> From<R> for CoalescingLayer<R>
+where
+    R: pokemon::Repository,
+{
+    fn from(upstream: R) -> CoalescingLayer<R> {
+        CoalescingLayer {
+            upstream,
+            inflight: Arc::default(),
+        }
+    }
+}
+
+impl<R> pokemon::Repository for CoalescingLayer<R>
+where
+    R: pokemon::Repository + Clone + Send + Sync + 'static,
+    R::Error: Send + Sync,
+{
+    type Error = Arc<R::Error>;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        let future = self
+            .inflight
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .entry(num)
+            .or_insert_with(|| {
+                let upstream = self.upstream.clone();
+                let inflight = self.inflight.clone();
+
+                async move {
+                    let result = AssertUnwindSafe(upstream.get_sourced(num))
+                        .catch_unwind()
+                        .await;
+
+                    // Always clear the in-flight lookup, even on panic, so that
+                    // the next lookups for the same id are not poisoned.
+                    inflight
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner)
+                        .remove(&num);
+
+                    match result {
+                        Ok(result) => result.map_err(Arc::new),
+                        Err(panic) => std::panic::resume_unwind(panic),
+                    }
+                }
+                .boxed()
+                .shared()
+            })
+            .clone();
+
+        future.await
+    }
+
+    /// Batches are forwarded as they are, without deduplication.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        self.upstream
+            .get_many(ids)
+            .await
+            .into_iter()
+            .map(|result| result.map_err(Arc::new))
+            .collect()
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        self.upstream.probe().await.map_err(Arc::new)
+    }
+}
+
+impl<R> pokemon::Catalogue for CoalescingLayer<R>
+where
+    R: pokemon::Repository + pokemon::Catalogue + Send + Sync,
+    <R as pokemon::Repository>::Error: Send + Sync,
+{
+    type Error = <R as pokemon::Catalogue>::Error;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        self.upstream.list().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    use futures::future::join_all;
+
+    use super::*;
+    use crate::testing::{self, Stub, StubError};
+
+    use poke_domain::pokemon::Repository;
+
+    /// Upstream panicking on its first lookup.
+    #[derive(Clone, Default)]
+    struct PanicOnce(Arc<AtomicUsize>);
+
+    impl pokemon::Repository for PanicOnce {
+        type Error = StubError;
+
+        async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+            if self.0.fetch_add(1, Ordering::SeqCst) == 0 {
+                panic!("upstream panicked");
+            }
+
+            Ok(Some(testing::pokemon(num)))
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_lookups_reach_the_upstream_once() {
+        let upstream = Stub::with(vec![25]).slow(Duration::from_millis(50));
+        let layer = CoalescingLayer::from(upstream.clone());
+
+        let results = join_all((0..4).map(|_| layer.get(25))).await;
+
+        assert!(results
+            .iter()
+            .all(|result| matches!(result, Ok(Some(pokemon)) if pokemon.dex_id == 25)));
+        assert_eq!(upstream.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn errors_are_shared_by_every_caller() {
+        let upstream = Stub::with(vec![25]).slow(Duration::from_millis(50));
+        upstream.set_failing(true);
+        let layer = CoalescingLayer::from(upstream.clone());
+
+        let (first, second) = futures::join!(layer.get(25), layer.get(25));
+
+        assert!(Arc::ptr_eq(&first.unwrap_err(), &second.unwrap_err()));
+        assert_eq!(upstream.calls(), 1);
+    }
+
+    #[tokio::test]
+    async fn later_lookups_reach_the_upstream_again() {
+        let upstream = Stub::with(vec![25]);
+        let layer = CoalescingLayer::from(upstream.clone());
+
+        layer.get(25).await.unwrap();
+        layer.get(25).await.unwrap();
+
+        assert_eq!(upstream.calls(), 2);
+        assert!(layer.inflight.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn panics_clear_the_inflight_lookup() {
+        let upstream = PanicOnce::default();
+        let layer = CoalescingLayer::from(upstream.clone());
+
+        let result = AssertUnwindSafe(layer.get(25)).catch_unwind().await;
+        assert!(result.is_err());
+        assert!(layer.inflight.lock().unwrap().is_empty());
+
+        assert!(layer.get(25).await.unwrap().is_some());
+        assert_eq!(upstream.0.load(Ordering::SeqCst), 2);
+    }
+}
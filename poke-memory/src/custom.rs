@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use poke_domain::pokemon::{self, Pokemon, Source, WriteError};
+
+use crate::InMemoryRepository;
+
+/// Repository decorator serving custom Pokémons (e.g. fakemons or
+/// event-exclusive variants) next to the ones found upstream.
+///
+/// Custom Pokémons take precedence over upstream ones with the same id,
+/// and are the only ones modified through [`WritableRepository`].
+///
+/// When a snapshot file is set, the custom Pokémons are saved to it
+/// after every modification, one save at a time.
+///
+/// [`WritableRepository`]: pokemon::WritableRepository
+#[derive(Clone)]
+pub struct CustomLayer<R> {
+    upstream: R,
+    custom: InMemoryRepository,
+    snapshot: Option<Arc<PathBuf>>,
+    // Held while saving, so that concurrent saves can't finish out of order,
+    // replacing the snapshot of a modification with an older one.
+    saving: Arc<Mutex<()>>,
+}
+
+impl<R> From<R> for CustomLayer<R> {
+    #[inline]
+    fn from(upstream: R) -> CustomLayer<R> {
+        CustomLayer::new(upstream, InMemoryRepository::default())
+    }
+}
+
+impl<R> CustomLayer<R> {
+    pub fn new(upstream: R, custom: InMemoryRepository) -> CustomLayer<R> {
+        CustomLayer {
+            upstream,
+            custom,
+            snapshot: None,
+            saving: Arc::default(),
+        }
+    }
+
+    /// Saves the custom Pokémons to the specified file after every modification.
+    pub fn with_snapshot(mut self, path: PathBuf) -> CustomLayer<R> {
+        self.snapshot = Some(Arc::new(path));
+        self
+    }
+
+    /// Returns the in-memory storage holding the custom Pokémons.
+    pub fn storage(&self) -> &InMemoryRepository {
+        &self.custom
+    }
+}
+
+impl<R> CustomLayer<R>
+where
+    R: pokemon::Repository,
+{
+    async fn persist<T>(
+        &self,
+        result: Result<T, WriteError<Infallible>>,
+    ) -> Result<T, WriteError<R::Error>> {
+        let value = result.map_err(widen)?;
+
+        if let Some(ref path) = self.snapshot {
+            let _saving = self.saving.lock().await;
+
+            if let Err(err) = self.custom.save_snapshot(path).await {
+                log::error!("Failed to save custom Pokemons to {:?}: {}", path, err);
+            }
+        }
+
+        Ok(value)
+    }
+}
+
+fn widen<E>(error: WriteError<Infallible>) -> WriteError<E> {
+    match error {
+        WriteError::AlreadyExists { dex_id } => WriteError::AlreadyExists { dex_id },
+        WriteError::NotFound { dex_id } => WriteError::NotFound { dex_id },
+        WriteError::RepositoryError(never) => match never {},
+    }
+}
+
+impl<R> pokemon::Repository for CustomLayer<R>
+where
+    R: pokemon::Repository + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        if let Ok(Some(pokemon)) = self.custom.get(num).await {
+            log::debug!("Got custom Pokemon #{}", num);
+            return Ok(Some((pokemon, "custom")));
+        }
+
+        self.upstream.get_sourced(num).await
+    }
+
+    /// Serves the custom Pokémons, forwarding all the others in a single batch.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>>
+    where
+        Self::Error: Send,
+    {
+        let mut results = Vec::with_capacity(ids.len());
+        let mut rest = Vec::new();
+
+        for num in ids {
+            match self.custom.get(*num).await {
+                Ok(Some(pokemon)) => results.push(Some(Ok(Some(pokemon)))),
+                _ => {
+                    rest.push(*num);
+                    results.push(None);
+                }
+            }
+        }
+
+        if rest.is_empty() {
+            return results.into_iter().flatten().collect();
+        }
+
+        let mut forwarded = self.upstream.get_many(&rest).await.into_iter();
+
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| forwarded.next().expect("missing upstream result"))
+            })
+            .collect()
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        self.upstream.probe().await
+    }
+}
+
+impl<R> pokemon::WritableRepository for CustomLayer<R>
+where
+    R: pokemon::Repository + Send + Sync,
+{
+    async fn insert(&self, pokemon: Pokemon) -> Result<(), WriteError<Self::Error>> {
+        self.persist(self.custom.insert(pokemon).await).await
+    }
+
+    async fn update(&self, pokemon: Pokemon) -> Result<(), WriteError<Self::Error>> {
+        self.persist(self.custom.update(pokemon).await).await
+    }
+
+    async fn delete(&self, num: u32) -> Result<Pokemon, WriteError<Self::Error>> {
+        self.persist(self.custom.delete(num).await).await
+    }
+}
+
+impl<R> pokemon::Catalogue for CustomLayer<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        let mut entries: HashMap<u32, String> = self.upstream.list().await?.into_iter().collect();
+
+        let data = self.custom.backend.read().await;
+        for pokemon in data.pokemons() {
+            entries.insert(pokemon.dex_id, pokemon.name.clone());
+        }
+
+        Ok(entries.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{pokemon, temp_path, Stub};
+
+    use poke_domain::pokemon::{Repository, WritableRepository};
+
+    #[tokio::test]
+    async fn custom_pokemons_take_precedence() {
+        let layer = CustomLayer::from(Stub::with(vec![1, 25]));
+        layer.insert(pokemon(10001)).await.unwrap();
+
+        let mut replacement = pokemon(25);
+        replacement.name = "pikachu-rock-star".to_owned();
+        layer.insert(replacement.clone()).await.unwrap();
+
+        let sourced = layer.get_sourced(25).await.unwrap();
+        assert_eq!(sourced, Some((replacement, "custom")));
+
+        let (_, source) = layer.get_sourced(1).await.unwrap().unwrap();
+        assert_eq!(source, "upstream");
+
+        let (_, source) = layer.get_sourced(10001).await.unwrap().unwrap();
+        assert_eq!(source, "custom");
+    }
+
+    #[tokio::test]
+    async fn batches_forward_the_upstream_pokemons_only() {
+        let upstream = Stub::with(vec![1, 25]);
+        let layer = CustomLayer::from(upstream.clone());
+        layer.insert(pokemon(10001)).await.unwrap();
+
+        let found: Vec<_> = layer
+            .get_many(&[25, 10001, 1, 99999])
+            .await
+            .into_iter()
+            .map(|result| result.unwrap().map(|pokemon| pokemon.dex_id))
+            .collect();
+
+        assert_eq!(found, vec![Some(25), Some(10001), Some(1), None]);
+        assert_eq!(upstream.batches(), vec![vec![25, 1, 99999]]);
+    }
+
+    #[tokio::test]
+    async fn snapshot_follows_concurrent_writes() {
+        let path = temp_path("custom-concurrent.json");
+        let layer = CustomLayer::from(Stub::default()).with_snapshot(path.clone());
+
+        let inserts = (10001..=10050).map(|id| layer.insert(pokemon(id)));
+
+        for result in futures::future::join_all(inserts).await {
+            result.unwrap();
+        }
+
+        let restored = InMemoryRepository::default();
+        assert_eq!(restored.load_snapshot(&path).await.unwrap(), 50);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,559 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use poke_domain::pokemon::{self, Pokemon, Source};
+
+/// When to fall back to the secondary repository.
+#[derive(Clone, Debug)]
+pub struct FallbackPolicy {
+    /// Falls back when the primary repository fails.
+    pub on_error: bool,
+
+    /// Falls back when the primary repository doesn't find the Pokémon.
+    pub on_not_found: bool,
+
+    /// Falls back when the primary repository doesn't answer in time.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for FallbackPolicy {
+    fn default() -> Self {
+        FallbackPolicy {
+            on_error: true,
+            on_not_found: false,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct FallbackStats {
+    pub primary: u64,
+    pub secondary: u64,
+    pub primary_errors: u64,
+    pub primary_timeouts: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    primary: AtomicU64,
+    secondary: AtomicU64,
+    primary_errors: AtomicU64,
+    primary_timeouts: AtomicU64,
+}
+
+/// Repository combinator trying a primary repository first, and falling back
+/// to a secondary one according to the [`FallbackPolicy`], e.g. to serve
+/// Pokémons from an offline dataset while pokeapi.co is unavailable.
+#[derive(Clone)]
+pub struct FallbackLayer<P, S> {
+    primary: P,
+    secondary: S,
+    policy: FallbackPolicy,
+    counters: Arc<Counters>,
+}
+
+enum Outcome<T, E> {
+    Served(T),
+    Fallback,
+    Failed(E),
+}
+
+impl<P, S> FallbackLayer<P, S> {
+    pub fn new(primary: P, secondary: S, policy: FallbackPolicy) -> FallbackLayer<P, S> {
+        FallbackLayer {
+            primary,
+            secondary,
+            policy,
+            counters: Arc::default(),
+        }
+    }
+
+    pub fn stats(&self) -> FallbackStats {
+        FallbackStats {
+            primary: self.counters.primary.load(Ordering::Relaxed),
+            secondary: self.counters.secondary.load(Ordering::Relaxed),
+            primary_errors: self.counters.primary_errors.load(Ordering::Relaxed),
+            primary_timeouts: self.counters.primary_timeouts.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Runs the primary lookup, deciding whether to fall back
+    /// according to the policy.
+    async fn try_primary<T, E, F>(
+        &self,
+        what: &str,
+        lookup: F,
+        found: fn(&T) -> bool,
+    ) -> Outcome<T, E>
+    where
+        F: Future<Output = Result<T, E>>,
+        E: std::error::Error,
+    {
+        match self.within_timeout(what, lookup).await {
+            None => Outcome::Fallback,
+            Some(result) => self.decide(what, result, found),
+        }
+    }
+
+    /// Runs the primary lookup within the timeout of the policy, if any.
+    async fn within_timeout<F>(&self, what: &str, lookup: F) -> Option<F::Output>
+    where
+        F: Future,
+    {
+        let timeout = match self.policy.timeout {
+            None => return Some(lookup.await),
+            Some(timeout) => timeout,
+        };
+
+        match tokio::time::timeout(timeout, lookup).await {
+            Ok(result) => Some(result),
+            Err(_) => {
+                log::warn!("Primary repository timed out on {}, falling back", what);
+                self.counters
+                    .primary_timeouts
+                    .fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    /// Decides whether to fall back on the result of the primary lookup.
+    fn decide<T, E>(&self, what: &str, result: Result<T, E>, found: fn(&T) -> bool) -> Outcome<T, E>
+    where
+        E: std::error::Error,
+    {
+        match result {
+            Ok(value) if !found(&value) && self.policy.on_not_found => Outcome::Fallback,
+            Ok(value) => Outcome::Served(value),
+            Err(err) => {
+                self.counters.primary_errors.fetch_add(1, Ordering::Relaxed);
+
+                if !self.policy.on_error {
+                    return Outcome::Failed(err);
+                }
+
+                log::warn!(
+                    "Primary repository failed on {}, falling back: {}",
+                    what,
+                    err
+                );
+                Outcome::Fallback
+            }
+        }
+    }
+}
+
+impl<P, S> pokemon::Repository for FallbackLayer<P, S>
+where
+    P: pokemon::Repository + Send + Sync,
+    S: pokemon::Repository + Send + Sync,
+    P::Error: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Error = FallbackError<P::Error, S::Error>;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        let what = format!("Pokemon #{}", num);
+
+        match self
+            .try_primary(&what, self.primary.get_sourced(num), Option::is_some)
+            .await
+        {
+            Outcome::Served(result) => {
+                self.counters.primary.fetch_add(1, Ordering::Relaxed);
+                return Ok(result);
+            }
+            Outcome::Failed(err) => return Err(FallbackError::Primary(err)),
+            Outcome::Fallback => (),
+        }
+
+        let result = self
+            .secondary
+            .get_sourced(num)
+            .await
+            .map_err(FallbackError::Secondary)?;
+
+        self.counters.secondary.fetch_add(1, Ordering::Relaxed);
+        log::debug!("Pokemon #{} served by secondary repository", num);
+
+        Ok(result)
+    }
+
+    /// Looks up all the ids in a single primary batch, and the ones
+    /// to fall back on in a single secondary batch.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        let what = format!("{} Pokemons", ids.len());
+
+        // Results served by (or failed on) the primary repository,
+        // `None` for the ids to fall back on.
+        let results: Vec<Option<Result<_, Self::Error>>> =
+            match self.within_timeout(&what, self.primary.get_many(ids)).await {
+                None => ids.iter().map(|_| None).collect(),
+                Some(results) => ids
+                    .iter()
+                    .zip(results)
+                    .map(|(num, result)| {
+                        let what = format!("Pokemon #{}", num);
+
+                        match self.decide(&what, result, Option::is_some) {
+                            Outcome::Served(result) => {
+                                self.counters.primary.fetch_add(1, Ordering::Relaxed);
+                                Some(Ok(result))
+                            }
+                            Outcome::Failed(err) => Some(Err(FallbackError::Primary(err))),
+                            Outcome::Fallback => None,
+                        }
+                    })
+                    .collect(),
+            };
+
+        let fallbacks: Vec<u32> = ids
+            .iter()
+            .zip(&results)
+            .filter(|(_, result)| result.is_none())
+            .map(|(num, _)| *num)
+            .collect();
+
+        if fallbacks.is_empty() {
+            return results.into_iter().flatten().collect();
+        }
+
+        let mut secondary = self.secondary.get_many(&fallbacks).await.into_iter();
+
+        log::debug!(
+            "{} Pokemons served by secondary repository",
+            fallbacks.len()
+        );
+
+        results
+            .into_iter()
+            .map(|result| {
+                result.unwrap_or_else(|| {
+                    secondary
+                        .next()
+                        .expect("missing secondary result")
+                        .inspect(|_| {
+                            self.counters.secondary.fetch_add(1, Ordering::Relaxed);
+                        })
+                        .map_err(FallbackError::Secondary)
+                })
+            })
+            .collect()
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        // Lookups can be served as long as either repository can serve them.
+        match self.primary.probe().await {
+            Ok(()) => return Ok(()),
+            Err(err) if !self.policy.on_error => return Err(FallbackError::Primary(err)),
+            Err(err) => log::warn!("Primary repository probe failed: {}", err),
+        }
+
+        self.secondary
+            .probe()
+            .await
+            .map_err(FallbackError::Secondary)
+    }
+}
+
+impl<P, S> pokemon::Catalogue for FallbackLayer<P, S>
+where
+    P: pokemon::Catalogue + Send + Sync,
+    S: pokemon::Catalogue + Send + Sync,
+    P::Error: Send + 'static,
+    S::Error: Send + 'static,
+{
+    type Error = FallbackError<P::Error, S::Error>;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        let served = match self
+            .try_primary("catalogue", self.primary.list(), |list| !list.is_empty())
+            .await
+        {
+            Outcome::Served(list) => Some(list),
+            Outcome::Failed(err) => return Err(FallbackError::Primary(err)),
+            Outcome::Fallback => None,
+        };
+
+        match served {
+            Some(list) => Ok(list),
+            None => self
+                .secondary
+                .list()
+                .await
+                .map_err(FallbackError::Secondary),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum FallbackError<P, S> {
+    /// The primary repository failed, and the policy doesn't allow falling back.
+    Primary(P),
+    /// The secondary repository failed after falling back.
+    Secondary(S),
+}
+
+impl<P, S> std::error::Error for FallbackError<P, S>
+where
+    P: std::error::Error + 'static,
+    S: std::error::Error + 'static,
+{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FallbackError::Primary(inner) => Some(inner),
+            FallbackError::Secondary(inner) => Some(inner),
+        }
+    }
+}
+
+impl<P, S> Display for FallbackError<P, S>
+where
+    P: std::error::Error,
+    S: std::error::Error,
+{
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        match self {
+            FallbackError::Primary(inner) => write!(f, "primary repository failed: {}", inner),
+            FallbackError::Secondary(inner) => {
+                write!(f, "secondary repository failed: {}", inner)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Stub;
+
+    use poke_domain::pokemon::Repository;
+
+    fn dex_ids<E>(results: Vec<Result<Option<Pokemon>, E>>) -> Vec<Option<u32>>
+    where
+        E: std::fmt::Debug,
+    {
+        results
+            .into_iter()
+            .map(|result| result.unwrap().map(|pokemon| pokemon.dex_id))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn falls_back_on_primary_errors() {
+        let primary = Stub::with(vec![1]);
+        let secondary = Stub::with(vec![1]);
+        let fallback = FallbackLayer::new(
+            primary.clone(),
+            secondary.clone(),
+            FallbackPolicy::default(),
+        );
+
+        assert!(fallback.get(1).await.unwrap().is_some());
+        assert_eq!(secondary.calls(), 0);
+
+        primary.set_failing(true);
+
+        assert!(fallback.get(1).await.unwrap().is_some());
+        assert_eq!(secondary.calls(), 1);
+
+        assert_eq!(
+            fallback.stats(),
+            FallbackStats {
+                primary: 1,
+                secondary: 1,
+                primary_errors: 1,
+                primary_timeouts: 0,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn falls_back_on_missing_pokemons_only_if_enabled() {
+        let primary = Stub::with(vec![1]);
+        let secondary = Stub::with(vec![2]);
+
+        let strict = FallbackLayer::new(
+            primary.clone(),
+            secondary.clone(),
+            FallbackPolicy::default(),
+        );
+
+        assert!(strict.get(2).await.unwrap().is_none());
+        assert_eq!(secondary.calls(), 0);
+        assert_eq!(strict.stats().primary, 1);
+
+        let fallback = FallbackLayer::new(
+            primary,
+            secondary.clone(),
+            FallbackPolicy {
+                on_not_found: true,
+                ..FallbackPolicy::default()
+            },
+        );
+
+        assert_eq!(fallback.get(2).await.unwrap().unwrap().dex_id, 2);
+        assert_eq!(fallback.get(1).await.unwrap().unwrap().dex_id, 1);
+        assert_eq!(secondary.calls(), 1);
+
+        let stats = fallback.stats();
+        assert_eq!((stats.primary, stats.secondary), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn falls_back_on_timeouts() {
+        let primary = Stub::with(vec![1]).slow(Duration::from_millis(50));
+        let secondary = Stub::with(vec![1]);
+        let fallback = FallbackLayer::new(
+            primary,
+            secondary.clone(),
+            FallbackPolicy {
+                timeout: Some(Duration::from_millis(10)),
+                ..FallbackPolicy::default()
+            },
+        );
+
+        let (pokemon, _) = fallback.get_sourced(1).await.unwrap().unwrap();
+        assert_eq!(pokemon.dex_id, 1);
+        assert_eq!(secondary.calls(), 1);
+
+        assert_eq!(
+            fallback.stats(),
+            FallbackStats {
+                primary: 0,
+                secondary: 1,
+                primary_errors: 0,
+                primary_timeouts: 1,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn returns_the_errors_it_cannot_fall_back_on() {
+        let primary = Stub::with(vec![1]);
+        let secondary = Stub::with(vec![1]);
+        primary.set_failing(true);
+
+        let strict = FallbackLayer::new(
+            primary.clone(),
+            secondary.clone(),
+            FallbackPolicy {
+                on_error: false,
+                ..FallbackPolicy::default()
+            },
+        );
+
+        assert!(matches!(
+            strict.get(1).await,
+            Err(FallbackError::Primary(_))
+        ));
+        assert_eq!(secondary.calls(), 0);
+        assert_eq!(strict.stats().primary_errors, 1);
+
+        let fallback = FallbackLayer::new(primary, secondary.clone(), FallbackPolicy::default());
+        secondary.set_failing(true);
+
+        assert!(matches!(
+            fallback.get_sourced(1).await,
+            Err(FallbackError::Secondary(_))
+        ));
+        assert_eq!(fallback.stats().secondary, 0);
+    }
+
+    #[tokio::test]
+    async fn batches_fall_back_on_primary_errors() {
+        let primary = Stub::with(vec![1, 2]);
+        let secondary = Stub::with(vec![1, 2]);
+        let fallback = FallbackLayer::new(
+            primary.clone(),
+            secondary.clone(),
+            FallbackPolicy::default(),
+        );
+
+        primary.set_failing(true);
+
+        assert_eq!(
+            dex_ids(fallback.get_many(&[1, 2, 3]).await),
+            vec![Some(1), Some(2), None]
+        );
+        assert_eq!(secondary.batches(), vec![vec![1, 2, 3]]);
+
+        let stats = fallback.stats();
+        assert_eq!((stats.primary_errors, stats.secondary), (3, 3));
+    }
+
+    #[tokio::test]
+    async fn batches_fall_back_on_the_missing_pokemons_only() {
+        let primary = Stub::with(vec![1]);
+        let secondary = Stub::with(vec![2]);
+        let fallback = FallbackLayer::new(
+            primary.clone(),
+            secondary.clone(),
+            FallbackPolicy {
+                on_not_found: true,
+                ..FallbackPolicy::default()
+            },
+        );
+
+        assert_eq!(
+            dex_ids(fallback.get_many(&[1, 2]).await),
+            vec![Some(1), Some(2)]
+        );
+        assert_eq!(primary.batches(), vec![vec![1, 2]]);
+        assert_eq!(secondary.batches(), vec![vec![2]]);
+
+        let stats = fallback.stats();
+        assert_eq!((stats.primary, stats.secondary), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn batches_fall_back_as_a_whole_on_timeouts() {
+        let primary = Stub::with(vec![1, 2]).slow(Duration::from_millis(50));
+        let secondary = Stub::with(vec![1, 2]);
+        let fallback = FallbackLayer::new(
+            primary,
+            secondary.clone(),
+            FallbackPolicy {
+                timeout: Some(Duration::from_millis(10)),
+                ..FallbackPolicy::default()
+            },
+        );
+
+        assert_eq!(
+            dex_ids(fallback.get_many(&[1, 2]).await),
+            vec![Some(1), Some(2)]
+        );
+        assert_eq!(secondary.batches(), vec![vec![1, 2]]);
+        assert_eq!(fallback.stats().primary_timeouts, 1);
+    }
+
+    #[tokio::test]
+    async fn primary_errors_are_returned_unless_falling_back() {
+        let primary = Stub::with(vec![1]);
+        let secondary = Stub::with(vec![1]);
+        let fallback = FallbackLayer::new(
+            primary.clone(),
+            secondary.clone(),
+            FallbackPolicy {
+                on_error: false,
+                ..FallbackPolicy::default()
+            },
+        );
+
+        primary.set_failing(true);
+
+        let results = fallback.get_many(&[1]).await;
+        assert!(matches!(results[0], Err(FallbackError::Primary(_))));
+        assert!(secondary.batches().is_empty());
+    }
+}
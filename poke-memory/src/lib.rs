@@ -1,38 +1,374 @@
+pub mod breaker;
 pub mod cache;
+pub mod coalesce;
+pub mod custom;
+pub mod fallback;
+pub mod names;
+pub mod patch;
+pub mod snapshot;
+pub mod warmup;
 
-use std::sync::Arc;
+#[cfg(test)]
+mod testing;
 
-use futures::future::BoxFuture;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+use futures::{Stream, StreamExt};
 use tokio::sync::RwLock;
 
-use poke_domain::{pokemon, pokemon::Pokemon};
+use poke_domain::pokemon::{self, Pokemon, Source, WriteError};
 
 #[derive(Clone, Default)]
 pub struct InMemoryRepository {
-    pub(crate) backend: Arc<RwLock<Vec<Pokemon>>>,
+    pub(crate) backend: Arc<RwLock<Backend>>,
+}
+
+/// Pokémons indexed by their Pokédex id, with a secondary index by name.
+///
+/// When a capacity is set, the least recently used entry is evicted
+/// on insertion of a new entry over capacity.
+#[derive(Default)]
+pub(crate) struct Backend {
+    pokemons: HashMap<u32, Entry>,
+    names: HashMap<String, u32>,
+    capacity: Option<usize>,
+    clock: AtomicU64,
+    // Ids by logical timestamp of their last access, oldest first.
+    // Behind its own lock, so that lookups only need a read lock on the backend.
+    recency: Mutex<BTreeMap<u64, u32>>,
+}
+
+struct Entry {
+    pokemon: Pokemon,
+    inserted_at: Instant,
+    // Key of the entry in the recency index, only changed while holding its lock.
+    last_used: AtomicU64,
+}
+
+impl Backend {
+    fn with_capacity(capacity: usize) -> Self {
+        Backend {
+            capacity: Some(capacity),
+            ..Backend::default()
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn recency(&self) -> std::sync::MutexGuard<'_, BTreeMap<u64, u32>> {
+        self.recency.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn pokemons(&self) -> impl Iterator<Item = &Pokemon> {
+        self.pokemons.values().map(|entry| &entry.pokemon)
+    }
+
+    /// Returns all the Pokémons, together with the time elapsed since their insertion.
+    pub(crate) fn pokemons_with_age(&self) -> impl Iterator<Item = (&Pokemon, Duration)> {
+        self.pokemons
+            .values()
+            .map(|entry| (&entry.pokemon, entry.inserted_at.elapsed()))
+    }
+
+    fn get(&self, num: u32) -> Option<&Entry> {
+        let entry = self.pokemons.get(&num)?;
+
+        let mut recency = self.recency();
+        let now = self.tick();
+
+        recency.remove(&entry.last_used.swap(now, Ordering::Relaxed));
+        recency.insert(now, num);
+
+        Some(entry)
+    }
+
+    /// Inserts the Pokémon, replacing any previous entry with the same id.
+    ///
+    /// Returns the Pokémon evicted to make room for the new entry, if any.
+    pub(crate) fn upsert(&mut self, pokemon: Pokemon) -> Option<Pokemon> {
+        self.upsert_aged(pokemon, Duration::default())
+    }
+
+    /// Inserts the Pokémon as if it was inserted `age` ago, e.g. when
+    /// restoring it from a snapshot, so that it expires when it would have.
+    pub(crate) fn upsert_aged(&mut self, pokemon: Pokemon, age: Duration) -> Option<Pokemon> {
+        if let Some(previous) = self.pokemons.get(&pokemon.dex_id) {
+            // Drop the stale name entry, in case the Pokémon has been renamed.
+            if previous.pokemon.name != pokemon.name
+                && self.names.get(&previous.pokemon.name) == Some(&pokemon.dex_id)
+            {
+                self.names.remove(&previous.pokemon.name);
+            }
+        }
+
+        let dex_id = pokemon.dex_id;
+        let now = self.tick();
+
+        let entry = Entry {
+            inserted_at: instant_before(Instant::now(), age),
+            last_used: AtomicU64::new(now),
+            pokemon,
+        };
+
+        self.names.insert(entry.pokemon.name.clone(), dex_id);
+
+        let recency = self
+            .recency
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        recency.insert(now, dex_id);
+
+        if let Some(previous) = self.pokemons.insert(dex_id, entry) {
+            recency.remove(&previous.last_used.into_inner());
+        }
+
+        match self.capacity {
+            Some(capacity) if self.pokemons.len() > capacity => self.evict(),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn remove(&mut self, num: u32) -> Option<Pokemon> {
+        let entry = self.pokemons.remove(&num)?;
+
+        self.recency
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .remove(&entry.last_used.into_inner());
+
+        if self.names.get(&entry.pokemon.name) == Some(&num) {
+            self.names.remove(&entry.pokemon.name);
+        }
+
+        Some(entry.pokemon)
+    }
+
+    fn evict(&mut self) -> Option<Pokemon> {
+        let lru = self
+            .recency
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .values()
+            .next()
+            .copied()?;
+
+        self.remove(lru)
+    }
+}
+
+/// Returns the instant `age` before `now`, or the earliest instant representable
+/// if the clock started less than `age` ago.
+fn instant_before(now: Instant, age: Duration) -> Instant {
+    if let Some(instant) = now.checked_sub(age) {
+        return instant;
+    }
+
+    let mut instant = now;
+    let mut step = age;
+
+    while step > Duration::from_millis(1) {
+        step /= 2;
+
+        if let Some(earlier) = instant.checked_sub(step) {
+            instant = earlier;
+        }
+    }
+
+    instant
 }
 
 impl From<Vec<Pokemon>> for InMemoryRepository {
     #[inline]
     fn from(value: Vec<Pokemon>) -> Self {
+        let mut backend = Backend::default();
+
+        for pokemon in value {
+            backend.upsert(pokemon);
+        }
+
         InMemoryRepository {
-            backend: Arc::new(RwLock::new(value)),
+            backend: Arc::new(RwLock::new(backend)),
         }
     }
 }
 
+impl InMemoryRepository {
+    /// Creates an empty repository holding at most `capacity` Pokémons,
+    /// evicting the least recently used ones.
+    pub fn with_capacity(capacity: usize) -> Self {
+        InMemoryRepository {
+            backend: Arc::new(RwLock::new(Backend::with_capacity(capacity))),
+        }
+    }
+
+    pub async fn get_by_name(&self, name: &str) -> Option<Pokemon> {
+        let data = self.backend.read().await;
+        let id = data.names.get(name)?;
+
+        data.get(*id).map(|entry| entry.pokemon.clone())
+    }
+
+    /// Returns the Pokémon with the specified id, together with the time
+    /// elapsed since it was inserted.
+    pub async fn get_with_age(&self, num: u32) -> Option<(Pokemon, Duration)> {
+        self.backend
+            .read()
+            .await
+            .get(num)
+            .map(|entry| (entry.pokemon.clone(), entry.inserted_at.elapsed()))
+    }
+
+    pub async fn upsert(&self, pokemon: Pokemon) -> Option<Pokemon> {
+        self.backend.write().await.upsert(pokemon)
+    }
+
+    pub async fn remove(&self, num: u32) -> Option<Pokemon> {
+        self.backend.write().await.remove(num)
+    }
+
+    pub async fn len(&self) -> usize {
+        self.backend.read().await.pokemons.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.backend.read().await.pokemons.is_empty()
+    }
+}
+
 impl pokemon::Repository for InMemoryRepository {
     type Error = std::convert::Infallible;
 
-    fn get<'a>(&'a self, num: u32) -> BoxFuture<'a, Result<Option<Pokemon>, Self::Error>>
-    where
-        Self: Sync + 'a,
-    {
-        Box::pin(async move {
-            let data = self.backend.read().await;
-            let position = data.iter().position(|pokemon| pokemon.dex_id == num);
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        let data = self.backend.read().await;
 
-            Ok(position.and_then(|idx| data.get(idx).cloned()))
+        Ok(data.get(num).map(|entry| entry.pokemon.clone()))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        Ok(self.get(num).await?.map(|pokemon| (pokemon, "memory")))
+    }
+
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        let data = self.backend.read().await;
+
+        ids.iter()
+            .map(|num| Ok(data.get(*num).map(|entry| entry.pokemon.clone())))
+            .collect()
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+impl pokemon::Listing for InMemoryRepository {
+    /// Streams a snapshot of the Pokémons taken when the stream is first
+    /// polled, so that the lock is not held while the stream is consumed.
+    fn stream_all(&self) -> impl Stream<Item = Result<Pokemon, Self::Error>> + Send + '_ {
+        futures::stream::once(async move {
+            let mut pokemons: Vec<Pokemon> =
+                self.backend.read().await.pokemons().cloned().collect();
+            pokemons.sort_by_key(|pokemon| pokemon.dex_id);
+
+            futures::stream::iter(pokemons.into_iter().map(Ok))
         })
+        .flatten()
+    }
+}
+
+impl pokemon::WritableRepository for InMemoryRepository {
+    async fn insert(&self, pokemon: Pokemon) -> Result<(), WriteError<Self::Error>> {
+        let mut data = self.backend.write().await;
+
+        if data.pokemons.contains_key(&pokemon.dex_id) {
+            return Err(WriteError::AlreadyExists {
+                dex_id: pokemon.dex_id,
+            });
+        }
+
+        data.upsert(pokemon);
+        Ok(())
+    }
+
+    async fn update(&self, pokemon: Pokemon) -> Result<(), WriteError<Self::Error>> {
+        let mut data = self.backend.write().await;
+
+        if !data.pokemons.contains_key(&pokemon.dex_id) {
+            return Err(WriteError::NotFound {
+                dex_id: pokemon.dex_id,
+            });
+        }
+
+        data.upsert(pokemon);
+        Ok(())
+    }
+
+    async fn delete(&self, num: u32) -> Result<Pokemon, WriteError<Self::Error>> {
+        self.backend
+            .write()
+            .await
+            .remove(num)
+            .ok_or(WriteError::NotFound { dex_id: num })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::pokemon;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut backend = Backend::with_capacity(3);
+
+        for id in 1..=3 {
+            assert_eq!(backend.upsert(pokemon(id)), None);
+        }
+
+        // #1 is used again, so #2 becomes the least recently used.
+        backend.get(1);
+
+        assert_eq!(backend.upsert(pokemon(4)).map(|p| p.dex_id), Some(2));
+        assert_eq!(backend.upsert(pokemon(5)).map(|p| p.dex_id), Some(3));
+        assert_eq!(backend.upsert(pokemon(6)).map(|p| p.dex_id), Some(1));
+    }
+
+    #[test]
+    fn recency_index_follows_the_entries() {
+        let mut backend = Backend::with_capacity(2);
+
+        backend.upsert(pokemon(1));
+        backend.upsert(pokemon(1));
+        backend.upsert(pokemon(2));
+        backend.get(2);
+        backend.remove(2);
+
+        assert_eq!(backend.recency().len(), backend.pokemons.len());
+        assert_eq!(backend.recency().values().collect::<Vec<_>>(), vec![&1]);
+    }
+
+    #[tokio::test]
+    async fn streams_a_snapshot_in_dex_order() {
+        use futures::TryStreamExt;
+        use poke_domain::pokemon::{Listing, WritableRepository};
+
+        let repository = InMemoryRepository::from(vec![pokemon(3), pokemon(1), pokemon(2)]);
+
+        let mut stream = Box::pin(repository.stream_all());
+        let first = stream.try_next().await.unwrap().map(|p| p.dex_id);
+
+        // The lock is not held while the stream is consumed.
+        repository.insert(pokemon(4)).await.unwrap();
+        repository.delete(2).await.unwrap();
+
+        let rest: Vec<u32> = stream.map_ok(|p| p.dex_id).try_collect().await.unwrap();
+
+        assert_eq!(first, Some(1));
+        assert_eq!(rest, vec![2, 3]);
     }
 }
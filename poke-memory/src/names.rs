@@ -0,0 +1,337 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use poke_domain::pokemon::{self, CatalogueEntry};
+
+/// Index of the Pokémon names known to a [`pokemon::Catalogue`],
+/// for lookups by name, autocompletion and suggestions on misspelled names.
+///
+/// Names are matched case-insensitively, with spaces standing for hyphens
+/// (e.g. `Mr Mime` matches `mr-mime`).
+#[derive(Clone, Default)]
+pub struct NameIndex {
+    // Sorted by normalized name, so that names sharing a prefix are contiguous.
+    entries: Arc<RwLock<Vec<Entry>>>,
+    // Whether the names have been loaded at least once.
+    loaded: Arc<AtomicBool>,
+}
+
+struct Entry {
+    key: String,
+    name: String,
+    dex_id: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Match {
+    pub dex_id: u32,
+    pub name: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Suggestion {
+    pub dex_id: u32,
+    pub name: String,
+    /// Edit distance from the name looked up.
+    pub distance: usize,
+}
+
+fn normalize(name: &str) -> String {
+    name.trim().to_lowercase().replace(' ', "-")
+}
+
+/// Levenshtein distance between two strings, in characters.
+fn distance(a: &[char], b: &[char]) -> usize {
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + if ca == cb { 0 } else { 1 };
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+
+        std::mem::swap(&mut previous, &mut current);
+    }
+
+    previous[b.len()]
+}
+
+impl NameIndex {
+    pub fn new(entries: Vec<CatalogueEntry>) -> Self {
+        let index = NameIndex::default();
+        index.replace(entries);
+        index
+    }
+
+    /// Replaces the indexed names with the ones currently in the catalogue,
+    /// returning how many have been indexed.
+    pub async fn refresh<C>(&self, catalogue: &C) -> Result<usize, C::Error>
+    where
+        C: pokemon::Catalogue,
+    {
+        let entries = catalogue.list().await?;
+        let count = entries.len();
+
+        self.replace(entries);
+        Ok(count)
+    }
+
+    /// Indexes the name of a Pokémon, replacing the one it had, if any,
+    /// e.g. after a custom Pokémon has been added or modified.
+    ///
+    /// The Pokémon takes the place of any other one with the same name.
+    pub fn insert(&self, dex_id: u32, name: String) {
+        let entry = Entry {
+            key: normalize(&name),
+            name,
+            dex_id,
+        };
+
+        let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+        entries.retain(|entry| entry.dex_id != dex_id);
+
+        match entries.binary_search_by(|other| other.key.cmp(&entry.key)) {
+            Ok(idx) => entries[idx] = entry,
+            Err(idx) => entries.insert(idx, entry),
+        }
+    }
+
+    /// Removes the name of a Pokémon from the index, if any.
+    pub fn remove(&self, dex_id: u32) {
+        self.entries
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|entry| entry.dex_id != dex_id);
+    }
+
+    /// Whether the names have been loaded, either at creation
+    /// or by a successful [`NameIndex::refresh`].
+    pub fn is_loaded(&self) -> bool {
+        self.loaded.load(Ordering::SeqCst)
+    }
+
+    fn replace(&self, entries: Vec<CatalogueEntry>) {
+        let mut entries: Vec<Entry> = entries
+            .into_iter()
+            .map(|(dex_id, name)| Entry {
+                key: normalize(&name),
+                name,
+                dex_id,
+            })
+            .collect();
+
+        entries.sort_by(|a, b| a.key.cmp(&b.key).then(a.dex_id.cmp(&b.dex_id)));
+        entries.dedup_by(|a, b| a.key == b.key);
+
+        *self.entries.write().unwrap_or_else(PoisonError::into_inner) = entries;
+        self.loaded.store(true, Ordering::SeqCst);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the Pokédex id of the Pokémon with the specified name, if any.
+    pub fn find(&self, name: &str) -> Option<u32> {
+        let key = normalize(name);
+        let entries = self.entries.read().unwrap_or_else(PoisonError::into_inner);
+
+        entries
+            .binary_search_by(|entry| entry.key.as_str().cmp(&key))
+            .ok()
+            .map(|idx| entries[idx].dex_id)
+    }
+
+    /// Returns up to `limit` Pokémons whose name starts with `prefix`,
+    /// in alphabetical order.
+    pub fn autocomplete(&self, prefix: &str, limit: usize) -> Vec<Match> {
+        let prefix = normalize(prefix);
+        let entries = self.entries.read().unwrap_or_else(PoisonError::into_inner);
+        let start = entries.partition_point(|entry| entry.key < prefix);
+
+        entries[start..]
+            .iter()
+            .take_while(|entry| entry.key.starts_with(&prefix))
+            .take(limit)
+            .map(|entry| Match {
+                dex_id: entry.dex_id,
+                name: entry.name.clone(),
+            })
+            .collect()
+    }
+
+    /// Returns up to `limit` Pokémons whose name is close to `name`,
+    /// closest first.
+    ///
+    /// Names further away than a third of the length of `name` are not
+    /// considered similar enough to be suggested.
+    pub fn suggest(&self, name: &str, limit: usize) -> Vec<Suggestion> {
+        let key: Vec<char> = normalize(name).chars().collect();
+        let max_distance = key.len() / 3 + 1;
+
+        let entries = self.entries.read().unwrap_or_else(PoisonError::into_inner);
+
+        let mut suggestions: Vec<Suggestion> = entries
+            .iter()
+            .filter_map(|entry| {
+                let candidate: Vec<char> = entry.key.chars().collect();
+
+                // The distance is at least the difference in length: skip the obvious misses.
+                if candidate.len().abs_diff(key.len()) > max_distance {
+                    return None;
+                }
+
+                let distance = distance(&key, &candidate);
+
+                if distance > max_distance {
+                    return None;
+                }
+
+                Some(Suggestion {
+                    dex_id: entry.dex_id,
+                    name: entry.name.clone(),
+                    distance,
+                })
+            })
+            .collect();
+
+        // Entries are already sorted by name, and the sort is stable.
+        suggestions.sort_by_key(|suggestion| suggestion.distance);
+        suggestions.truncate(limit);
+        suggestions
+    }
+}
+
+/// Refreshes the index from the catalogue every `period`, starting immediately,
+/// so that names added to the catalogue in the meantime can be looked up.
+pub async fn refresh_periodically<C>(index: NameIndex, catalogue: C, period: Duration)
+where
+    C: pokemon::Catalogue,
+{
+    let mut interval = tokio::time::interval(period);
+
+    loop {
+        interval.tick().await;
+
+        match index.refresh(&catalogue).await {
+            Ok(count) => log::debug!("Indexed {} Pokemon names", count),
+            Err(err) => log::error!("Failed to refresh the Pokemon names index: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Stub;
+
+    fn index() -> NameIndex {
+        NameIndex::new(vec![
+            (25, "pikachu".to_owned()),
+            (26, "raichu".to_owned()),
+            (122, "mr-mime".to_owned()),
+            (172, "pichu".to_owned()),
+            (1, "bulbasaur".to_owned()),
+        ])
+    }
+
+    fn names(matches: Vec<Match>) -> Vec<String> {
+        matches.into_iter().map(|m| m.name).collect()
+    }
+
+    fn names_of(suggestions: Vec<Suggestion>) -> Vec<String> {
+        suggestions.into_iter().map(|s| s.name).collect()
+    }
+
+    #[test]
+    fn finds_names_case_insensitively() {
+        let index = index();
+
+        assert_eq!(index.find("pikachu"), Some(25));
+        assert_eq!(index.find(" PikaChu "), Some(25));
+        assert_eq!(index.find("Mr Mime"), Some(122));
+        assert_eq!(index.find("pika"), None);
+    }
+
+    #[test]
+    fn autocompletes_in_alphabetical_order() {
+        let index = index();
+
+        assert_eq!(
+            names(index.autocomplete("pi", 10)),
+            vec!["pichu", "pikachu"]
+        );
+        assert_eq!(names(index.autocomplete("PI", 1)), vec!["pichu"]);
+        assert_eq!(names(index.autocomplete("mr ", 10)), vec!["mr-mime"]);
+        assert!(index.autocomplete("zz", 10).is_empty());
+    }
+
+    #[test]
+    fn suggests_the_closest_names_first() {
+        let index = index();
+
+        let suggestions: Vec<_> = index
+            .suggest("Pikachuu", 10)
+            .into_iter()
+            .map(|s| (s.name, s.distance))
+            .collect();
+
+        assert_eq!(
+            suggestions,
+            vec![("pikachu".to_owned(), 1), ("pichu".to_owned(), 3)]
+        );
+
+        assert_eq!(names_of(index.suggest("pikachuu", 1)), vec!["pikachu"]);
+        assert!(index.suggest("charizard", 10).is_empty());
+    }
+
+    #[test]
+    fn follows_inserted_and_removed_pokemons() {
+        let index = index();
+
+        index.insert(10001, "Pikachu Rock Star".to_owned());
+        assert_eq!(index.find("pikachu-rock-star"), Some(10001));
+        assert_eq!(
+            names(index.autocomplete("pikachu", 10)),
+            vec!["pikachu", "Pikachu Rock Star"]
+        );
+
+        // Renaming a Pokémon replaces its previous name.
+        index.insert(10001, "pikachu-pop-star".to_owned());
+        assert_eq!(index.find("pikachu-rock-star"), None);
+        assert_eq!(index.find("pikachu-pop-star"), Some(10001));
+
+        index.remove(10001);
+        assert_eq!(index.find("pikachu-pop-star"), None);
+        assert_eq!(index.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn is_loaded_once_refreshed() {
+        let index = NameIndex::default();
+        assert!(!index.is_loaded());
+
+        let catalogue = Stub::with(vec![1, 2]);
+        catalogue.set_failing(true);
+        assert!(index.refresh(&catalogue).await.is_err());
+        assert!(!index.is_loaded());
+
+        catalogue.set_failing(false);
+        assert_eq!(index.refresh(&catalogue).await.unwrap(), 2);
+        assert!(index.is_loaded());
+        assert_eq!(index.find("pokemon-2"), Some(2));
+    }
+}
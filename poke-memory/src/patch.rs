@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+
+use poke_domain::pokemon::{self, Pokemon, Source, Stats, Type};
+
+/// Declarative changes to apply over an upstream Pokémon.
+/// Fields that are not set are left untouched.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Patch {
+    pub dex_id: u32,
+
+    #[serde(rename = "type", default)]
+    pub typ: Option<Type>,
+
+    #[serde(default)]
+    pub stats: StatsPatch,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StatsPatch {
+    pub speed: Option<u16>,
+    pub special_defense: Option<u16>,
+    pub special_attack: Option<u16>,
+    pub defense: Option<u16>,
+    pub attack: Option<u16>,
+    pub hit_points: Option<u16>,
+}
+
+impl Patch {
+    pub fn apply(&self, mut pokemon: Pokemon) -> Pokemon {
+        if let Some(typ) = self.typ {
+            pokemon.typ = typ;
+        }
+
+        self.stats.apply(&mut pokemon.stats);
+        pokemon
+    }
+}
+
+impl StatsPatch {
+    fn apply(&self, stats: &mut Stats) {
+        stats.speed = self.speed.unwrap_or(stats.speed);
+        stats.special_defense = self.special_defense.unwrap_or(stats.special_defense);
+        stats.special_attack = self.special_attack.unwrap_or(stats.special_attack);
+        stats.defense = self.defense.unwrap_or(stats.defense);
+        stats.attack = self.attack.unwrap_or(stats.attack);
+        stats.hit_points = self.hit_points.unwrap_or(stats.hit_points);
+    }
+}
+
+/// File format of the patches, e.g. in TOML:
+///
+/// ```toml
+/// [[pokemon]]
+/// dex_id = 25
+/// type = ["electric", "fairy"]
+///
+/// [pokemon.stats]
+/// attack = 60
+/// speed = 100
+/// ```
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PatchFile {
+    #[serde(default)]
+    pokemon: Vec<Patch>,
+}
+
+impl PatchFile {
+    /// Reads the patches from a TOML file if its extension is `.toml`,
+    /// or from a JSON file otherwise.
+    async fn read(path: &Path) -> Result<HashMap<u32, Patch>, PatchError> {
+        let data = tokio::fs::read(path).await?;
+
+        let file: PatchFile = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("toml") => toml::from_slice(&data)?,
+            _ => serde_json::from_slice(&data)?,
+        };
+
+        Ok(file
+            .pokemon
+            .into_iter()
+            .map(|patch| (patch.dex_id, patch))
+            .collect())
+    }
+}
+
+/// Original and patched version of a Pokémon, with the list of changed fields.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Diff {
+    pub original: Pokemon,
+    pub patched: Pokemon,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Change {
+    pub field: &'static str,
+    pub original: serde_json::Value,
+    pub patched: serde_json::Value,
+}
+
+impl Diff {
+    fn new(original: Pokemon, patched: Pokemon) -> Diff {
+        let mut changes = Vec::new();
+
+        let mut compare = |field, original: serde_json::Value, patched: serde_json::Value| {
+            if original != patched {
+                changes.push(Change {
+                    field,
+                    original,
+                    patched,
+                });
+            }
+        };
+
+        compare("type", json(&original.typ), json(&patched.typ));
+
+        let (from, to) = (&original.stats, &patched.stats);
+        compare("stats.speed", from.speed.into(), to.speed.into());
+        compare(
+            "stats.special_defense",
+            from.special_defense.into(),
+            to.special_defense.into(),
+        );
+        compare(
+            "stats.special_attack",
+            from.special_attack.into(),
+            to.special_attack.into(),
+        );
+        compare("stats.defense", from.defense.into(), to.defense.into());
+        compare("stats.attack", from.attack.into(), to.attack.into());
+        compare(
+            "stats.hit_points",
+            from.hit_points.into(),
+            to.hit_points.into(),
+        );
+
+        Diff {
+            original,
+            patched,
+            changes,
+        }
+    }
+}
+
+fn json<T: Serialize>(value: &T) -> serde_json::Value {
+    serde_json::to_value(value).unwrap_or(serde_json::Value::Null)
+}
+
+/// Repository decorator applying balance patches over the Pokémons
+/// found upstream, e.g. to tweak the stats of a Pokémon for a game mode
+/// without forking the upstream data.
+///
+/// The patches are read from a TOML or JSON file, and can be reloaded
+/// at runtime with [`PatchLayer::reload`].
+#[derive(Clone)]
+pub struct PatchLayer<R> {
+    upstream: R,
+    path: Option<Arc<PathBuf>>,
+    patches: Arc<RwLock<HashMap<u32, Patch>>>,
+}
+
+impl<R> From<R> for PatchLayer<R> {
+    #[inline]
+    fn from(upstream: R) -> PatchLayer<R> {
+        PatchLayer {
+            upstream,
+            path: None,
+            patches: Arc::default(),
+        }
+    }
+}
+
+impl<R> PatchLayer<R> {
+    /// Creates a new layer with the patches read from the specified file.
+    pub async fn open(upstream: R, path: PathBuf) -> Result<PatchLayer<R>, PatchError> {
+        let patches = PatchFile::read(&path).await?;
+
+        log::info!("Loaded {} Pokemon patches from {:?}", patches.len(), path);
+
+        Ok(PatchLayer {
+            upstream,
+            path: Some(Arc::new(path)),
+            patches: Arc::new(RwLock::new(patches)),
+        })
+    }
+
+    /// Reads the patches from the file again, replacing the current ones,
+    /// and returns the number of patches loaded.
+    ///
+    /// On failure the current patches are kept in place.
+    pub async fn reload(&self) -> Result<usize, PatchError> {
+        let path = match self.path {
+            None => return Ok(0),
+            Some(ref path) => path,
+        };
+
+        let patches = PatchFile::read(path).await?;
+        let count = patches.len();
+
+        *self.patches.write().await = patches;
+
+        log::info!("Reloaded {} Pokemon patches from {:?}", count, path);
+
+        Ok(count)
+    }
+
+    /// Returns all the patches currently applied.
+    pub async fn patches(&self) -> Vec<Patch> {
+        self.patches.read().await.values().cloned().collect()
+    }
+}
+
+impl<R> PatchLayer<R>
+where
+    R: pokemon::Repository + Send + Sync,
+{
+    /// Returns the Pokémon as found upstream, together with its patched version.
+    pub async fn diff(&self, num: u32) -> Result<Option<Diff>, R::Error> {
+        let original = match self.upstream.get(num).await? {
+            None => return Ok(None),
+            Some(pokemon) => pokemon,
+        };
+
+        let patched = self.patch(original.clone()).await;
+
+        Ok(Some(Diff::new(original, patched)))
+    }
+
+    async fn patch(&self, pokemon: Pokemon) -> Pokemon {
+        match self.patches.read().await.get(&pokemon.dex_id) {
+            None => pokemon,
+            Some(patch) => patch.apply(pokemon),
+        }
+    }
+}
+
+impl<R> pokemon::Repository for PatchLayer<R>
+where
+    R: pokemon::Repository + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        let (pokemon, source) = match self.upstream.get_sourced(num).await? {
+            None => return Ok(None),
+            Some(sourced) => sourced,
+        };
+
+        Ok(Some((self.patch(pokemon).await, source)))
+    }
+
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>>
+    where
+        Self::Error: Send,
+    {
+        let mut results = Vec::with_capacity(ids.len());
+
+        for result in self.upstream.get_many(ids).await {
+            results.push(match result {
+                Ok(Some(pokemon)) => Ok(Some(self.patch(pokemon).await)),
+                other => other,
+            });
+        }
+
+        results
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        self.upstream.probe().await
+    }
+}
+
+impl<R> pokemon::Catalogue for PatchLayer<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        self.upstream.list().await
+    }
+}
+
+#[derive(Debug)]
+pub enum PatchError {
+    Io { inner: std::io::Error },
+    MalformedJson { inner: serde_json::Error },
+    MalformedToml { inner: toml::de::Error },
+}
+
+impl std::error::Error for PatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use PatchError::*;
+
+        match self {
+            Io { inner } => Some(inner),
+            MalformedJson { inner } => Some(inner),
+            MalformedToml { inner } => Some(inner),
+        }
+    }
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        use PatchError::*;
+
+        match self {
+            Io { inner } => write!(f, "failed to read patches: {}", inner),
+            MalformedJson { inner } => write!(f, "malformed patches: {}", inner),
+            MalformedToml { inner } => write!(f, "malformed patches: {}", inner),
+        }
+    }
+}
+
+impl From<std::io::Error> for PatchError {
+    fn from(error: std::io::Error) -> PatchError {
+        PatchError::Io { inner: error }
+    }
+}
+
+impl From<serde_json::Error> for PatchError {
+    fn from(error: serde_json::Error) -> PatchError {
+        PatchError::MalformedJson { inner: error }
+    }
+}
+
+impl From<toml::de::Error> for PatchError {
+    fn from(error: toml::de::Error) -> PatchError {
+        PatchError::MalformedToml { inner: error }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use poke_domain::pokemon::{Element, Repository};
+
+    use super::*;
+    use crate::testing::{pokemon, temp_path, Stub};
+
+    const TOML: &str = r#"
+[[pokemon]]
+dex_id = 25
+type = ["electric", "fairy"]
+
+[pokemon.stats]
+attack = 60
+speed = 100
+"#;
+
+    const JSON: &str = r#"{"pokemon": [{"dex_id": 25, "stats": {"defense": 80}}]}"#;
+
+    #[test]
+    fn patches_only_the_set_fields() {
+        let patch = Patch {
+            dex_id: 1,
+            typ: None,
+            stats: StatsPatch {
+                attack: Some(60),
+                hit_points: Some(0),
+                ..StatsPatch::default()
+            },
+        };
+
+        let original = pokemon(1);
+        let patched = patch.apply(original.clone());
+
+        assert_eq!(patched.stats.attack, 60);
+        assert_eq!(patched.stats.hit_points, 0);
+        assert_eq!(patched.stats.speed, original.stats.speed);
+        assert_eq!(patched.typ, original.typ);
+        assert_eq!(patched.name, original.name);
+    }
+
+    #[tokio::test]
+    async fn reads_toml_patch_files() {
+        let path = temp_path("patches-read.toml");
+        std::fs::write(&path, TOML).unwrap();
+
+        let patches = PatchFile::read(&path).await.unwrap();
+
+        let patch = &patches[&25];
+        assert_eq!(
+            patch.typ,
+            Some(Type::Double(Element::Electric, Element::Fairy))
+        );
+        assert_eq!(patch.stats.attack, Some(60));
+        assert_eq!(patch.stats.speed, Some(100));
+        assert_eq!(patch.stats.defense, None);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn reads_json_patch_files() {
+        let path = temp_path("patches-read.json");
+        std::fs::write(&path, JSON).unwrap();
+
+        let patches = PatchFile::read(&path).await.unwrap();
+
+        assert_eq!(patches[&25].typ, None);
+        assert_eq!(patches[&25].stats.defense, Some(80));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_unknown_fields() {
+        let path = temp_path("patches-unknown.json");
+        std::fs::write(&path, r#"{"pokemon": [{"dex_id": 25, "nmae": "pika"}]}"#).unwrap();
+
+        assert!(PatchFile::read(&path).await.is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_reloads_keep_the_current_patches() {
+        let path = temp_path("patches-reload.toml");
+        std::fs::write(&path, TOML).unwrap();
+
+        let layer = PatchLayer::open(Stub::with(vec![25]), path.clone())
+            .await
+            .unwrap();
+
+        std::fs::write(&path, "[[pokemon]]\ndex_id = \"twenty-five\"").unwrap();
+        assert!(layer.reload().await.is_err());
+
+        let pikachu = layer.get(25).await.unwrap().unwrap();
+        assert_eq!(pikachu.stats.attack, 60);
+        assert_eq!(layer.patches().await.len(), 1);
+
+        std::fs::write(&path, "").unwrap();
+        assert_eq!(layer.reload().await.unwrap(), 0);
+        assert_eq!(layer.get(25).await.unwrap(), Some(pokemon(25)));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn diffs_list_the_changed_fields() {
+        let path = temp_path("patches-diff.toml");
+        std::fs::write(&path, TOML).unwrap();
+
+        let layer = PatchLayer::open(Stub::with(vec![1, 25]), path.clone())
+            .await
+            .unwrap();
+
+        let diff = layer.diff(25).await.unwrap().unwrap();
+        assert_eq!(diff.original, pokemon(25));
+        assert_eq!(diff.patched, layer.get(25).await.unwrap().unwrap());
+
+        let fields: Vec<_> = diff.changes.iter().map(|change| change.field).collect();
+        assert_eq!(fields, vec!["type", "stats.speed", "stats.attack"]);
+
+        assert_eq!(diff.changes[0].original, serde_json::json!("normal"));
+        assert_eq!(
+            diff.changes[0].patched,
+            serde_json::json!(["electric", "fairy"])
+        );
+        assert_eq!(diff.changes[1].original, serde_json::json!(45));
+        assert_eq!(diff.changes[1].patched, serde_json::json!(100));
+
+        assert!(layer.diff(1).await.unwrap().unwrap().changes.is_empty());
+        assert!(layer.diff(151).await.unwrap().is_none());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,272 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use poke_domain::pokemon::Pokemon;
+
+use crate::InMemoryRepository;
+
+/// Version of the snapshot format: snapshots written with a different
+/// version are refused when loading.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+// Distinguishes the temporary files of concurrent saves.
+static SAVES: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    version: u32,
+    pokemons: Vec<SnapshotEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotEntry {
+    pokemon: Pokemon,
+    /// Wall-clock time of the insertion, in milliseconds since the Unix epoch.
+    inserted_at: u64,
+}
+
+impl InMemoryRepository {
+    /// Writes all the Pokémons in the repository to the specified file,
+    /// returning the number of Pokémons written.
+    ///
+    /// The snapshot is written to a temporary file first, and then moved
+    /// in place, so that a failure never corrupts a previous snapshot.
+    /// Every save uses its own temporary file, so concurrent saves never
+    /// interleave their writes.
+    pub async fn save_snapshot(&self, path: &Path) -> Result<usize, SnapshotError> {
+        let now = SystemTime::now();
+
+        let pokemons = self
+            .backend
+            .read()
+            .await
+            .pokemons_with_age()
+            .map(|(pokemon, age)| SnapshotEntry {
+                pokemon: pokemon.clone(),
+                inserted_at: unix_millis(now.checked_sub(age).unwrap_or(UNIX_EPOCH)),
+            })
+            .collect();
+
+        let snapshot = Snapshot {
+            version: SNAPSHOT_VERSION,
+            pokemons,
+        };
+
+        let tmp = path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            SAVES.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        let contents = serde_json::to_vec(&snapshot)?;
+        let written = match tokio::fs::write(&tmp, contents).await {
+            Ok(()) => tokio::fs::rename(&tmp, path).await,
+            Err(err) => Err(err),
+        };
+
+        if let Err(err) = written {
+            // A partially written file is never read: just drop it.
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(err.into());
+        }
+
+        Ok(snapshot.pokemons.len())
+    }
+
+    /// Loads all the Pokémons from the specified snapshot file into
+    /// the repository, returning the number of Pokémons loaded.
+    ///
+    /// The Pokémons keep the age they had when saved, plus the time
+    /// elapsed since, so that they expire as if never unloaded.
+    pub async fn load_snapshot(&self, path: &Path) -> Result<usize, SnapshotError> {
+        let snapshot: Snapshot = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::VersionMismatch {
+                found: snapshot.version,
+            });
+        }
+
+        let count = snapshot.pokemons.len();
+        let now = unix_millis(SystemTime::now());
+        let mut backend = self.backend.write().await;
+
+        for entry in snapshot.pokemons {
+            // Entries from the future, e.g. after a clock adjustment, are just fresh.
+            let age = Duration::from_millis(now.saturating_sub(entry.inserted_at));
+            backend.upsert_aged(entry.pokemon, age);
+        }
+
+        Ok(count)
+    }
+}
+
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Writes a snapshot of the repository to the specified file every `period`.
+pub async fn save_periodically(repository: InMemoryRepository, path: PathBuf, period: Duration) {
+    let mut interval = tokio::time::interval(period);
+
+    // The first tick completes immediately: skip it, there's nothing new to save.
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        match repository.save_snapshot(&path).await {
+            Ok(count) => log::debug!("Saved snapshot of {} Pokemons to {:?}", count, path),
+            Err(err) => log::error!("Failed to save snapshot to {:?}: {}", path, err),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    VersionMismatch { found: u32 },
+    Io { inner: std::io::Error },
+    Malformed { inner: serde_json::Error },
+}
+
+impl std::error::Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use SnapshotError::*;
+
+        match self {
+            VersionMismatch { .. } => None,
+            Io { inner } => Some(inner),
+            Malformed { inner } => Some(inner),
+        }
+    }
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        use SnapshotError::*;
+
+        match self {
+            VersionMismatch { found } => write!(
+                f,
+                "unsupported snapshot version {}, expected {}",
+                found, SNAPSHOT_VERSION
+            ),
+            Io { inner } => write!(f, "failed to access snapshot: {}", inner),
+            Malformed { inner } => write!(f, "malformed snapshot: {}", inner),
+        }
+    }
+}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(error: std::io::Error) -> SnapshotError {
+        SnapshotError::Io { inner: error }
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(error: serde_json::Error) -> SnapshotError {
+        SnapshotError::Malformed { inner: error }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{pokemon, temp_path};
+
+    #[tokio::test]
+    async fn snapshots_restore_the_age_of_the_pokemons() {
+        let path = temp_path("snapshot-age.json");
+        let repository = InMemoryRepository::default();
+
+        repository
+            .backend
+            .write()
+            .await
+            .upsert_aged(pokemon(25), Duration::from_secs(600));
+        repository.upsert(pokemon(1)).await;
+
+        assert_eq!(repository.save_snapshot(&path).await.unwrap(), 2);
+
+        let restored = InMemoryRepository::default();
+        assert_eq!(restored.load_snapshot(&path).await.unwrap(), 2);
+
+        let (_, age) = restored.get_with_age(25).await.unwrap();
+        assert!(age >= Duration::from_secs(600), "age: {:?}", age);
+        assert!(age < Duration::from_secs(610), "age: {:?}", age);
+
+        let (_, age) = restored.get_with_age(1).await.unwrap();
+        assert!(age < Duration::from_secs(10), "age: {:?}", age);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn concurrent_saves_never_clash() {
+        let path = temp_path("snapshot-concurrent.json");
+        let repository = InMemoryRepository::from((1..=100).map(pokemon).collect::<Vec<_>>());
+
+        let saves = (0..8).map(|_| repository.save_snapshot(&path));
+
+        for result in futures::future::join_all(saves).await {
+            assert_eq!(result.unwrap(), 100);
+        }
+
+        let restored = InMemoryRepository::default();
+        assert_eq!(restored.load_snapshot(&path).await.unwrap(), 100);
+
+        let leftovers = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.starts_with("snapshot-concurrent") && name.ends_with(".tmp")
+            })
+            .count();
+
+        assert_eq!(leftovers, 0);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn failed_saves_leave_no_temporary_file() {
+        // A non-empty directory cannot be replaced by the snapshot.
+        let path = temp_path("snapshot-failed.json");
+        std::fs::create_dir_all(path.join("occupied")).unwrap();
+
+        let repository = InMemoryRepository::from(vec![pokemon(25)]);
+        assert!(repository.save_snapshot(&path).await.is_err());
+
+        let leftovers = std::fs::read_dir(path.parent().unwrap())
+            .unwrap()
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                name.starts_with("snapshot-failed") && name.ends_with(".tmp")
+            })
+            .count();
+
+        assert_eq!(leftovers, 0);
+        std::fs::remove_dir_all(&path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn snapshots_of_other_versions_are_refused() {
+        let path = temp_path("snapshot-version.json");
+        std::fs::write(&path, r#"{"version":0,"pokemons":[]}"#).unwrap();
+
+        let err = InMemoryRepository::default()
+            .load_snapshot(&path)
+            .await
+            .unwrap_err();
+
+        assert!(matches!(err, SnapshotError::VersionMismatch { found: 0 }));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
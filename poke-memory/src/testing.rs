@@ -0,0 +1,144 @@
+//! Repositories and data shared by the tests of the crate.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use poke_domain::pokemon::{self, Element, Pokemon, Stats, Type};
+
+pub(crate) fn pokemon(dex_id: u32) -> Pokemon {
+    Pokemon {
+        dex_id,
+        name: format!("pokemon-{}", dex_id),
+        typ: Type::Single(Element::Normal),
+        height: 10,
+        weight: 100,
+        base_experience: 64,
+        stats: Stats {
+            speed: 45,
+            special_defense: 65,
+            special_attack: 65,
+            defense: 49,
+            attack: 49,
+            hit_points: 45,
+        },
+    }
+}
+
+/// Path of a file named `name` in a temporary directory of the test run.
+pub(crate) fn temp_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("poke-memory-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    dir.join(name)
+}
+
+/// Upstream counting its lookups, which can be made slow or failing.
+#[derive(Clone, Default)]
+pub(crate) struct Stub {
+    pokemons: Arc<Mutex<HashMap<u32, Pokemon>>>,
+    calls: Arc<AtomicUsize>,
+    batches: Arc<Mutex<Vec<Vec<u32>>>>,
+    delay: Duration,
+    failing: Arc<AtomicBool>,
+}
+
+#[derive(Debug)]
+pub(crate) struct StubError;
+
+impl std::error::Error for StubError {}
+
+impl Display for StubError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "stub upstream failure")
+    }
+}
+
+impl Stub {
+    pub(crate) fn with(ids: impl IntoIterator<Item = u32>) -> Self {
+        let stub = Stub::default();
+
+        for id in ids {
+            stub.insert(pokemon(id));
+        }
+
+        stub
+    }
+
+    pub(crate) fn slow(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    pub(crate) fn insert(&self, pokemon: Pokemon) {
+        self.pokemons
+            .lock()
+            .unwrap()
+            .insert(pokemon.dex_id, pokemon);
+    }
+
+    pub(crate) fn set_failing(&self, failing: bool) {
+        self.failing.store(failing, Ordering::SeqCst);
+    }
+
+    pub(crate) fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+
+    /// Returns the ids of every batch looked up, in order.
+    pub(crate) fn batches(&self) -> Vec<Vec<u32>> {
+        self.batches.lock().unwrap().clone()
+    }
+}
+
+impl pokemon::Repository for Stub {
+    type Error = StubError;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+
+        if self.delay > Duration::default() {
+            tokio::time::delay_for(self.delay).await;
+        }
+
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(StubError);
+        }
+
+        Ok(self.pokemons.lock().unwrap().get(&num).cloned())
+    }
+
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        self.batches.lock().unwrap().push(ids.to_vec());
+
+        let mut results = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            results.push(self.get(*id).await);
+        }
+
+        results
+    }
+}
+
+impl pokemon::Catalogue for Stub {
+    type Error = StubError;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(StubError);
+        }
+
+        let pokemons = self.pokemons.lock().unwrap();
+        let mut entries: Vec<_> = pokemons
+            .values()
+            .map(|pokemon| (pokemon.dex_id, pokemon.name.clone()))
+            .collect();
+
+        entries.sort();
+        Ok(entries)
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::ops::RangeInclusive;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use poke_domain::pokemon::{self, Repository};
+
+use crate::cache::CacheLayer;
+
+/// Largest id range that can be selected, well above the size of the Pokédex.
+pub const MAX_RANGE_LEN: u32 = 100_000;
+
+/// Set of Pokémons to preload into the cache.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Selection {
+    /// Every Pokémon listed by the upstream catalogue.
+    All,
+    /// Pokédex ids range, e.g. `1..=151` or `1..152`,
+    /// of at most [`MAX_RANGE_LEN`] ids.
+    Range(RangeInclusive<u32>),
+    /// File with a Pokémon name on each line, e.g. `file:names.txt`.
+    /// Empty lines and lines starting with `#` are ignored.
+    Names(PathBuf),
+}
+
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "all" {
+            return Ok(Selection::All);
+        }
+
+        if let Some(path) = s.strip_prefix("file:") {
+            return match path {
+                "" => Err("missing path of the file of names".to_owned()),
+                path => Ok(Selection::Names(PathBuf::from(path))),
+            };
+        }
+
+        match parse_range(s) {
+            Some(range) => range.map(Selection::Range),
+            None => Err(format!(
+                "invalid selection {:?}, expected \"all\", an id range or file:<path>",
+                s
+            )),
+        }
+    }
+}
+
+/// Parses `start..=end` or `start..end`, returning `None`
+/// if `s` is not shaped like a range of ids.
+fn parse_range(s: &str) -> Option<Result<RangeInclusive<u32>, String>> {
+    let (start, end, inclusive) = match s.find("..=") {
+        Some(idx) => (&s[..idx], &s[idx + 3..], true),
+        None => {
+            let idx = s.find("..")?;
+            (&s[..idx], &s[idx + 2..], false)
+        }
+    };
+
+    let is_number = |bound: &str| !bound.is_empty() && bound.bytes().all(|b| b.is_ascii_digit());
+
+    if !is_number(start) || !is_number(end) {
+        return None;
+    }
+
+    Some(bounded_range(start, end, inclusive))
+}
+
+fn bounded_range(start: &str, end: &str, inclusive: bool) -> Result<RangeInclusive<u32>, String> {
+    let parse = |bound: &str| {
+        bound
+            .parse::<u32>()
+            .map_err(|err| format!("invalid range bound {:?}: {}", bound, err))
+    };
+
+    let (start, end) = (parse(start)?, parse(end)?);
+
+    let end = match inclusive {
+        true => Some(end),
+        false => end.checked_sub(1),
+    };
+
+    match end {
+        Some(end) if start <= end && end - start < MAX_RANGE_LEN => Ok(start..=end),
+        Some(end) if start <= end => Err(format!(
+            "range too large, at most {} ids can be selected",
+            MAX_RANGE_LEN
+        )),
+        _ => Err("empty range".to_owned()),
+    }
+}
+
+impl Selection {
+    async fn resolve<C>(self, catalogue: &C) -> Result<Vec<u32>, String>
+    where
+        C: pokemon::Catalogue + Sync,
+    {
+        let names = match self {
+            Selection::Range(range) => return Ok(range.collect()),
+            Selection::All => {
+                let list = catalogue.list().await.map_err(|err| err.to_string())?;
+                return Ok(list.into_iter().map(|(id, _)| id).collect());
+            }
+            Selection::Names(path) => tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|err| format!("failed to read {:?}: {}", path, err))?,
+        };
+
+        let ids: HashMap<String, u32> = catalogue
+            .list()
+            .await
+            .map_err(|err| err.to_string())?
+            .into_iter()
+            .map(|(id, name)| (name, id))
+            .collect();
+
+        Ok(names
+            .lines()
+            .map(str::trim)
+            .filter(|name| !name.is_empty() && !name.starts_with('#'))
+            .filter_map(|name| match ids.get(name) {
+                Some(id) => Some(*id),
+                None => {
+                    log::warn!("Unknown Pokemon {:?} in warm-up list, skipping", name);
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+/// Shared handle to the progress of a cache warm-up.
+#[derive(Clone, Default)]
+pub struct WarmupStatus(Arc<Progress>);
+
+#[derive(Default)]
+struct Progress {
+    total: AtomicUsize,
+    loaded: AtomicUsize,
+    failed: AtomicUsize,
+    finished: AtomicBool,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct WarmupProgress {
+    pub total: usize,
+    pub loaded: usize,
+    pub failed: usize,
+    pub finished: bool,
+}
+
+impl WarmupStatus {
+    /// Returns a status for a cache with no warm-up to wait for.
+    pub fn skipped() -> Self {
+        let status = WarmupStatus::default();
+        status.0.finished.store(true, Ordering::SeqCst);
+        status
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.0.finished.load(Ordering::SeqCst)
+    }
+
+    pub fn progress(&self) -> WarmupProgress {
+        WarmupProgress {
+            total: self.0.total.load(Ordering::Relaxed),
+            loaded: self.0.loaded.load(Ordering::Relaxed),
+            failed: self.0.failed.load(Ordering::Relaxed),
+            finished: self.is_finished(),
+        }
+    }
+}
+
+/// Preloads the selected Pokémons into the cache in batches of
+/// `concurrency` lookups, updating `status` as it goes.
+///
+/// The warm-up is marked finished even if it fails, so as not to hold
+/// readiness forever: failures are logged and counted instead.
+pub async fn run<R, C>(
+    cache: CacheLayer<R>,
+    catalogue: C,
+    selection: Selection,
+    concurrency: usize,
+    status: WarmupStatus,
+) where
+    R: pokemon::Repository + Clone + Send + Sync + 'static,
+    R::Error: Send + Sync,
+    C: pokemon::Catalogue + Sync,
+{
+    let ids = match selection.resolve(&catalogue).await {
+        Ok(ids) => ids,
+        Err(err) => {
+            log::error!("Failed to resolve cache warm-up selection: {}", err);
+            status.0.finished.store(true, Ordering::SeqCst);
+            return;
+        }
+    };
+
+    let total = ids.len();
+    status.0.total.store(total, Ordering::Relaxed);
+    log::info!("Warming up cache with {} Pokemons", total);
+
+    for batch in ids.chunks(concurrency.max(1)) {
+        let results = cache.get_many(batch).await;
+
+        for (id, result) in batch.iter().zip(results) {
+            match result {
+                Ok(_) => status.0.loaded.fetch_add(1, Ordering::Relaxed),
+                Err(err) => {
+                    log::warn!("Failed to warm up Pokemon #{}: {}", id, err);
+                    status.0.failed.fetch_add(1, Ordering::Relaxed)
+                }
+            };
+        }
+
+        let progress = status.progress();
+        let done = progress.loaded + progress.failed;
+
+        if done / 100 > (done - batch.len()) / 100 {
+            log::info!("Cache warm-up progress: {}/{}", done, total);
+        }
+    }
+
+    status.0.finished.store(true, Ordering::SeqCst);
+
+    let progress = status.progress();
+    log::info!(
+        "Cache warm-up finished: {} loaded, {} failed",
+        progress.loaded,
+        progress.failed
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::{CacheConfig, WriteMode};
+    use crate::testing::Stub;
+
+    fn parse(s: &str) -> Result<Selection, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn parses_all() {
+        assert_eq!(parse("all"), Ok(Selection::All));
+    }
+
+    #[test]
+    fn parses_ranges() {
+        assert_eq!(parse("1..=151"), Ok(Selection::Range(1..=151)));
+        assert_eq!(parse("1..152"), Ok(Selection::Range(1..=151)));
+        assert_eq!(parse("25..=25"), Ok(Selection::Range(25..=25)));
+    }
+
+    #[test]
+    fn refuses_empty_ranges() {
+        assert!(parse("1..1").is_err());
+        assert!(parse("0..0").is_err());
+        assert!(parse("151..=1").is_err());
+    }
+
+    #[test]
+    fn refuses_unbounded_ranges() {
+        assert!(parse("1..=4294967295").is_err());
+        assert!(parse("1..=99999999999").is_err());
+        assert_eq!(
+            parse(&format!("1..={}", MAX_RANGE_LEN)),
+            Ok(Selection::Range(1..=MAX_RANGE_LEN))
+        );
+        assert!(parse(&format!("0..={}", MAX_RANGE_LEN)).is_err());
+    }
+
+    #[test]
+    fn parses_names_files() {
+        assert_eq!(
+            parse("file:names.txt"),
+            Ok(Selection::Names(PathBuf::from("names.txt")))
+        );
+        assert_eq!(
+            parse("file:../names.txt"),
+            Ok(Selection::Names(PathBuf::from("../names.txt")))
+        );
+        assert_eq!(
+            parse("file:1..=151"),
+            Ok(Selection::Names(PathBuf::from("1..=151")))
+        );
+    }
+
+    #[test]
+    fn refuses_unrecognized_selections() {
+        assert!(parse("names.txt").is_err());
+        assert!(parse("1-151").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("file:").is_err());
+    }
+
+    #[tokio::test]
+    async fn warms_up_in_batches() {
+        let upstream = Stub::with(1..=10);
+        let cache = CacheLayer::new(
+            upstream.clone(),
+            CacheConfig {
+                write_mode: WriteMode::WriteThrough,
+                ..CacheConfig::default()
+            },
+        );
+        let status = WarmupStatus::default();
+
+        run(
+            cache.clone(),
+            upstream.clone(),
+            Selection::All,
+            4,
+            status.clone(),
+        )
+        .await;
+
+        let batches: Vec<usize> = upstream.batches().iter().map(Vec::len).collect();
+        assert_eq!(batches, vec![4, 4, 2]);
+        assert_eq!(cache.storage().len().await, 10);
+
+        assert_eq!(
+            status.progress(),
+            WarmupProgress {
+                total: 10,
+                loaded: 10,
+                failed: 0,
+                finished: true,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn counts_the_failed_lookups() {
+        let upstream = Stub::with(1..=3);
+        let cache = CacheLayer::new(
+            upstream.clone(),
+            CacheConfig {
+                write_mode: WriteMode::WriteThrough,
+                ..CacheConfig::default()
+            },
+        );
+        let status = WarmupStatus::default();
+
+        upstream.set_failing(true);
+        run(cache, upstream, Selection::Range(1..=3), 2, status.clone()).await;
+
+        let progress = status.progress();
+        assert_eq!((progress.loaded, progress.failed), (0, 3));
+        assert!(progress.finished);
+    }
+}
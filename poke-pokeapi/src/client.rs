@@ -1,29 +1,176 @@
+use std::collections::{HashMap, HashSet};
+
+use futures::{StreamExt, TryStreamExt};
+
+use serde::de::DeserializeOwned;
+
 use crate::model;
+use crate::resource::{ResourceList, ResourceRef};
 
 pub const POKEAPI_URL: &str = "https://pokeapi.co/api/v2";
 
+/// Path of the api root in the urls of pokeapi resources.
+const API_PATH: &str = "/api/v2";
+
+const LIST_PAGE_SIZE: u32 = 100;
+const RESOLVE_CONCURRENCY: usize = 8;
+
 #[derive(Clone)]
 pub struct Client {
     client: reqwest::Client,
+    base_url: String,
 }
 
 impl Default for Client {
     fn default() -> Self {
+        Client::new(POKEAPI_URL)
+    }
+}
+
+impl Client {
+    /// Creates a client for the pokeapi instance at `base_url`, e.g. a local mirror.
+    pub fn new(base_url: impl Into<String>) -> Self {
         Client {
             client: reqwest::Client::new(),
+            base_url: base_url.into(),
         }
     }
-}
 
-impl Client {
+    /// Returns the Pokémon with the specified id, or `None` if pokeapi
+    /// answers with `404 Not Found`.
     pub async fn get_pokemon_by_id(&self, id: u32) -> Result<Option<model::Root>, reqwest::Error> {
-        let url = format!("{}/pokemon/{}", POKEAPI_URL, id);
+        let url = format!("{}/pokemon/{}", self.base_url, id);
+        let response = self.client.get(&url).send().await?;
+
+        // pokeapi answers with a plain-text body for unknown Pokémons.
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        response
+            .error_for_status()?
+            .json::<model::Root>()
+            .await
+            .map(Some)
+    }
+
+    /// Returns the pokeapi document of the Pokémon as is, without decoding it.
+    pub async fn get_pokemon_raw(&self, id: u32) -> Result<Option<Vec<u8>>, reqwest::Error> {
+        let url = format!("{}/pokemon/{}", self.base_url, id);
+        let response = self.client.get(&url).send().await?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let data = response.error_for_status()?.bytes().await?;
+
+        Ok(Some(data.to_vec()))
+    }
+
+    /// Checks that pokeapi answers, requesting the first page of the
+    /// Pokémons with a single entry: cheaper than any Pokémon document.
+    pub async fn ping(&self) -> Result<(), reqwest::Error> {
+        let url = format!("{}/pokemon?limit=1", self.base_url);
 
         self.client
             .get(&url)
             .send()
             .await?
-            .json::<Option<model::Root>>()
+            .error_for_status()
+            .map(|_| ())
+    }
+
+    pub async fn list_pokemons(&self) -> Result<Vec<ResourceRef<model::Root>>, reqwest::Error> {
+        let mut url = self.pokemons_url();
+        let mut resources = Vec::new();
+
+        loop {
+            let page = self.list_pokemons_page(&url).await?;
+            resources.extend(page.results);
+
+            match page.next {
+                Some(next) => url = next,
+                None => return Ok(resources),
+            }
+        }
+    }
+
+    /// Url of the first page of the Pokémons.
+    pub fn pokemons_url(&self) -> String {
+        format!("{}/pokemon?limit={}", self.base_url, LIST_PAGE_SIZE)
+    }
+
+    /// Returns the page of the Pokémons at `url`, with the url
+    /// of the next page, if any, pointing at `base_url`.
+    pub async fn list_pokemons_page(
+        &self,
+        url: &str,
+    ) -> Result<ResourceList<model::Root>, reqwest::Error> {
+        let mut page = self
+            .client
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ResourceList<model::Root>>()
+            .await?;
+
+        page.next = page.next.map(|next| self.rebase(&next));
+
+        Ok(page)
+    }
+
+    pub async fn resolve<T>(&self, resource: &ResourceRef<T>) -> Result<T, reqwest::Error>
+    where
+        T: DeserializeOwned,
+    {
+        self.client
+            .get(&self.rebase(&resource.url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<T>()
             .await
     }
+
+    /// Resolves all the specified references, returning the resolved
+    /// models in the same order. Each distinct url is requested only once.
+    pub async fn resolve_all<'a, T, I>(&self, resources: I) -> Result<Vec<T>, reqwest::Error>
+    where
+        T: DeserializeOwned + Clone + 'a,
+        I: IntoIterator<Item = &'a ResourceRef<T>>,
+    {
+        let resources: Vec<&ResourceRef<T>> = resources.into_iter().collect();
+
+        let mut seen = HashSet::new();
+        let unique = resources
+            .iter()
+            .filter(|resource| seen.insert(resource.url.as_str()))
+            .copied();
+
+        let resolved = futures::stream::iter(unique)
+            .map(|resource| async move {
+                let model = self.resolve(resource).await?;
+                Ok::<_, reqwest::Error>((resource.url.as_str(), model))
+            })
+            .buffer_unordered(RESOLVE_CONCURRENCY)
+            .try_collect::<HashMap<_, _>>()
+            .await?;
+
+        Ok(resources
+            .iter()
+            .map(|resource| resolved[resource.url.as_str()].clone())
+            .collect())
+    }
+
+    /// Points a url found in a pokeapi response at `base_url`: pokeapi links
+    /// its resources with absolute urls to pokeapi.co, which must not be
+    /// followed when the client is using a local mirror.
+    fn rebase(&self, url: &str) -> String {
+        match url.find(API_PATH) {
+            Some(idx) => format!("{}{}", self.base_url, &url[idx + API_PATH.len()..]),
+            None => url.to_owned(),
+        }
+    }
 }
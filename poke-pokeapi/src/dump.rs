@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt};
+
+use poke_domain::{pokemon, pokemon::Pokemon, pokemon::Source};
+
+use crate::model;
+
+/// Maximum number of files read concurrently for bulk lookups.
+const CONCURRENCY: usize = 16;
+
+/// Repository reading Pokémons from a local directory laid out like
+/// the official PokeAPI `api-data` dump, i.e. `pokemon/{id}/index.json`.
+///
+/// The directory is scanned once when opening the repository, building
+/// an id and name index; the Pokémon data itself is read lazily from disk.
+#[derive(Clone)]
+pub struct DumpRepository {
+    index: Arc<Index>,
+}
+
+struct Index {
+    paths: HashMap<u32, PathBuf>,
+    names: HashMap<String, u32>,
+}
+
+impl DumpRepository {
+    pub fn open<P: AsRef<Path>>(root: P) -> Result<Self, DumpError> {
+        let mut paths = HashMap::new();
+        let mut names = HashMap::new();
+
+        for entry in std::fs::read_dir(root.as_ref().join("pokemon"))? {
+            let entry = entry?;
+
+            // Skips the `pokemon/index.json` list and any non-numeric entry.
+            let id = match entry.file_name().to_str().and_then(|s| s.parse().ok()) {
+                Some(id) => id,
+                None => continue,
+            };
+
+            let path = entry.path().join("index.json");
+            let data = match std::fs::read(&path) {
+                Ok(data) => data,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    log::warn!("Skipping Pokemon #{}: missing {:?}", id, path);
+                    continue;
+                }
+                Err(err) => return Err(err.into()),
+            };
+
+            // A single bad entry should not make the whole dump unusable.
+            let root: model::Root = match serde_json::from_slice(&data) {
+                Ok(root) => root,
+                Err(err) => {
+                    log::warn!("Skipping Pokemon #{}: malformed {:?}: {}", id, path, err);
+                    continue;
+                }
+            };
+
+            names.insert(root.name, id);
+            paths.insert(id, path);
+        }
+
+        log::info!(
+            "Loaded {} Pokemons from dump {:?}",
+            paths.len(),
+            root.as_ref()
+        );
+
+        Ok(DumpRepository {
+            index: Arc::new(Index { paths, names }),
+        })
+    }
+
+    /// Returns the Pokédex id of the Pokémon with the specified name, if any.
+    pub fn id_of(&self, name: &str) -> Option<u32> {
+        self.index.names.get(name).copied()
+    }
+}
+
+impl pokemon::Repository for DumpRepository {
+    type Error = DumpError;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        let path = match self.index.paths.get(&num) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        let data = tokio::fs::read(path).await?;
+        let root: model::Root = serde_json::from_slice(&data)?;
+
+        Ok(Some(Pokemon::from(root)))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        Ok(self.get(num).await?.map(|pokemon| (pokemon, "dump")))
+    }
+
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        futures::stream::iter(ids.iter().copied())
+            .map(|num| self.get(num))
+            .buffered(CONCURRENCY)
+            .collect()
+            .await
+    }
+}
+
+impl pokemon::Listing for DumpRepository {
+    fn stream_all(&self) -> impl Stream<Item = Result<Pokemon, Self::Error>> + Send + '_ {
+        let mut ids: Vec<u32> = self.index.paths.keys().copied().collect();
+        ids.sort_unstable();
+
+        futures::stream::iter(ids)
+            .map(move |num| pokemon::Repository::get(self, num))
+            .buffered(CONCURRENCY)
+            .filter_map(|result| futures::future::ready(result.transpose()))
+    }
+}
+
+impl pokemon::Catalogue for DumpRepository {
+    type Error = DumpError;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        Ok(self
+            .index
+            .names
+            .iter()
+            .map(|(name, id)| (*id, name.clone()))
+            .collect())
+    }
+}
+
+#[derive(Debug)]
+pub enum DumpError {
+    Io { inner: std::io::Error },
+    Malformed { inner: serde_json::Error },
+}
+
+impl std::error::Error for DumpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use DumpError::*;
+
+        match self {
+            Io { inner } => Some(inner),
+            Malformed { inner } => Some(inner),
+        }
+    }
+}
+
+impl Display for DumpError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        use DumpError::*;
+
+        match self {
+            Io { inner } => write!(f, "failed to read pokeapi dump: {}", inner),
+            Malformed { inner } => write!(f, "malformed pokeapi dump entry: {}", inner),
+        }
+    }
+}
+
+impl From<std::io::Error> for DumpError {
+    fn from(error: std::io::Error) -> DumpError {
+        DumpError::Io { inner: error }
+    }
+}
+
+impl From<serde_json::Error> for DumpError {
+    fn from(error: serde_json::Error) -> DumpError {
+        DumpError::Malformed { inner: error }
+    }
+}
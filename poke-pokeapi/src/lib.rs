@@ -1,3 +1,6 @@
 pub mod client;
+pub mod dump;
+pub mod mirror;
 pub mod model;
 pub mod repository;
+pub mod resource;
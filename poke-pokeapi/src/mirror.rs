@@ -0,0 +1,176 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use futures::{StreamExt, TryStreamExt};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::client::Client;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// Crawls pokeapi.co into a local directory, using the same layout
+/// of the official `api-data` dump (readable by [`DumpRepository`]).
+///
+/// Resources already present on disk are not fetched again, so an
+/// interrupted mirror can be resumed by running it on the same directory.
+///
+/// [`DumpRepository`]: crate::dump::DumpRepository
+pub struct Mirror {
+    client: Client,
+    root: PathBuf,
+    concurrency: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    pub pokemon: Collection,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Collection {
+    pub count: usize,
+    /// SHA-256 checksums of every mirrored resource, keyed by id.
+    pub checksums: BTreeMap<u32, String>,
+}
+
+impl Mirror {
+    pub fn new(client: Client, root: PathBuf, concurrency: usize) -> Self {
+        Mirror {
+            client,
+            root,
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    pub async fn run(&self) -> Result<Manifest, MirrorError> {
+        let ids: Vec<u32> = self
+            .client
+            .list_pokemons()
+            .await?
+            .iter()
+            .filter_map(|resource| resource.id())
+            .collect();
+
+        let total = ids.len();
+        log::info!("Mirroring {} Pokemons into {:?}", total, self.root);
+
+        let checksums = futures::stream::iter(ids)
+            .map(|id| self.mirror_pokemon(id))
+            .buffer_unordered(self.concurrency)
+            .enumerate()
+            .map(|(done, result)| {
+                if (done + 1) % 100 == 0 {
+                    log::info!("Mirrored {}/{} Pokemons", done + 1, total);
+                }
+
+                result
+            })
+            .try_collect::<BTreeMap<_, _>>()
+            .await?;
+
+        let manifest = Manifest {
+            pokemon: Collection {
+                count: checksums.len(),
+                checksums,
+            },
+        };
+
+        tokio::fs::write(
+            self.root.join(MANIFEST_FILE),
+            serde_json::to_vec_pretty(&manifest)?,
+        )
+        .await?;
+
+        Ok(manifest)
+    }
+
+    async fn mirror_pokemon(&self, id: u32) -> Result<(u32, String), MirrorError> {
+        let dir = self.root.join("pokemon").join(id.to_string());
+        let path = dir.join("index.json");
+
+        let data = match tokio::fs::read(&path).await {
+            Ok(data) => {
+                log::debug!("Pokemon #{} already mirrored, skipping", id);
+                data
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                // Stores the document as served, so that the mirror is a faithful
+                // copy of pokeapi and the checksums cover the original data.
+                let data = self
+                    .client
+                    .get_pokemon_raw(id)
+                    .await?
+                    .ok_or(MirrorError::NotFound { id })?;
+
+                // Write to a temporary file first, so that an interruption
+                // never leaves a partial resource behind.
+                let tmp = dir.join("index.json.tmp");
+                tokio::fs::create_dir_all(&dir).await?;
+                tokio::fs::write(&tmp, &data).await?;
+                tokio::fs::rename(&tmp, &path).await?;
+
+                log::debug!("Mirrored Pokemon #{}", id);
+                data
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok((id, format!("{:x}", Sha256::digest(&data))))
+    }
+}
+
+#[derive(Debug)]
+pub enum MirrorError {
+    NotFound { id: u32 },
+    Client { inner: reqwest::Error },
+    Io { inner: std::io::Error },
+    Malformed { inner: serde_json::Error },
+}
+
+impl std::error::Error for MirrorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use MirrorError::*;
+
+        match self {
+            NotFound { .. } => None,
+            Client { inner } => Some(inner),
+            Io { inner } => Some(inner),
+            Malformed { inner } => Some(inner),
+        }
+    }
+}
+
+impl Display for MirrorError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        use MirrorError::*;
+
+        match self {
+            NotFound { id } => write!(f, "pokemon #{} listed but not found", id),
+            Client { inner } => write!(f, "pokeapi.co client failed: {}", inner),
+            Io { inner } => write!(f, "failed to write mirror: {}", inner),
+            Malformed { inner } => write!(f, "failed to serialize resource: {}", inner),
+        }
+    }
+}
+
+impl From<reqwest::Error> for MirrorError {
+    fn from(error: reqwest::Error) -> MirrorError {
+        MirrorError::Client { inner: error }
+    }
+}
+
+impl From<std::io::Error> for MirrorError {
+    fn from(error: std::io::Error) -> MirrorError {
+        MirrorError::Io { inner: error }
+    }
+}
+
+impl From<serde_json::Error> for MirrorError {
+    fn from(error: serde_json::Error) -> MirrorError {
+        MirrorError::Malformed { inner: error }
+    }
+}
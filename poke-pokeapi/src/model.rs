@@ -2,12 +2,15 @@ use serde::{Deserialize, Serialize};
 
 use poke_domain::pokemon;
 
+use crate::resource::ResourceRef;
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Root {
     // pub abilities: Vec<Ability>,
+    /// Missing for some alternative forms in the pokeapi data.
     #[serde(rename = "base_experience")]
-    pub base_experience: i64,
+    pub base_experience: Option<i64>,
     pub height: i64,
     pub id: i64,
     // pub moves: Vec<Mfe>,
@@ -65,7 +68,7 @@ impl From<Root> for pokemon::Pokemon {
             name: value.name,
             height: value.height as u32,
             weight: value.weight as u32,
-            base_experience: value.base_experience as u32,
+            base_experience: value.base_experience.unwrap_or_default() as u32,
             typ: Root::from_types(&value.types),
             stats: Root::from_stats(&value.stats),
         }
@@ -163,14 +166,19 @@ pub struct Stat {
     #[serde(rename = "base_stat")]
     pub base_stat: i64,
     pub effort: i64,
-    pub stat: Stat2,
+    pub stat: ResourceRef<StatDetail>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Stat2 {
+pub struct StatDetail {
+    pub id: i64,
     pub name: String,
-    pub url: String,
+    #[serde(rename = "game_index")]
+    pub game_index: i64,
+    #[serde(rename = "is_battle_only")]
+    pub is_battle_only: bool,
+    pub names: Vec<Name>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -178,7 +186,7 @@ pub struct Stat2 {
 pub struct Type {
     pub slot: i64,
     #[serde(rename = "type")]
-    pub type_field: Type2,
+    pub type_field: ResourceRef<TypeDetail>,
 }
 
 impl From<&Type> for pokemon::Element {
@@ -189,13 +197,47 @@ impl From<&Type> for pokemon::Element {
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
-pub struct Type2 {
+pub struct TypeDetail {
+    pub id: i64,
+    pub name: String,
+    #[serde(rename = "damage_relations")]
+    pub damage_relations: DamageRelations,
+    pub names: Vec<Name>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DamageRelations {
+    #[serde(rename = "no_damage_to")]
+    pub no_damage_to: Vec<ResourceRef<TypeDetail>>,
+    #[serde(rename = "half_damage_to")]
+    pub half_damage_to: Vec<ResourceRef<TypeDetail>>,
+    #[serde(rename = "double_damage_to")]
+    pub double_damage_to: Vec<ResourceRef<TypeDetail>>,
+    #[serde(rename = "no_damage_from")]
+    pub no_damage_from: Vec<ResourceRef<TypeDetail>>,
+    #[serde(rename = "half_damage_from")]
+    pub half_damage_from: Vec<ResourceRef<TypeDetail>>,
+    #[serde(rename = "double_damage_from")]
+    pub double_damage_from: Vec<ResourceRef<TypeDetail>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Name {
+    pub name: String,
+    pub language: ResourceRef<Language>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Language {
+    pub id: i64,
     pub name: String,
-    pub url: String,
 }
 
-impl From<&Type2> for pokemon::Element {
-    fn from(value: &Type2) -> pokemon::Element {
+impl From<&ResourceRef<TypeDetail>> for pokemon::Element {
+    fn from(value: &ResourceRef<TypeDetail>) -> pokemon::Element {
         // String implements Deref<Target=str> ;)
         match &*(value.name) {
             "normal" => pokemon::Element::Normal,
@@ -1,41 +1,116 @@
 use std::fmt::{Display, Formatter, Result as FmtResult};
 
-use futures::future::BoxFuture;
+use futures::future::Either;
+use futures::{Stream, StreamExt};
 
-use poke_domain::{pokemon, pokemon::Pokemon};
+use poke_domain::{pokemon, pokemon::Pokemon, pokemon::Source};
 
 use crate::client::Client;
 
+/// Maximum number of concurrent requests to pokeapi.co for bulk lookups.
+const CONCURRENCY: usize = 8;
+
 #[derive(Clone, Default)]
 pub struct PokemonRepository(Client);
 
+impl From<Client> for PokemonRepository {
+    #[inline]
+    fn from(client: Client) -> Self {
+        PokemonRepository(client)
+    }
+}
+
 impl pokemon::Repository for PokemonRepository {
     type Error = RepositoryError;
 
-    fn get<'a>(&'a self, num: u32) -> BoxFuture<'a, Result<Option<Pokemon>, Self::Error>>
-    where
-        Self: Sync + 'a,
-    {
-        Box::pin(async move {
-            Ok(self
-                .0
-                .get_pokemon_by_id(num)
-                .await
-                .map_err(RepositoryError::from)?
-                .map(Pokemon::from))
-        })
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self
+            .0
+            .get_pokemon_by_id(num)
+            .await
+            .map_err(RepositoryError::from)?
+            .map(Pokemon::from))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        Ok(self.get(num).await?.map(|pokemon| (pokemon, "pokeapi")))
+    }
+
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>> {
+        futures::stream::iter(ids.iter().copied())
+            .map(|num| self.get(num))
+            .buffered(CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        self.0.ping().await.map_err(RepositoryError::from)
+    }
+}
+
+impl pokemon::Listing for PokemonRepository {
+    /// Streams the Pokémons a page of the listing at a time, resolving
+    /// the Pokémons of each page with at most [`CONCURRENCY`] requests.
+    fn stream_all(&self) -> impl Stream<Item = Result<Pokemon, Self::Error>> + Send + '_ {
+        let client = &self.0;
+
+        let pages = futures::stream::unfold(Some(client.pokemons_url()), move |url| async move {
+            let page = client.list_pokemons_page(&url?).await;
+
+            // Stops at the last page, or at the first failure.
+            let next = page.as_ref().ok().and_then(|page| page.next.clone());
+            Some((page, next))
+        });
+
+        pages
+            .map(move |page| match page {
+                Ok(page) => Either::Left(
+                    futures::stream::iter(page.results)
+                        .map(move |resource| async move { client.resolve(&resource).await })
+                        .buffered(CONCURRENCY),
+                ),
+                Err(err) => Either::Right(futures::stream::once(futures::future::err(err))),
+            })
+            .flatten()
+            .map(|result| result.map(Pokemon::from).map_err(RepositoryError::from))
+    }
+}
+
+impl pokemon::Catalogue for PokemonRepository {
+    type Error = RepositoryError;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        Ok(self
+            .0
+            .list_pokemons()
+            .await
+            .map_err(RepositoryError::from)?
+            .into_iter()
+            .filter_map(|resource| Some((resource.id()?, resource.name)))
+            .collect())
     }
 }
 
 #[derive(Debug)]
 pub enum RepositoryError {
-    InternalServerError { inner: reqwest::Error },
+    /// pokeapi.co answered with an unexpected error status.
+    Status {
+        status: reqwest::StatusCode,
+        inner: reqwest::Error,
+    },
+    InternalServerError {
+        inner: reqwest::Error,
+    },
 }
 
 impl std::error::Error for RepositoryError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use RepositoryError::*;
+
         match self {
-            RepositoryError::InternalServerError { inner } => Some(inner),
+            Status { inner, .. } => Some(inner),
+            InternalServerError { inner } => Some(inner),
         }
     }
 }
@@ -45,6 +120,7 @@ impl Display for RepositoryError {
         use RepositoryError::*;
 
         match self {
+            Status { status, .. } => write!(f, "pokeapi.co answered with {}", status),
             InternalServerError { inner } => {
                 write!(f, "pokeapi.co client failed unrecoverably: {}", inner)
             }
@@ -54,10 +130,14 @@ impl Display for RepositoryError {
 
 impl From<reqwest::Error> for RepositoryError {
     fn from(error: reqwest::Error) -> RepositoryError {
-        if error.is_status() {
-            unimplemented!()
-        } else {
-            RepositoryError::InternalServerError { inner: error }
+        // Unknown Pokémons are answered with `404 Not Found`, which the
+        // client already maps to `None`: any status left is a failure.
+        match error.status() {
+            Some(status) => RepositoryError::Status {
+                status,
+                inner: error,
+            },
+            None => RepositoryError::InternalServerError { inner: error },
         }
     }
 }
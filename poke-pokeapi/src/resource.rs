@@ -0,0 +1,90 @@
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::marker::PhantomData;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::client::Client;
+
+/// Typed reference to another pokeapi resource, as found in the
+/// `{ "name": ..., "url": ... }` objects of most pokeapi responses.
+///
+/// The reference can be resolved into its target model `T` through a [`Client`].
+#[derive(Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct ResourceRef<T> {
+    pub name: String,
+    pub url: String,
+
+    #[serde(skip)]
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ResourceRef<T> {
+    pub fn new(name: String, url: String) -> Self {
+        ResourceRef {
+            name,
+            url,
+            marker: PhantomData,
+        }
+    }
+
+    /// Extracts the resource id from the trailing segment of its url,
+    /// e.g. `https://pokeapi.co/api/v2/pokemon/25/`.
+    pub fn id(&self) -> Option<u32> {
+        self.url
+            .trim_end_matches('/')
+            .rsplit('/')
+            .next()?
+            .parse()
+            .ok()
+    }
+}
+
+impl<T> ResourceRef<T>
+where
+    T: DeserializeOwned,
+{
+    pub async fn resolve(&self, client: &Client) -> Result<T, reqwest::Error> {
+        client.resolve(self).await
+    }
+}
+
+// Implemented by hand, since deriving would require the same traits on T.
+
+impl<T> Clone for ResourceRef<T> {
+    fn clone(&self) -> Self {
+        ResourceRef::new(self.name.clone(), self.url.clone())
+    }
+}
+
+impl<T> Default for ResourceRef<T> {
+    fn default() -> Self {
+        ResourceRef::new(String::default(), String::default())
+    }
+}
+
+impl<T> PartialEq for ResourceRef<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.url == other.url
+    }
+}
+
+impl<T> Debug for ResourceRef<T> {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        f.debug_struct("ResourceRef")
+            .field("name", &self.name)
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+/// Paginated list of references, as returned by the pokeapi list endpoints.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(bound = "")]
+pub struct ResourceList<T> {
+    pub count: i64,
+    pub next: Option<String>,
+    pub previous: Option<String>,
+    pub results: Vec<ResourceRef<T>>,
+}
@@ -0,0 +1,107 @@
+mod support;
+
+use std::time::Duration;
+
+use poke_pokeapi::client::Client;
+use poke_pokeapi::resource::ResourceRef;
+
+use support::Pokeapi;
+
+fn pokemon_ref(id: u32) -> ResourceRef<serde_json::Value> {
+    ResourceRef::new(
+        format!("pokemon-{}", id),
+        format!("https://pokeapi.co/api/v2/pokemon/{}/", id),
+    )
+}
+
+#[tokio::test]
+async fn resolves_references_on_the_base_url() {
+    let pokeapi = Pokeapi::new(vec![("/pokemon/25/", 200, br#"{"id": 25}"#)]);
+    let client = Client::new(pokeapi.serve().await);
+
+    let pikachu = client.resolve(&pokemon_ref(25)).await.unwrap();
+
+    assert_eq!(pikachu["id"], 25);
+    assert_eq!(pokeapi.requests(), vec!["/pokemon/25/"]);
+}
+
+#[tokio::test]
+async fn follows_the_pages_on_the_base_url() {
+    let first = br#"{"count": 2, "previous": null, "results": [
+        {"name": "bulbasaur", "url": "https://pokeapi.co/api/v2/pokemon/1/"}
+    ], "next": "https://pokeapi.co/api/v2/pokemon?offset=1&limit=100"}"#;
+    let second = br#"{"count": 2, "next": null, "results": [
+        {"name": "ivysaur", "url": "https://pokeapi.co/api/v2/pokemon/2/"}
+    ], "previous": "https://pokeapi.co/api/v2/pokemon?offset=0&limit=100"}"#;
+
+    let pokeapi = Pokeapi::new(vec![
+        ("/pokemon?limit=100", 200, first),
+        ("/pokemon?offset=1&limit=100", 200, second),
+    ]);
+    let client = Client::new(pokeapi.serve().await);
+
+    let names: Vec<String> = client
+        .list_pokemons()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|resource| resource.name)
+        .collect();
+
+    assert_eq!(names, vec!["bulbasaur", "ivysaur"]);
+}
+
+#[tokio::test]
+async fn resolve_all_requests_each_url_once() {
+    let pokeapi = Pokeapi::new(vec![
+        ("/pokemon/1/", 200, br#"{"id": 1}"#),
+        ("/pokemon/25/", 200, br#"{"id": 25}"#),
+    ]);
+    let client = Client::new(pokeapi.serve().await);
+
+    let refs = vec![pokemon_ref(25), pokemon_ref(1), pokemon_ref(25)];
+    let resolved = client.resolve_all(&refs).await.unwrap();
+
+    let ids: Vec<_> = resolved
+        .iter()
+        .map(|pokemon| pokemon["id"].clone())
+        .collect();
+    assert_eq!(ids, vec![25, 1, 25]);
+
+    let mut requests = pokeapi.requests();
+    requests.sort();
+    assert_eq!(requests, vec!["/pokemon/1/", "/pokemon/25/"]);
+}
+
+#[tokio::test]
+async fn resolve_all_bounds_the_concurrent_requests() {
+    let responses: Vec<(String, Vec<u8>)> = (1..=24)
+        .map(|id| {
+            (
+                format!("/pokemon/{}/", id),
+                format!(r#"{{"id": {}}}"#, id).into_bytes(),
+            )
+        })
+        .collect();
+
+    let pokeapi = Pokeapi::new(
+        responses
+            .iter()
+            .map(|(path, body)| (path.as_str(), 200, body.as_slice()))
+            .collect(),
+    )
+    .with_delay(Duration::from_millis(50));
+    let client = Client::new(pokeapi.serve().await);
+
+    let refs: Vec<_> = (1..=24).map(pokemon_ref).collect();
+    let resolved = client.resolve_all(&refs).await.unwrap();
+
+    assert_eq!(resolved.len(), 24);
+    assert_eq!(pokeapi.requests().len(), 24);
+    assert!(pokeapi.max_in_flight() > 1, "requests were not concurrent");
+    assert!(
+        pokeapi.max_in_flight() <= 8,
+        "{} requests in flight",
+        pokeapi.max_in_flight()
+    );
+}
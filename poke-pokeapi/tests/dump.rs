@@ -0,0 +1,59 @@
+//! Reads the small dump in `tests/fixtures/dump`, which has a form
+//! without base experience and a malformed entry (#999).
+
+use std::path::PathBuf;
+
+use futures::TryStreamExt;
+
+use poke_domain::pokemon::{Element, Listing, Repository, Type};
+use poke_pokeapi::dump::DumpRepository;
+
+fn fixture() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/dump")
+}
+
+#[tokio::test]
+async fn open_skips_malformed_entries() {
+    let dump = DumpRepository::open(fixture()).expect("failed to open the fixture dump");
+
+    assert_eq!(dump.id_of("bulbasaur"), Some(1));
+    assert_eq!(dump.id_of("pikachu"), Some(25));
+    assert_eq!(dump.id_of("broken"), None);
+
+    assert!(dump.get(999).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn get_reads_pokemons_from_disk() {
+    let dump = DumpRepository::open(fixture()).unwrap();
+
+    let bulbasaur = dump.get(1).await.unwrap().expect("bulbasaur not found");
+    assert_eq!(bulbasaur.name, "bulbasaur");
+    assert_eq!(bulbasaur.base_experience, 64);
+    assert_eq!(bulbasaur.typ, Type::Double(Element::Grass, Element::Poison));
+    assert_eq!(bulbasaur.stats.hit_points, 45);
+
+    assert!(dump.get(151).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn missing_base_experience_defaults_to_zero() {
+    let dump = DumpRepository::open(fixture()).unwrap();
+
+    let mimikyu = dump.get(10143).await.unwrap().expect("form not found");
+    assert_eq!(mimikyu.base_experience, 0);
+}
+
+#[tokio::test]
+async fn streams_the_pokemons_in_dex_order() {
+    let dump = DumpRepository::open(fixture()).unwrap();
+
+    let ids: Vec<u32> = dump
+        .stream_all()
+        .map_ok(|pokemon| pokemon.dex_id)
+        .try_collect()
+        .await
+        .unwrap();
+
+    assert_eq!(ids, vec![1, 25, 10143]);
+}
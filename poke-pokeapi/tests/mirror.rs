@@ -0,0 +1,57 @@
+mod support;
+
+use sha2::{Digest, Sha256};
+
+use poke_pokeapi::client::Client;
+use poke_pokeapi::mirror::{Manifest, Mirror, MANIFEST_FILE};
+
+use support::{temp_dir, Pokeapi};
+
+const LIST: &[u8] = br#"{"count": 2, "next": null, "previous": null, "results": [
+    {"name": "bulbasaur", "url": "https://pokeapi.co/api/v2/pokemon/1/"},
+    {"name": "pikachu", "url": "https://pokeapi.co/api/v2/pokemon/25/"}
+]}"#;
+
+// Fields unknown to the model and odd formatting must be kept as is.
+const PIKACHU: &[u8] =
+    br#"{ "id": 25, "name": "pikachu", "base_experience": 112, "is_default": true }"#;
+
+#[tokio::test]
+async fn mirror_stores_raw_documents_and_resumes() {
+    let root = temp_dir("mirror-resume");
+
+    // A previous, interrupted run already mirrored Bulbasaur.
+    let bulbasaur = br#"{"id": 1, "name": "bulbasaur"}"#;
+    std::fs::create_dir_all(root.join("pokemon/1")).unwrap();
+    std::fs::write(root.join("pokemon/1/index.json"), &bulbasaur[..]).unwrap();
+
+    let pokeapi = Pokeapi::new(vec![
+        ("/pokemon?limit=100", 200, LIST),
+        ("/pokemon/25", 200, PIKACHU),
+    ]);
+
+    let client = Client::new(pokeapi.serve().await);
+    let manifest = Mirror::new(client, root.clone(), 4).run().await.unwrap();
+
+    assert!(!pokeapi.requests().contains(&"/pokemon/1".to_owned()));
+
+    let pikachu = std::fs::read(root.join("pokemon/25/index.json")).unwrap();
+    assert_eq!(pikachu, PIKACHU);
+    assert!(!root.join("pokemon/25/index.json.tmp").exists());
+
+    assert_eq!(manifest.pokemon.count, 2);
+    assert_eq!(
+        manifest.pokemon.checksums[&1],
+        format!("{:x}", Sha256::digest(&bulbasaur[..]))
+    );
+    assert_eq!(
+        manifest.pokemon.checksums[&25],
+        format!("{:x}", Sha256::digest(PIKACHU))
+    );
+
+    let written: Manifest =
+        serde_json::from_slice(&std::fs::read(root.join(MANIFEST_FILE)).unwrap()).unwrap();
+    assert_eq!(written.pokemon.checksums, manifest.pokemon.checksums);
+
+    std::fs::remove_dir_all(root).unwrap();
+}
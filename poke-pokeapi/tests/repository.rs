@@ -0,0 +1,169 @@
+mod support;
+
+use std::time::Duration;
+
+use futures::{StreamExt, TryStreamExt};
+
+use poke_domain::pokemon::{Listing, Repository};
+
+use poke_pokeapi::client::Client;
+use poke_pokeapi::repository::{PokemonRepository, RepositoryError};
+
+use support::Pokeapi;
+
+const PIKACHU: &[u8] = include_bytes!("fixtures/dump/pokemon/25/index.json");
+
+#[tokio::test]
+async fn looks_up_pokemons() {
+    let pokeapi = Pokeapi::new(vec![("/pokemon/25", 200, PIKACHU)]);
+    let repository = PokemonRepository::from(Client::new(pokeapi.serve().await));
+
+    let pokemon = repository.get(25).await.unwrap().unwrap();
+
+    assert_eq!(pokemon.dex_id, 25);
+    assert_eq!(pokemon.name, "pikachu");
+}
+
+#[tokio::test]
+async fn unknown_pokemons_are_not_found() {
+    let pokeapi = Pokeapi::default();
+    let repository = PokemonRepository::from(Client::new(pokeapi.serve().await));
+
+    assert!(repository.get(99999).await.unwrap().is_none());
+    assert_eq!(pokeapi.requests(), vec!["/pokemon/99999"]);
+}
+
+#[tokio::test]
+async fn server_errors_are_reported_with_their_status() {
+    let pokeapi = Pokeapi::new(vec![("/pokemon/25", 500, b"oops")]);
+    let repository = PokemonRepository::from(Client::new(pokeapi.serve().await));
+
+    match repository.get(25).await {
+        Err(RepositoryError::Status { status, .. }) => assert_eq!(status.as_u16(), 500),
+        other => panic!("unexpected result: {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn batches_keep_the_order_of_the_ids() {
+    let pokeapi = Pokeapi::new(vec![
+        ("/pokemon/25", 200, PIKACHU),
+        ("/pokemon/1", 500, b"oops"),
+    ]);
+    let repository = PokemonRepository::from(Client::new(pokeapi.serve().await));
+
+    let results = repository.get_many(&[99999, 25, 1]).await;
+
+    assert!(matches!(results[0], Ok(None)));
+    assert!(matches!(results[1], Ok(Some(ref pokemon)) if pokemon.dex_id == 25));
+    assert!(matches!(results[2], Err(RepositoryError::Status { .. })));
+}
+
+/// Listing page of the Pokémons `ids`, pointing at the `next` page if any.
+fn page(ids: std::ops::RangeInclusive<u32>, next: Option<&str>) -> Vec<u8> {
+    let results: Vec<_> = ids
+        .map(|id| {
+            serde_json::json!({
+                "name": format!("pokemon-{}", id),
+                "url": format!("https://pokeapi.co/api/v2/pokemon/{}/", id),
+            })
+        })
+        .collect();
+
+    serde_json::to_vec(&serde_json::json!({
+        "count": 16,
+        "previous": null,
+        "next": next,
+        "results": results,
+    }))
+    .unwrap()
+}
+
+/// The Pikachu fixture, renumbered to `id`.
+fn pokemon(id: u32) -> Vec<u8> {
+    let mut pokemon: serde_json::Value = serde_json::from_slice(PIKACHU).unwrap();
+    pokemon["id"] = id.into();
+
+    serde_json::to_vec(&pokemon).unwrap()
+}
+
+#[tokio::test]
+async fn streams_the_pages_in_order_with_bounded_concurrency() {
+    let mut responses = vec![
+        (
+            "/pokemon?limit=100".to_owned(),
+            page(
+                1..=10,
+                Some("https://pokeapi.co/api/v2/pokemon?offset=10&limit=100"),
+            ),
+        ),
+        (
+            "/pokemon?offset=10&limit=100".to_owned(),
+            page(11..=16, None),
+        ),
+    ];
+    responses.extend((1..=16).map(|id| (format!("/pokemon/{}/", id), pokemon(id))));
+
+    let pokeapi = Pokeapi::new(
+        responses
+            .iter()
+            .map(|(path, body)| (path.as_str(), 200, body.as_slice()))
+            .collect(),
+    )
+    .with_delay(Duration::from_millis(20));
+    let repository = PokemonRepository::from(Client::new(pokeapi.serve().await));
+
+    let ids: Vec<u32> = repository
+        .stream_all()
+        .map_ok(|pokemon| pokemon.dex_id)
+        .try_collect()
+        .await
+        .unwrap();
+
+    assert_eq!(ids, (1..=16).collect::<Vec<_>>());
+    assert!(pokeapi.max_in_flight() > 1, "requests were not concurrent");
+    assert!(
+        pokeapi.max_in_flight() <= 8,
+        "{} requests in flight",
+        pokeapi.max_in_flight()
+    );
+
+    // The next page is only requested once the previous one is resolved.
+    let requests = pokeapi.requests();
+    let next_page = requests
+        .iter()
+        .position(|path| path == "/pokemon?offset=10&limit=100")
+        .unwrap();
+    assert_eq!(next_page, 11, "requests: {:?}", requests);
+}
+
+#[tokio::test]
+async fn streams_stop_at_the_first_failed_page() {
+    let pokeapi = Pokeapi::new(vec![("/pokemon?limit=100", 500, b"oops")]);
+    let repository = PokemonRepository::from(Client::new(pokeapi.serve().await));
+
+    let results: Vec<_> = repository.stream_all().collect().await;
+
+    assert_eq!(results.len(), 1);
+    assert!(matches!(results[0], Err(RepositoryError::Status { .. })));
+}
+
+#[tokio::test]
+async fn probes_with_a_single_entry_listing() {
+    let pokeapi = Pokeapi::new(vec![("/pokemon?limit=1", 200, b"{}")]);
+    let repository = PokemonRepository::from(Client::new(pokeapi.serve().await));
+
+    repository.probe().await.unwrap();
+    assert_eq!(pokeapi.requests(), vec!["/pokemon?limit=1"]);
+}
+
+#[tokio::test]
+async fn probes_fail_on_server_errors() {
+    let pokeapi = Pokeapi::new(vec![("/pokemon?limit=1", 503, b"down")]);
+    let repository = PokemonRepository::from(Client::new(pokeapi.serve().await));
+
+    match repository.probe().await {
+        Err(RepositoryError::Status { status, .. }) => assert_eq!(status.as_u16(), 503),
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
@@ -0,0 +1,121 @@
+//! Minimal HTTP server standing in for pokeapi.co in the tests.
+
+#![allow(dead_code)]
+
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Canned responses by request path (including the query), answering
+/// `404 Not Found` with a plain-text body to any other path, like pokeapi.co.
+#[derive(Clone, Default)]
+pub struct Pokeapi {
+    responses: Arc<HashMap<String, (u16, Vec<u8>)>>,
+    requests: Arc<Mutex<Vec<String>>>,
+    delay: Duration,
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: Arc<AtomicUsize>,
+}
+
+impl Pokeapi {
+    pub fn new(responses: Vec<(&str, u16, &[u8])>) -> Self {
+        let responses = responses
+            .into_iter()
+            .map(|(path, status, body)| (path.to_owned(), (status, body.to_vec())))
+            .collect();
+
+        Pokeapi {
+            responses: Arc::new(responses),
+            ..Pokeapi::default()
+        }
+    }
+
+    /// Answers every request after the delay, e.g. to observe concurrent requests.
+    pub fn with_delay(self, delay: Duration) -> Self {
+        Pokeapi { delay, ..self }
+    }
+
+    /// Starts serving on a random local port, returning the base url.
+    pub async fn serve(&self) -> String {
+        let mut listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = self.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let (mut socket, _) = match listener.accept().await {
+                    Ok(conn) => conn,
+                    Err(_) => return,
+                };
+
+                let server = server.clone();
+
+                tokio::spawn(async move {
+                    let mut request = Vec::new();
+                    let mut buf = [0; 1024];
+
+                    while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                        match socket.read(&mut buf).await {
+                            Ok(0) | Err(_) => return,
+                            Ok(n) => request.extend_from_slice(&buf[..n]),
+                        }
+                    }
+
+                    let request = String::from_utf8_lossy(&request);
+                    let path = request.split(' ').nth(1).unwrap_or("/").to_owned();
+                    server.requests.lock().unwrap().push(path.clone());
+
+                    let in_flight = server.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    server.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+                    tokio::time::delay_for(server.delay).await;
+                    server.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+                    let (status, body) = server
+                        .responses
+                        .get(&path)
+                        .cloned()
+                        .unwrap_or_else(|| (404, b"Not Found".to_vec()));
+
+                    let head = format!(
+                        "HTTP/1.1 {} X\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    );
+
+                    let _ = socket.write_all(head.as_bytes()).await;
+                    let _ = socket.write_all(&body).await;
+                });
+            }
+        });
+
+        format!("http://{}", addr)
+    }
+
+    /// Maximum number of requests served at the same time so far.
+    pub fn max_in_flight(&self) -> usize {
+        self.max_in_flight.load(Ordering::SeqCst)
+    }
+
+    /// Paths requested so far.
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Empty directory for a test, under the system temporary directory.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("poke-pokeapi-{}-{}", name, std::process::id()));
+
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
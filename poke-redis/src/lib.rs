@@ -0,0 +1,275 @@
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::time::Duration;
+
+use redis::aio::MultiplexedConnection;
+
+use poke_domain::{pokemon, pokemon::Pokemon, pokemon::Source};
+
+pub const DEFAULT_KEY_PREFIX: &str = "poke:pokemon:";
+
+/// Default time after which a redis command is given up.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Repository decorator caching the Pokémons found upstream in Redis,
+/// so that the cache can be shared by several instances.
+///
+/// Redis failures never fail lookups: they are logged, and the lookup
+/// falls back to the upstream repository. Redis commands are given up
+/// after [`RedisConfig::timeout`], so that an unresponsive Redis costs
+/// at most that much on top of the upstream latency.
+#[derive(Clone)]
+pub struct RedisCache<R> {
+    upstream: R,
+    connection: MultiplexedConnection,
+    config: RedisConfig,
+}
+
+#[derive(Clone, Debug)]
+pub struct RedisConfig {
+    /// Prefix of the keys the Pokémons are stored under, followed by their id.
+    pub key_prefix: String,
+
+    /// Time after which a stored Pokémon expires.
+    /// Stored Pokémons never expire if not set.
+    pub ttl: Option<Duration>,
+
+    /// Time after which a redis command is given up.
+    pub timeout: Duration,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        RedisConfig {
+            key_prefix: String::from(DEFAULT_KEY_PREFIX),
+            ttl: None,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+impl<R> RedisCache<R> {
+    pub async fn connect(
+        url: &str,
+        upstream: R,
+        config: RedisConfig,
+    ) -> Result<RedisCache<R>, redis::RedisError> {
+        let connection = redis::Client::open(url)?
+            .get_multiplexed_tokio_connection()
+            .await?;
+
+        Ok(RedisCache {
+            upstream,
+            connection,
+            config,
+        })
+    }
+
+    fn key(&self, num: u32) -> String {
+        format!("{}{}", self.config.key_prefix, num)
+    }
+
+    async fn lookup(&self, num: u32) -> Result<Option<Pokemon>, RedisCacheError> {
+        let mut found = self.lookup_many(&[num]).await?;
+        Ok(found.pop().flatten())
+    }
+
+    /// Looks up all the specified ids with a single `MGET`.
+    ///
+    /// Malformed Pokémons are logged and reported as missing,
+    /// so that they are replaced by the upstream ones.
+    async fn lookup_many(&self, ids: &[u32]) -> Result<Vec<Option<Pokemon>>, RedisCacheError> {
+        let keys: Vec<String> = ids.iter().map(|num| self.key(*num)).collect();
+
+        let mut connection = self.connection.clone();
+        let mut cmd = redis::cmd("MGET");
+        cmd.arg(keys);
+
+        let data: Vec<Option<Vec<u8>>> =
+            tokio::time::timeout(self.config.timeout, cmd.query_async(&mut connection))
+                .await
+                .map_err(|_| RedisCacheError::Timeout)??;
+
+        let decode = |(num, data): (&u32, Option<Vec<u8>>)| match serde_json::from_slice(&data?) {
+            Ok(pokemon) => Some(pokemon),
+            Err(err) => {
+                log::warn!("Malformed Pokemon #{} in redis: {}", num, err);
+                None
+            }
+        };
+
+        Ok(ids.iter().zip(data).map(decode).collect())
+    }
+
+    async fn store(&self, pokemon: &Pokemon) -> Result<(), RedisCacheError> {
+        self.store_many(std::slice::from_ref(pokemon)).await
+    }
+
+    /// Stores all the specified Pokémons with a single pipeline of `SET`s.
+    async fn store_many(&self, pokemons: &[Pokemon]) -> Result<(), RedisCacheError> {
+        let mut pipeline = redis::pipe();
+
+        for pokemon in pokemons {
+            let cmd = pipeline
+                .cmd("SET")
+                .arg(self.key(pokemon.dex_id))
+                .arg(serde_json::to_vec(pokemon)?);
+
+            if let Some(ttl) = self.config.ttl {
+                // Sub-second ttls would be rounded by EX.
+                cmd.arg("PX").arg(ttl.as_millis().max(1) as u64);
+            }
+
+            cmd.ignore();
+        }
+
+        let mut connection = self.connection.clone();
+
+        tokio::time::timeout(
+            self.config.timeout,
+            pipeline.query_async::<_, ()>(&mut connection),
+        )
+        .await
+        .map_err(|_| RedisCacheError::Timeout)??;
+
+        Ok(())
+    }
+}
+
+impl<R> pokemon::Repository for RedisCache<R>
+where
+    R: pokemon::Repository + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        Ok(self.get_sourced(num).await?.map(|(pokemon, _)| pokemon))
+    }
+
+    async fn get_sourced(&self, num: u32) -> Result<Option<(Pokemon, Source)>, Self::Error> {
+        match self.lookup(num).await {
+            Ok(Some(pokemon)) => {
+                log::debug!("Got Pokemon #{} from redis", num);
+                return Ok(Some((pokemon, "redis")));
+            }
+            Ok(None) => (),
+            Err(err) => log::warn!("Failed to look up Pokemon #{} in redis: {}", num, err),
+        }
+
+        let result = self.upstream.get_sourced(num).await?;
+
+        if let Some((ref pokemon, _)) = result {
+            if let Err(err) = self.store(pokemon).await {
+                log::warn!("Failed to store Pokemon #{} in redis: {}", num, err);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// Serves the Pokémons found in redis, forwarding all the others in a single batch.
+    async fn get_many(&self, ids: &[u32]) -> Vec<Result<Option<Pokemon>, Self::Error>>
+    where
+        Self::Error: Send,
+    {
+        let cached = match self.lookup_many(ids).await {
+            Ok(cached) => cached,
+            Err(err) => {
+                log::warn!("Failed to look up Pokemons {:?} in redis: {}", ids, err);
+                vec![None; ids.len()]
+            }
+        };
+
+        let misses: Vec<u32> = ids
+            .iter()
+            .zip(&cached)
+            .filter(|(_, pokemon)| pokemon.is_none())
+            .map(|(num, _)| *num)
+            .collect();
+
+        if misses.is_empty() {
+            return cached.into_iter().map(Ok).collect();
+        }
+
+        let fetched = self.upstream.get_many(&misses).await;
+
+        // Collected first, since the upstream errors might not be `Sync`.
+        let found: Vec<Pokemon> = fetched
+            .iter()
+            .filter_map(|result| result.as_ref().ok()?.clone())
+            .collect();
+
+        if !found.is_empty() {
+            if let Err(err) = self.store_many(&found).await {
+                log::warn!("Failed to store Pokemons {:?} in redis: {}", misses, err);
+            }
+        }
+
+        let mut fetched = fetched.into_iter();
+
+        cached
+            .into_iter()
+            .map(|pokemon| match pokemon {
+                Some(pokemon) => Ok(Some(pokemon)),
+                None => fetched.next().expect("missing upstream result"),
+            })
+            .collect()
+    }
+
+    async fn probe(&self) -> Result<(), Self::Error> {
+        self.upstream.probe().await
+    }
+}
+
+impl<R> pokemon::Catalogue for RedisCache<R>
+where
+    R: pokemon::Catalogue + Send + Sync,
+{
+    type Error = R::Error;
+
+    async fn list(&self) -> Result<Vec<pokemon::CatalogueEntry>, Self::Error> {
+        self.upstream.list().await
+    }
+}
+
+#[derive(Debug)]
+pub enum RedisCacheError {
+    Redis { inner: redis::RedisError },
+    Malformed { inner: serde_json::Error },
+    Timeout,
+}
+
+impl std::error::Error for RedisCacheError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use RedisCacheError::*;
+
+        match self {
+            Redis { inner } => Some(inner),
+            Malformed { inner } => Some(inner),
+            Timeout => None,
+        }
+    }
+}
+
+impl Display for RedisCacheError {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        use RedisCacheError::*;
+
+        match self {
+            Redis { inner } => write!(f, "redis command failed: {}", inner),
+            Malformed { inner } => write!(f, "malformed pokemon in redis: {}", inner),
+            Timeout => write!(f, "redis command timed out"),
+        }
+    }
+}
+
+impl From<redis::RedisError> for RedisCacheError {
+    fn from(error: redis::RedisError) -> RedisCacheError {
+        RedisCacheError::Redis { inner: error }
+    }
+}
+
+impl From<serde_json::Error> for RedisCacheError {
+    fn from(error: serde_json::Error) -> RedisCacheError {
+        RedisCacheError::Malformed { inner: error }
+    }
+}
@@ -0,0 +1,196 @@
+//! Tests of the redis cache.
+//!
+//! The tests against a live Redis are ignored by default: start the Redis
+//! of the compose file and run them with `cargo test -p poke-redis -- --ignored`,
+//! or point `POKE_REDIS_URL` to another instance.
+
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use redis::aio::MultiplexedConnection;
+use tokio::net::TcpListener;
+
+use poke_domain::pokemon::{self, Element, Pokemon, Repository, Stats, Type};
+use poke_redis::{RedisCache, RedisConfig};
+
+fn pokemon(dex_id: u32) -> Pokemon {
+    Pokemon {
+        dex_id,
+        name: format!("pokemon-{}", dex_id),
+        typ: Type::Single(Element::Electric),
+        height: 4,
+        weight: 60,
+        base_experience: 112,
+        stats: Stats {
+            speed: 90,
+            special_defense: 50,
+            special_attack: 50,
+            defense: 40,
+            attack: 55,
+            hit_points: 35,
+        },
+    }
+}
+
+/// Upstream finding every Pokémon, counting its lookups.
+#[derive(Clone, Default)]
+struct Upstream(Arc<AtomicUsize>);
+
+impl Upstream {
+    fn calls(&self) -> usize {
+        self.0.load(Ordering::SeqCst)
+    }
+}
+
+impl pokemon::Repository for Upstream {
+    type Error = std::convert::Infallible;
+
+    async fn get(&self, num: u32) -> Result<Option<Pokemon>, Self::Error> {
+        self.0.fetch_add(1, Ordering::SeqCst);
+        Ok(Some(pokemon(num)))
+    }
+}
+
+fn redis_url() -> String {
+    std::env::var("POKE_REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_owned())
+}
+
+/// Configuration with keys private to the test, so that runs never clash.
+fn config(test: &str) -> RedisConfig {
+    RedisConfig {
+        key_prefix: format!("poke:test:{}:{}:", std::process::id(), test),
+        ..RedisConfig::default()
+    }
+}
+
+async fn connection() -> MultiplexedConnection {
+    redis::Client::open(redis_url().as_str())
+        .unwrap()
+        .get_multiplexed_tokio_connection()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+#[ignore]
+async fn misses_are_stored_and_then_hit() {
+    let upstream = Upstream::default();
+    let cache = RedisCache::connect(&redis_url(), upstream.clone(), config("hit"))
+        .await
+        .unwrap();
+
+    let (_, source) = cache.get_sourced(25).await.unwrap().unwrap();
+    assert_eq!(source, "upstream");
+    assert_eq!(upstream.calls(), 1);
+
+    let (found, source) = cache.get_sourced(25).await.unwrap().unwrap();
+    assert_eq!(source, "redis");
+    assert_eq!(found, pokemon(25));
+    assert_eq!(upstream.calls(), 1);
+}
+
+#[tokio::test]
+#[ignore]
+async fn batches_store_the_misses_and_hit_the_rest() {
+    let upstream = Upstream::default();
+    let cache = RedisCache::connect(&redis_url(), upstream.clone(), config("batch"))
+        .await
+        .unwrap();
+
+    cache.get(1).await.unwrap();
+
+    let found = cache.get_many(&[1, 4, 7]).await;
+    let ids: Vec<_> = found
+        .into_iter()
+        .map(|result| result.unwrap().unwrap().dex_id)
+        .collect();
+
+    assert_eq!(ids, vec![1, 4, 7]);
+    assert_eq!(upstream.calls(), 3);
+
+    cache.get_many(&[1, 4, 7]).await;
+    assert_eq!(upstream.calls(), 3);
+}
+
+#[tokio::test]
+#[ignore]
+async fn stored_pokemons_expire_after_the_ttl() {
+    let config = RedisConfig {
+        ttl: Some(Duration::from_millis(1500)),
+        ..config("ttl")
+    };
+    let key = format!("{}25", config.key_prefix);
+
+    let cache = RedisCache::connect(&redis_url(), Upstream::default(), config)
+        .await
+        .unwrap();
+    cache.get(25).await.unwrap();
+
+    let ttl: i64 = redis::cmd("PTTL")
+        .arg(&key)
+        .query_async(&mut connection().await)
+        .await
+        .unwrap();
+
+    assert!(ttl > 1000 && ttl <= 1500, "ttl: {}ms", ttl);
+}
+
+#[tokio::test]
+#[ignore]
+async fn malformed_pokemons_are_replaced_from_the_upstream() {
+    let config = config("malformed");
+    let key = format!("{}25", config.key_prefix);
+
+    redis::cmd("SET")
+        .arg(&key)
+        .arg("{not a pokemon")
+        .query_async::<_, ()>(&mut connection().await)
+        .await
+        .unwrap();
+
+    let upstream = Upstream::default();
+    let cache = RedisCache::connect(&redis_url(), upstream.clone(), config)
+        .await
+        .unwrap();
+
+    assert_eq!(cache.get(25).await.unwrap(), Some(pokemon(25)));
+    assert_eq!(upstream.calls(), 1);
+
+    let (_, source) = cache.get_sourced(25).await.unwrap().unwrap();
+    assert_eq!(source, "redis");
+}
+
+#[tokio::test]
+async fn unresponsive_redis_falls_back_to_the_upstream() {
+    // Accepts connections and never answers.
+    let mut listener = TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    tokio::spawn(async move {
+        let mut sockets = Vec::new();
+
+        while let Ok((socket, _)) = listener.accept().await {
+            sockets.push(socket);
+        }
+    });
+
+    let config = RedisConfig {
+        timeout: Duration::from_millis(50),
+        ..RedisConfig::default()
+    };
+
+    let upstream = Upstream::default();
+    let cache = RedisCache::connect(&format!("redis://{}", addr), upstream.clone(), config)
+        .await
+        .unwrap();
+
+    assert_eq!(cache.get(25).await.unwrap(), Some(pokemon(25)));
+
+    let found = cache.get_many(&[1, 4]).await;
+    assert!(found.iter().all(|result| matches!(result, Ok(Some(_)))));
+    assert_eq!(upstream.calls(), 3);
+}
@@ -1,37 +1,403 @@
+use std::future;
+use std::time::Duration;
+
 use eventually::command::dispatcher::DirectDispatcher;
 use eventually::optional::CommandHandler;
 use eventually::versioned::{CommandHandlerExt, Versioned};
 use eventually_memory::Store;
 
 use structopt::StructOpt;
-use warp::Filter;
+use warp::filters::BoxedFilter;
+use warp::{Filter, Reply};
 
-use poke_cli::{App, Subcommand};
+use poke_cli::{App, Mirror, Subcommand, Web};
+use poke_domain::pokemon;
 use poke_domain::trainer::{TrainerCommandHandler, TrainerEvent};
+use poke_http::health::{self, Readiness};
+use poke_http::metrics::Registry;
+use poke_layer::fault::{FaultConfig, FaultInjection};
+use poke_layer::limit::ConcurrencyLimit;
+use poke_layer::logging::Logging;
+use poke_layer::metrics::Metrics;
+use poke_layer::retry::Retry;
+use poke_layer::timeout::Timeout;
+use poke_layer::{
+    ConcurrencyLimitLayer, FaultLayer, LoggingLayer, MetricsLayer, RepositoryBuilder, RetryLayer,
+    TimeoutLayer,
+};
+use poke_memory::breaker::{BreakerConfig, BreakerLayer};
+use poke_memory::cache::{CacheConfig, CacheLayer, WriteMode};
+use poke_memory::custom::CustomLayer;
+use poke_memory::fallback::{FallbackLayer, FallbackPolicy};
+use poke_memory::names::{self, NameIndex};
+use poke_memory::patch::PatchLayer;
+use poke_memory::snapshot;
+use poke_memory::warmup::{self, WarmupStatus};
+use poke_memory::InMemoryRepository;
+use poke_pokeapi::repository::PokemonRepository;
+use poke_redis::{RedisCache, RedisConfig};
 
 #[tokio::main]
 async fn main() {
     env_logger::init();
 
     match App::from_args().subcommand {
-        Subcommand::Web { port } => web(port).await,
+        Subcommand::Web(opts) => web(opts).await,
+        Subcommand::Mirror(opts) => mirror(opts).await,
+    }
+}
+
+async fn mirror(opts: Mirror) {
+    let client = poke_pokeapi::client::Client::default();
+
+    let manifest = poke_pokeapi::mirror::Mirror::new(client, opts.output, opts.concurrency)
+        .run()
+        .await
+        .expect("failed to mirror pokeapi");
+
+    log::info!("Mirrored {} Pokemons", manifest.pokemon.count);
+}
+
+async fn web(opts: Web) {
+    let registry = Registry::new();
+
+    match (&opts.pokeapi_dump, &opts.fallback.dump) {
+        (Some(path), _) => {
+            if opts.fallback.dump.is_some() {
+                log::warn!("Serving from pokeapi dump, ignoring --fallback-dump");
+            }
+
+            let dump = poke_pokeapi::dump::DumpRepository::open(path)
+                .expect("failed to open pokeapi dump");
+
+            let dump = metered(dump, "dump", &registry);
+            with_redis(opts, dump, no_routes(), registry).await
+        }
+        (None, Some(path)) => {
+            let dump = poke_pokeapi::dump::DumpRepository::open(path)
+                .expect("failed to open pokeapi fallback dump");
+
+            let dump = metered(dump, "dump", &registry);
+
+            let policy = FallbackPolicy {
+                on_error: true,
+                on_not_found: opts.fallback.on_not_found,
+                timeout: opts.fallback.timeout.map(Duration::from_millis),
+            };
+
+            let (pokeapi, routes) = pokeapi(&opts, &registry);
+            let fallback = FallbackLayer::new(pokeapi, dump, policy);
+
+            let routes = routes
+                .or(poke_http::fallback(fallback.clone()))
+                .map(|reply| Box::new(reply) as Box<dyn Reply>)
+                .boxed();
+
+            with_redis(opts, fallback, routes, registry).await
+        }
+        (None, None) => {
+            let (pokeapi, routes) = pokeapi(&opts, &registry);
+            with_redis(opts, pokeapi, routes, registry).await
+        }
+    }
+}
+
+type Pokeapi = BreakerLayer<
+    Logging<Metrics<Retry<Timeout<ConcurrencyLimit<FaultInjection<PokemonRepository>>>>>>,
+>;
+
+/// Returns the pokeapi.co repository, behind a circuit breaker, together
+/// with the routes exposing its health and metrics.
+fn pokeapi(opts: &Web, registry: &Registry) -> (Pokeapi, UpstreamRoutes) {
+    let metrics = MetricsLayer::new();
+    registry.backend("pokeapi", metrics.handle());
+
+    let repository = RepositoryBuilder::new()
+        .layer(LoggingLayer::new("pokeapi"))
+        .layer(metrics.clone())
+        .layer(RetryLayer::new(
+            opts.upstream.retries,
+            Duration::from_millis(opts.upstream.retry_backoff),
+        ))
+        .layer(TimeoutLayer::new(Duration::from_millis(
+            opts.upstream.timeout,
+        )))
+        .layer(ConcurrencyLimitLayer::new(opts.upstream.concurrency))
+        .layer(faults(opts))
+        .build(PokemonRepository::default());
+
+    let config = BreakerConfig {
+        failure_threshold: opts.breaker.threshold,
+        cooldown: Duration::from_secs(opts.breaker.cooldown),
+    };
+
+    let repository = BreakerLayer::new(repository, config);
+
+    let routes = poke_http::breaker(repository.clone())
+        .or(poke_http::upstream_metrics(metrics.handle()))
+        .map(|reply| Box::new(reply) as Box<dyn Reply>)
+        .boxed();
+
+    (repository, routes)
+}
+
+/// Collects the lookup metrics of a repository, exposed as the specified backend.
+fn metered<R>(repository: R, backend: &'static str, registry: &Registry) -> Metrics<R> {
+    let metrics = MetricsLayer::new();
+    registry.backend(backend, metrics.handle());
+
+    RepositoryBuilder::new().layer(metrics).build(repository)
+}
+
+fn faults(opts: &Web) -> FaultLayer {
+    let faults = &opts.faults;
+
+    if !faults.enabled {
+        return FaultLayer::disabled();
+    }
+
+    log::warn!(
+        "Fault injection enabled on pokeapi.co requests: {:?}",
+        faults
+    );
+
+    FaultLayer::new(FaultConfig {
+        seed: faults.seed,
+        latency: Duration::from_millis(faults.latency),
+        jitter: Duration::from_millis(faults.jitter),
+        error_rate: faults.error_rate,
+        not_found_rate: faults.not_found_rate,
+        hang_rate: faults.hang_rate,
+    })
+}
+
+/// Routes specific to the upstream repository in use.
+type UpstreamRoutes = BoxedFilter<(Box<dyn Reply>,)>;
+
+fn no_routes() -> UpstreamRoutes {
+    warp::any()
+        .and_then(|| async { Err::<Box<dyn Reply>, _>(warp::reject::not_found()) })
+        .boxed()
+}
+
+async fn with_redis<R>(opts: Web, upstream: R, routes: UpstreamRoutes, registry: Registry)
+where
+    R: pokemon::Repository + pokemon::Catalogue + Send + Sync + Clone + 'static,
+    <R as pokemon::Repository>::Error: Send + Sync + 'static,
+{
+    let url = match opts.redis.url {
+        None => return serve(opts, upstream, routes, registry).await,
+        Some(ref url) => url,
+    };
+
+    let config = RedisConfig {
+        key_prefix: opts.redis.key_prefix.clone(),
+        ttl: opts.redis.ttl.map(Duration::from_secs),
+        timeout: Duration::from_millis(opts.redis.timeout),
+    };
+
+    let url = url.clone();
+
+    match RedisCache::connect(&url, upstream.clone(), config).await {
+        Ok(redis) => {
+            let redis = metered(redis, "redis", &registry);
+            serve(opts, redis, routes, registry).await
+        }
+        Err(err) => {
+            log::error!(
+                "Failed to connect to redis at {}, serving without it: {}",
+                url,
+                err
+            );
+            serve(opts, upstream, routes, registry).await
+        }
     }
 }
 
-async fn web(port: u16) {
+async fn serve<R>(opts: Web, upstream: R, routes: UpstreamRoutes, registry: Registry)
+where
+    R: pokemon::Repository + pokemon::Catalogue + Send + Sync + Clone + 'static,
+    <R as pokemon::Repository>::Error: Send + Sync + 'static,
+{
     let logger = warp::log("poke");
 
-    let poke_api = poke_pokeapi::repository::PokemonRepository::default();
-    let repository = poke_memory::cache::CacheLayer::from(poke_api);
+    let cache = CacheConfig {
+        capacity: opts.cache.capacity,
+        ttl: opts.cache.ttl.map(Duration::from_secs),
+        stale_while_revalidate: opts.cache.stale_while_revalidate,
+        negative_ttl: opts.cache.negative_ttl.map(Duration::from_secs),
+        write_mode: opts
+            .cache
+            .write_buffer
+            .map_or_else(WriteMode::default, |buffer| WriteMode::Background {
+                buffer,
+            }),
+    };
+
+    let cache = CacheLayer::new(upstream.clone(), cache);
+
+    // The upstream is reported, but not required for readiness:
+    // the cache and the fallback repository can still serve while it is down.
+    let readiness = Readiness::new().optional("upstream", {
+        let upstream = upstream.clone();
+        move || health::probe(upstream.clone())
+    });
+
+    if let Some(ref path) = opts.cache.snapshot {
+        let storage = cache.storage().clone();
+
+        match storage.load_snapshot(path).await {
+            Ok(count) => log::info!("Loaded {} Pokemons from snapshot {:?}", count, path),
+            Err(err) => log::warn!("Skipping snapshot {:?}: {}", path, err),
+        }
+
+        let interval = Duration::from_secs(opts.cache.snapshot_interval);
+        tokio::spawn(snapshot::save_periodically(storage, path.clone(), interval));
+    }
+
+    let warmup = match opts.cache.warmup {
+        None => WarmupStatus::skipped(),
+        Some(ref selection) => {
+            let status = WarmupStatus::default();
+
+            tokio::spawn(warmup::run(
+                cache.clone(),
+                upstream,
+                selection.clone(),
+                opts.cache.warmup_concurrency,
+                status.clone(),
+            ));
+
+            status
+        }
+    };
+
+    let readiness = readiness.critical("cache_warmup", {
+        let warmup = warmup.clone();
+        move || future::ready(health::warmup(&warmup))
+    });
+
+    let patches = match opts.patches {
+        None => PatchLayer::from(cache.clone()),
+        Some(ref path) => PatchLayer::open(cache.clone(), path.clone())
+            .await
+            .expect("failed to load patches"),
+    };
+
+    tokio::spawn(reload_on_hangup(patches.clone()));
+
+    let custom = InMemoryRepository::default();
+    let mut repository = CustomLayer::new(patches.clone(), custom.clone());
+
+    if let Some(ref path) = opts.admin.custom_pokemons {
+        if path.exists() {
+            let count = custom
+                .load_snapshot(path)
+                .await
+                .expect("failed to load custom pokemons");
+
+            log::info!("Loaded {} custom Pokemons from {:?}", count, path);
+        }
+
+        repository = repository.with_snapshot(path.clone());
+    }
+
+    let names = NameIndex::default();
+    let period = Duration::from_secs(opts.names_refresh);
+    tokio::spawn(names::refresh_periodically(
+        names.clone(),
+        repository.clone(),
+        period,
+    ));
+
+    // Lookups by id are still served while the names are being loaded.
+    let readiness = readiness.optional("names", {
+        let names = names.clone();
+        move || future::ready(health::names(&names))
+    });
 
     let handler = TrainerCommandHandler::new(repository.clone())
         .as_handler()
         .versioned();
 
     let event_store = Store::<String, Versioned<TrainerEvent>>::default();
-    let dispatcher = DirectDispatcher::new(event_store, handler);
+    let dispatcher = DirectDispatcher::new(registry.event_store(event_store.clone()), handler);
+
+    let readiness = readiness.critical("event_store", move || {
+        health::event_store(event_store.clone())
+    });
+
+    // Upstream routes go first, as they might extend the api ones.
+    let routes = routes
+        .or(poke_http::api(
+            repository.clone(),
+            names.clone(),
+            dispatcher,
+            registry.clone(),
+        ))
+        .or(poke_http::cache(cache.clone(), warmup))
+        .or(poke_http::patches(patches, opts.admin.token.clone()))
+        .or(poke_http::admin(
+            repository,
+            names,
+            opts.admin.token.clone(),
+        ))
+        .or(poke_http::docs())
+        .or(poke_http::health::health(readiness))
+        .or(poke_http::metrics::metrics(registry.clone(), cache.clone()))
+        .recover(poke_http::problem::recover)
+        .with(logger)
+        .with(poke_http::metrics::track(registry));
+
+    let (_, server) =
+        warp::serve(routes).bind_with_graceful_shutdown(([0, 0, 0, 0], opts.port), shutdown());
+
+    server.await;
+
+    if let Some(ref path) = opts.cache.snapshot {
+        match cache.storage().save_snapshot(path).await {
+            Ok(count) => log::info!("Saved snapshot of {} Pokemons to {:?}", count, path),
+            Err(err) => log::error!("Failed to save snapshot to {:?}: {}", path, err),
+        }
+    }
+}
+
+/// Reloads the balance patches every time the process receives SIGHUP.
+async fn reload_on_hangup<R>(patches: PatchLayer<R>) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut hangup = signal(SignalKind::hangup()).expect("failed to listen for SIGHUP");
+
+        while hangup.recv().await.is_some() {
+            if let Err(err) = patches.reload().await {
+                log::error!("Failed to reload patches: {}", err);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    drop(patches);
+}
+
+/// Completes when the process is asked to terminate, either through
+/// Ctrl-C or, on Unix, through SIGTERM (e.g. `docker stop`).
+async fn shutdown() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => (),
+            _ = terminate.recv() => (),
+        }
+    }
 
-    let routes = poke_http::api(repository, dispatcher).with(logger);
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
 
-    warp::serve(routes).run(([0, 0, 0, 0], port)).await;
+    log::info!("Shutting down");
 }